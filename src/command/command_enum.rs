@@ -1,11 +1,11 @@
-use std::sync::Arc;
+use std::{ops::Bound, sync::Arc};
 
-use axum::Json;
 use serde::{Deserialize, Serialize};
 
-use crate::persists::KvStore;
+use crate::persists::{KvStore, SnapshotPages, TableError, WriteBatch, WriteOptions};
 
-const DEFAULT_MEM_SIZE: usize = 64 * 1024;
+/// Entries read per scan when listing the whole store.
+const GET_ALL_PAGE_SIZE: usize = 1000;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CommandInput {
@@ -15,27 +15,72 @@ pub enum CommandInput {
 }
 
 pub struct CommandExecutor {
-    store: Arc<KvStore<DEFAULT_MEM_SIZE>>,
+    store: Arc<KvStore>,
 }
 
 impl CommandExecutor {
-    pub fn new(store: Arc<KvStore<DEFAULT_MEM_SIZE>>) -> Self {
+    pub fn new(store: Arc<KvStore>) -> Self {
         Self { store }
     }
 
-    pub async fn execute_put(&self, key: &str, value: &str) {
-        self.store.put_value(key, value).await.unwrap();
+    /// The sequence number of the put.
+    pub async fn execute_put(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        options: WriteOptions,
+    ) -> std::io::Result<u64> {
+        self.store.put_bytes_with_options(key, value, options).await
     }
 
-    pub async fn execute_get(&self, key: &str) -> Option<String> {
-        self.store.get_value(key).await
+    pub async fn execute_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, TableError> {
+        self.store.get_bytes(key).await
     }
 
-    pub async fn execute_delete(&self, key: &str) -> Option<(String, String)> {
-        self.store.delete_value(key).await.0
+    /// The deleted value, if the key was in the active memtable.
+    pub async fn execute_delete(
+        &self,
+        key: &[u8],
+        options: WriteOptions,
+    ) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(self.store.delete_bytes_with_options(key, options).await?.0)
     }
 
-    pub async fn handle_get_all(&self) -> Json<Vec<(String, String)>> {
-        Json(self.store.get_all().await)
+    /// Applies all operations of `batch` atomically, see [`KvStore::write`].
+    pub async fn execute_batch(
+        &self,
+        batch: WriteBatch,
+        options: WriteOptions,
+    ) -> std::io::Result<u64> {
+        self.store.write(batch, options).await
+    }
+
+    /// Up to `limit` entries from `start` (inclusive) to `end` (exclusive) in key order.
+    pub async fn execute_scan(
+        &self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, TableError> {
+        let start = start.map_or(Bound::Unbounded, Bound::Included);
+        let end = end.map_or(Bound::Unbounded, Bound::Excluded);
+        self.store.scan_bytes((start, end), limit).await
+    }
+
+    /// Up to `limit` entries whose key starts with `prefix`, from `cursor` on if given.
+    pub async fn execute_scan_prefix(
+        &self,
+        prefix: &[u8],
+        cursor: Option<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, TableError> {
+        self.store
+            .scan_prefix_bytes_from(prefix, cursor.unwrap_or(prefix), limit)
+            .await
+    }
+
+    /// Every entry of the store at the time of the call, see [`KvStore::get_all`].
+    pub fn execute_get_all(&self) -> SnapshotPages {
+        self.store.get_all(GET_ALL_PAGE_SIZE)
     }
 }
@@ -0,0 +1,37 @@
+use axum::http::StatusCode;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::Deserialize;
+
+/// How keys and values are represented as strings in requests and responses.
+///
+/// Text is the default. Binary data has to use base64 with the standard alphabet, in paths and
+/// query strings its `+` and `/` need to be percent-encoded.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    /// UTF-8 text, stored data that is not valid UTF-8 is decoded lossily.
+    #[default]
+    Utf8,
+    Base64,
+}
+
+impl Encoding {
+    /// Bytes represented by `text`, `400 Bad Request` for invalid base64.
+    pub fn decode(self, text: &str) -> Result<Vec<u8>, StatusCode> {
+        match self {
+            Encoding::Utf8 => Ok(text.as_bytes().to_vec()),
+            Encoding::Base64 => STANDARD.decode(text).map_err(|_| StatusCode::BAD_REQUEST),
+        }
+    }
+
+    pub fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Encoding::Base64 => STANDARD.encode(bytes),
+        }
+    }
+
+    pub fn encode_entry(self, (key, value): &(Vec<u8>, Vec<u8>)) -> (String, String) {
+        (self.encode(key), self.encode(value))
+    }
+}
@@ -0,0 +1,27 @@
+use axum::http::StatusCode;
+
+use crate::input::encoding::Encoding;
+
+#[test]
+fn base64_round_trips_binary_data() {
+    let bytes = [0x00, 0xff, 0x89, b'P', b'N', b'G'];
+    let encoded = Encoding::Base64.encode(&bytes);
+    assert_eq!(encoded, "AP+JUE5H");
+    assert_eq!(Encoding::Base64.decode(&encoded), Ok(bytes.to_vec()));
+}
+
+#[test]
+fn invalid_base64_is_a_bad_request() {
+    assert_eq!(
+        Encoding::Base64.decode("not base64!"),
+        Err(StatusCode::BAD_REQUEST)
+    );
+}
+
+#[test]
+fn utf8_is_the_default_and_never_fails() {
+    let encoding: Encoding = serde_json::from_str(r#""utf8""#).unwrap();
+    assert_eq!(encoding, Encoding::default());
+    assert_eq!(Encoding::Utf8.decode("key"), Ok(b"key".to_vec()));
+    assert_eq!(Encoding::Utf8.encode(&[b'a', 0xff]), "a\u{fffd}");
+}
@@ -1,23 +1,124 @@
 use crate::command::command_enum::CommandExecutor;
+use crate::input::encoding::Encoding;
+use crate::persists::{TableError, WriteBatch, WriteOptions};
 use axum::debug_handler;
 use axum::{
     Json,
-    extract::{Path, State},
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    response::IntoResponse,
 };
-use serde::Deserialize;
+use futures_util::{TryStreamExt, stream};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use axum::http::StatusCode;
+use axum::http::{StatusCode, header};
 
+/// Body of `PUT /`, key and value are base64 with `"encoding": "base64"`.
 #[derive(Deserialize)]
 pub struct PutRequest {
     pub key: String,
     pub value: String,
+    #[serde(default)]
+    pub encoding: Encoding,
 }
 
 #[derive(Deserialize)]
 pub struct DeleteRequest {
     pub key: String,
+    #[serde(default)]
+    pub encoding: Encoding,
+}
+
+/// Body of `POST /batch`, e.g.
+/// `{"ops": [{"op": "put", "key": "a", "value": "1"}, {"op": "delete", "key": "b"}]}`.
+/// `"encoding"` applies to every operation.
+#[derive(Deserialize)]
+pub struct BatchRequest {
+    pub ops: Vec<BatchRequestOp>,
+    #[serde(default)]
+    pub encoding: Encoding,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BatchRequestOp {
+    Put { key: String, value: String },
+    Delete { key: String },
+}
+
+/// `?encoding=base64` for keys in the path and keys and values in the response.
+#[derive(Deserialize, Default)]
+pub struct EncodingParams {
+    #[serde(default)]
+    pub encoding: Encoding,
+}
+
+const DEFAULT_SCAN_LIMIT: usize = 100;
+const MAX_SCAN_LIMIT: usize = 1000;
+
+/// Query parameters of `GET /scan`. `start` is inclusive and `end` exclusive, both optional.
+/// To continue a scan, pass the `next_cursor` of the previous page as `cursor`.
+/// `encoding` applies to the bounds, the cursor and the response.
+#[derive(Deserialize, Default)]
+pub struct ScanParams {
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub encoding: Encoding,
+}
+
+/// Query parameters of `GET /prefix/{prefix}`, paginated like `GET /scan`.
+#[derive(Deserialize, Default)]
+pub struct PrefixParams {
+    pub limit: Option<usize>,
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub encoding: Encoding,
+}
+
+#[derive(Serialize)]
+pub struct ScanResponse {
+    pub items: Vec<(String, String)>,
+    /// Key to continue from, `None` once the range is exhausted.
+    pub next_cursor: Option<String>,
+}
+
+impl ScanResponse {
+    /// Builds a page from up to `limit + 1` entries, the extra one becomes the cursor.
+    fn page(mut items: Vec<(String, String)>, limit: usize) -> Self {
+        let next_cursor = (items.len() > limit).then(|| items.remove(limit).0);
+        Self { items, next_cursor }
+    }
+}
+
+fn ndjson_lines(entries: &[(Vec<u8>, Vec<u8>)], encoding: Encoding) -> Bytes {
+    let mut out = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut out, &encoding.encode_entry(entry))
+            .expect("strings always serialize");
+        out.push(b'\n');
+    }
+    Bytes::from(out)
+}
+
+fn scan_limit(limit: Option<usize>) -> usize {
+    limit.unwrap_or(DEFAULT_SCAN_LIMIT).clamp(1, MAX_SCAN_LIMIT)
+}
+
+/// Query parameters of write requests, `?sync=true` acknowledges only after the WAL is synced.
+#[derive(Deserialize, Default)]
+pub struct WriteParams {
+    #[serde(default)]
+    pub sync: bool,
+}
+
+impl From<WriteParams> for WriteOptions {
+    fn from(params: WriteParams) -> Self {
+        WriteOptions { sync: params.sync }
+    }
 }
 
 #[derive(Clone)]
@@ -32,28 +133,164 @@ impl Handler {
         }
     }
 
-    pub async fn handle_put(&self, payload: PutRequest) {
+    pub async fn handle_put(
+        &self,
+        payload: PutRequest,
+        options: WriteOptions,
+    ) -> Result<(), StatusCode> {
+        let key = payload.encoding.decode(&payload.key)?;
+        let value = payload.encoding.decode(&payload.value)?;
+        self.handle_put_raw(&key, &value, options).await
+    }
+
+    pub async fn handle_put_raw(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        options: WriteOptions,
+    ) -> Result<(), StatusCode> {
         self.executor
-            .execute_put(&payload.key, &payload.value)
-            .await;
+            .execute_put(key, value, options)
+            .await
+            .map(|_| ())
+            .map_err(|e| {
+                eprintln!("put of key {} failed: {e}", String::from_utf8_lossy(key));
+                StatusCode::INTERNAL_SERVER_ERROR
+            })
     }
 
-    pub async fn handle_get(&self, key: &str) -> Option<String> {
-        self.executor.execute_get(key).await
+    pub async fn handle_batch(
+        &self,
+        payload: BatchRequest,
+        options: WriteOptions,
+    ) -> Result<(), StatusCode> {
+        if payload.ops.is_empty() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        let encoding = payload.encoding;
+        let mut batch = WriteBatch::new();
+        for op in payload.ops {
+            match op {
+                BatchRequestOp::Put { key, value } => {
+                    batch.put(encoding.decode(&key)?, encoding.decode(&value)?)
+                }
+                BatchRequestOp::Delete { key } => batch.delete(encoding.decode(&key)?),
+            };
+        }
+        self.executor
+            .execute_batch(batch, options)
+            .await
+            .map(|_| ())
+            .map_err(|e| {
+                eprintln!("write batch failed: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })
     }
 
-    pub async fn handle_get_all(&self) -> Json<Vec<(String, String)>> {
-        self.executor.handle_get_all().await
+    pub async fn handle_get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StatusCode> {
+        self.executor.execute_get(key).await.map_err(|e| {
+            eprintln!("get for key {} failed: {e}", String::from_utf8_lossy(key));
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
     }
 
-    pub async fn handle_delete(&self, key: &str) -> Option<(String, String)> {
-        self.executor.execute_delete(key).await
+    pub async fn handle_scan(&self, params: ScanParams) -> Result<ScanResponse, StatusCode> {
+        let limit = scan_limit(params.limit);
+        let encoding = params.encoding;
+        // the cursor is the first key of the next page
+        let start = params
+            .cursor
+            .or(params.start)
+            .map(|start| encoding.decode(&start))
+            .transpose()?;
+        let end = params.end.map(|end| encoding.decode(&end)).transpose()?;
+
+        // one extra entry tells whether there is another page
+        let items = self
+            .executor
+            .execute_scan(start.as_deref(), end.as_deref(), limit + 1)
+            .await
+            .map_err(|e| {
+                eprintln!("scan failed: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        Ok(ScanResponse::page(
+            items
+                .iter()
+                .map(|entry| encoding.encode_entry(entry))
+                .collect(),
+            limit,
+        ))
+    }
+
+    pub async fn handle_scan_prefix(
+        &self,
+        prefix: &str,
+        params: PrefixParams,
+    ) -> Result<ScanResponse, StatusCode> {
+        let limit = scan_limit(params.limit);
+        let encoding = params.encoding;
+        let cursor = params
+            .cursor
+            .map(|cursor| encoding.decode(&cursor))
+            .transpose()?;
+        let items = self
+            .executor
+            .execute_scan_prefix(&encoding.decode(prefix)?, cursor.as_deref(), limit + 1)
+            .await
+            .map_err(|e| {
+                eprintln!("prefix scan for {prefix} failed: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        Ok(ScanResponse::page(
+            items
+                .iter()
+                .map(|entry| encoding.encode_entry(entry))
+                .collect(),
+            limit,
+        ))
+    }
+
+    /// Streams every entry as one `["key","value"]` JSON line, a page of entries at a time.
+    ///
+    /// A read error after the first page can no longer change the status, the response is
+    /// cut short instead.
+    pub fn handle_get_all(&self, encoding: Encoding) -> Body {
+        let pages = self.executor.execute_get_all();
+        let lines = stream::try_unfold(pages, move |mut pages| async move {
+            let Some(page) = pages.next_page().await? else {
+                return Ok::<_, TableError>(None);
+            };
+            Ok(Some((ndjson_lines(&page, encoding), pages)))
+        })
+        .inspect_err(|e| eprintln!("get all failed: {e}"));
+        Body::from_stream(lines)
+    }
+
+    /// The deleted entry, if the key was in the active memtable.
+    pub async fn handle_delete(
+        &self,
+        payload: DeleteRequest,
+        options: WriteOptions,
+    ) -> Result<Option<(String, String)>, StatusCode> {
+        let encoding = payload.encoding;
+        let key = encoding.decode(&payload.key)?;
+        let value = self
+            .executor
+            .execute_delete(&key, options)
+            .await
+            .map_err(|e| {
+                eprintln!("delete of key {} failed: {e}", payload.key);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        Ok(value.map(|value| (payload.key, encoding.encode(&value))))
     }
 }
 
 #[debug_handler]
 pub async fn put_handler(
     State(handler): State<Arc<Handler>>,
+    Query(params): Query<WriteParams>,
     Json(payload): Json<PutRequest>,
 ) -> Result<Json<&'static str>, StatusCode> {
     //TODO revert
@@ -63,7 +300,32 @@ pub async fn put_handler(
     // }
     //TODO add validPayloadStruct later
 
-    handler.handle_put(payload).await;
+    handler.handle_put(payload, params.into()).await?;
+    Ok(Json("OK"))
+}
+
+/// Stores the request body as it is, e.g. `application/octet-stream`.
+#[debug_handler]
+pub async fn put_raw_handler(
+    State(handler): State<Arc<Handler>>,
+    Path(key): Path<String>,
+    Query(params): Query<WriteParams>,
+    Query(key_params): Query<EncodingParams>,
+    body: Bytes,
+) -> Result<Json<&'static str>, StatusCode> {
+    let key = key_params.encoding.decode(&key)?;
+    handler.handle_put_raw(&key, &body, params.into()).await?;
+    Ok(Json("OK"))
+}
+
+/// Applies all operations of the request or none of them.
+#[debug_handler]
+pub async fn batch_handler(
+    State(handler): State<Arc<Handler>>,
+    Query(params): Query<WriteParams>,
+    Json(payload): Json<BatchRequest>,
+) -> Result<Json<&'static str>, StatusCode> {
+    handler.handle_batch(payload, params.into()).await?;
     Ok(Json("OK"))
 }
 
@@ -71,19 +333,64 @@ pub async fn put_handler(
 pub async fn get_handler(
     State(handler): State<Arc<Handler>>,
     Path(key): Path<String>,
-) -> Json<Option<String>> {
-    Json(handler.handle_get(&key).await)
+    Query(params): Query<EncodingParams>,
+) -> Result<Json<Option<String>>, StatusCode> {
+    let encoding = params.encoding;
+    let value = handler.handle_get(&encoding.decode(&key)?).await?;
+    Ok(Json(value.map(|value| encoding.encode(&value))))
+}
+
+/// The value as `application/octet-stream`, `404 Not Found` for a missing key.
+#[debug_handler]
+pub async fn get_raw_handler(
+    State(handler): State<Arc<Handler>>,
+    Path(key): Path<String>,
+    Query(params): Query<EncodingParams>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let value = handler
+        .handle_get(&params.encoding.decode(&key)?)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(([(header::CONTENT_TYPE, "application/octet-stream")], value))
 }
 
 #[debug_handler]
 pub async fn delete_handler(
     State(handler): State<Arc<Handler>>,
+    Query(params): Query<WriteParams>,
     Json(payload): Json<DeleteRequest>,
-) -> Json<Option<(String, String)>> {
-    Json(handler.handle_delete(&payload.key).await)
+) -> Result<Json<Option<(String, String)>>, StatusCode> {
+    handler
+        .handle_delete(payload, params.into())
+        .await
+        .map(Json)
+}
+
+#[debug_handler]
+pub async fn scan_handler(
+    State(handler): State<Arc<Handler>>,
+    Query(params): Query<ScanParams>,
+) -> Result<Json<ScanResponse>, StatusCode> {
+    handler.handle_scan(params).await.map(Json)
+}
+
+#[debug_handler]
+pub async fn prefix_handler(
+    State(handler): State<Arc<Handler>>,
+    Path(prefix): Path<String>,
+    Query(params): Query<PrefixParams>,
+) -> Result<Json<ScanResponse>, StatusCode> {
+    handler.handle_scan_prefix(&prefix, params).await.map(Json)
 }
 
+/// Lists the whole store as newline-delimited JSON, consistent as of the start of the request.
 #[debug_handler]
-pub async fn get_all_handler(State(handler): State<Arc<Handler>>) -> Json<Vec<(String, String)>> {
-    handler.handle_get_all().await
+pub async fn get_all_handler(
+    State(handler): State<Arc<Handler>>,
+    Query(params): Query<EncodingParams>,
+) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        handler.handle_get_all(params.encoding),
+    )
 }
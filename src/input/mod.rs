@@ -1 +1,5 @@
+pub mod encoding;
 pub mod handlers;
+
+#[cfg(test)]
+mod encoding_test;
@@ -6,15 +6,36 @@ use std::sync::Arc;
 
 use axum::{
     Router,
-    routing::{delete, get, put},
+    routing::{delete, get, post, put},
 };
 
 use command::command_enum::CommandExecutor;
-use input::handlers::{Handler, delete_handler, get_all_handler, get_handler, put_handler};
-use persists::KvStore;
+use input::handlers::{
+    Handler, batch_handler, delete_handler, get_all_handler, get_handler, get_raw_handler,
+    prefix_handler, put_handler, put_raw_handler, scan_handler,
+};
+use persists::{KvStore, StoreOptions};
+
+/// [`StoreOptions::default`] with the memtable size and the memory budget, in bytes, taken
+/// from `KV_MEMTABLE_SIZE` and `KV_MEMORY_BUDGET` if they are set.
+fn store_options() -> StoreOptions {
+    let bytes = |name: &str| {
+        std::env::var(name).ok().map(|value| {
+            value
+                .parse::<usize>()
+                .unwrap_or_else(|e| panic!("invalid {name} {value:?}: {e}"))
+        })
+    };
+    let defaults = StoreOptions::default();
+    StoreOptions {
+        memtable_size: bytes("KV_MEMTABLE_SIZE").unwrap_or(defaults.memtable_size),
+        memory_budget: bytes("KV_MEMORY_BUDGET").or(defaults.memory_budget),
+        ..defaults
+    }
+}
 
 pub async fn run() {
-    let store = KvStore::new().await;
+    let store = KvStore::new(store_options()).await;
     let executor = CommandExecutor::new(store.clone());
     let handler = Arc::new(Handler::new(executor));
 
@@ -23,6 +44,10 @@ pub async fn run() {
         .route("/", delete(delete_handler))
         .route("/", get(get_all_handler))
         .route("/get/{key}", get(get_handler))
+        .route("/raw/{key}", put(put_raw_handler).get(get_raw_handler))
+        .route("/batch", post(batch_handler))
+        .route("/scan", get(scan_handler))
+        .route("/prefix/{prefix}", get(prefix_handler))
         .with_state(handler.clone());
 
     // run our app with hyper, listening globally on port 3000
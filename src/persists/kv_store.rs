@@ -1,83 +1,170 @@
 use std::{
-    collections::HashMap,
-    sync::{Arc, atomic::AtomicU64},
+    collections::{BTreeMap, HashMap},
+    ops::{Bound, RangeBounds},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64},
+    },
+    time::Duration,
 };
 
-use tokio::sync::{Mutex, RwLock, mpsc};
+use tokio::sync::{Mutex, MutexGuard, Notify, RwLock, RwLockReadGuard, RwLockWriteGuard, mpsc};
 
 use crate::persists::{
     lsm_tree::{
+        compaction::{
+            compaction_strategy::CompactionStrategy,
+            compaction_worker::{CompactionCommand, CompactionWorker},
+            leveled::LeveledCompaction,
+        },
         lsm_manager::LsmManager,
-        sorted_string_table::flush_worker::{FlushCommand, FlushResult, FlushWorker},
+        merging_iterator::{MergingIterator, ScanSource},
+        snapshot_list::SnapshotList,
+        sorted_string_table::{
+            bloom_filter::BloomFilterStats,
+            flush_worker::{FlushCommand, FlushResult, FlushWorker},
+            table_error::TableError,
+        },
     },
     memtable::{
         btree_map::BTreeMemTable,
-        memtable_trait::{LookupResult, MemTable},
+        memtable_trait::{LookupResult, MemTable, MemTableAccounting, MemTableValue},
     },
+    snapshot::{Snapshot, SnapshotPages},
+    store_options::StoreOptions,
+    write_batch::{BatchOp, WriteBatch},
 };
 
-use super::wal::{LogCommand, Wal};
+use super::wal::{
+    wal::{LogCommand, Wal},
+    wal_sync::{Durability, WalSyncer, WriteOptions},
+};
 
-pub struct KvStore<const MAX_SIZE: usize> {
-    pub(crate) store: Arc<RwLock<BTreeMemTable<{ MAX_SIZE }>>>,
-    pub(crate) flushable_tables: Arc<RwLock<HashMap<u64, Arc<BTreeMemTable<{ MAX_SIZE }>>>>>,
-    read_from_wal: bool,
+/// Wait before a failed flush is retried, doubled for every further failure in a row.
+const FLUSH_RETRY_DELAY: Duration = Duration::from_millis(100);
+/// Upper bound of the doubled [`FLUSH_RETRY_DELAY`].
+const MAX_FLUSH_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Key-value store on an LSM tree with memtables of type `M`, sized by
+/// [`StoreOptions::memtable_size`] and [`StoreOptions::memory_budget`].
+///
+/// `M` defaults to [`BTreeMemTable`]. With a memtable that takes concurrent writers, such as
+/// [`SkipListMemTable`](crate::persists::memtable::skiplist::SkipListMemTable), puts and
+/// deletes no longer block readers of the active memtable.
+pub struct KvStore<M: MemTable = BTreeMemTable> {
+    pub(crate) store: Arc<RwLock<M>>,
+    pub(crate) flushable_tables: Arc<RwLock<HashMap<u64, Arc<M>>>>,
     wal: Arc<Mutex<Wal>>,
+    durability: Durability,
+    memtable_size: usize,
+    memory_budget: Option<usize>,
+    memtable_accounting: MemTableAccounting,
+    /// Notified whenever a frozen memtable was flushed and dropped.
+    memtable_flushed: Notify,
+    wal_syncer: Arc<WalSyncer>,
     sequence_number_counter: AtomicU64,
-    flush_worker: Arc<FlushWorker<{ MAX_SIZE }>>,
+    /// Highest sequence number whose write is in a memtable, new snapshots are pinned here.
+    visible_sequence_number: AtomicU64,
+    snapshots: Arc<SnapshotList>,
+    flush_worker: Arc<FlushWorker<M>>,
     sender: mpsc::Sender<FlushCommand>,
-    lsm_manager: RwLock<LsmManager>,
+    pub(crate) lsm_manager: Arc<RwLock<LsmManager>>,
+    compaction_sender: mpsc::Sender<CompactionCommand>,
 }
 
-impl<const MAX_SIZE: usize> KvStore<MAX_SIZE> {
-    pub async fn new() -> Arc<Self> {
+impl<M: MemTable> KvStore<M> {
+    /// Opens the store with all of its files below `options.root_dir`.
+    pub async fn new(options: StoreOptions) -> Arc<Self> {
+        Self::with_compaction_strategy(options, Box::new(LeveledCompaction::default())).await
+    }
+
+    /// Opens the store with the given compaction strategy, e.g.
+    /// [`SizeTieredCompaction`](crate::persists::SizeTieredCompaction) for write-heavy workloads.
+    pub async fn with_compaction_strategy(
+        options: StoreOptions,
+        compaction_strategy: Box<dyn CompactionStrategy>,
+    ) -> Arc<Self> {
         let (flush_result_tx, flush_result_rx) = tokio::sync::mpsc::channel(16);
-        Self::new_with_channels(flush_result_tx, flush_result_rx).await
+        Self::open(
+            options,
+            flush_result_tx,
+            flush_result_rx,
+            compaction_strategy,
+        )
+        .await
     }
 
     pub async fn new_with_channels(
+        options: StoreOptions,
+        flush_result_tx: tokio::sync::mpsc::Sender<FlushResult>,
+        flush_result_rx: tokio::sync::mpsc::Receiver<FlushResult>,
+    ) -> Arc<Self> {
+        Self::open(
+            options,
+            flush_result_tx,
+            flush_result_rx,
+            Box::new(LeveledCompaction::default()),
+        )
+        .await
+    }
+
+    async fn open(
+        options: StoreOptions,
         flush_result_tx: tokio::sync::mpsc::Sender<FlushResult>,
         flush_result_rx: tokio::sync::mpsc::Receiver<FlushResult>,
+        compaction_strategy: Box<dyn CompactionStrategy>,
     ) -> Arc<Self> {
         let flushable_tables = Arc::new(RwLock::new(HashMap::new()));
         let (flush_tx, flush_rx) = tokio::sync::mpsc::channel(16);
-
-        let lsm_manager = RwLock::new(LsmManager::new());
-
-        lsm_manager
-            .write()
+        let (compaction_tx, compaction_rx) = tokio::sync::mpsc::channel(16);
+
+        let lsm_manager =
+            LsmManager::open(&options, compaction_strategy).expect("failed to open the lsm tree");
+        let flush_worker = FlushWorker::new(
+            flushable_tables.clone(),
+            lsm_manager.dir().to_path_buf(),
+            lsm_manager.file_numbers(),
+            lsm_manager.snapshots(),
+            lsm_manager.table_options(),
+        );
+        let snapshots = lsm_manager.snapshots();
+        let lsm_manager = Arc::new(RwLock::new(lsm_manager));
+
+        // 0 is never assigned, a snapshot of an empty store is pinned there
+        let next_sequence_number = lsm_manager
+            .read()
             .await
-            .initialize()
+            .max_sequence_number()
+            .map_or(1, |seq| seq + 1);
+
+        if let Some(legacy_path) = &options.legacy_wal_path {
+            Wal::migrate_legacy_wal(&options.wal_dir(), legacy_path)
+                .await
+                .expect("failed to migrate the legacy wal");
+        }
+        let (wal, wal_entries) = Wal::open(&options.wal_dir(), options.wal_recovery_mode)
             .await
-            .expect("failed to initialize lsm tree");
+            .expect("failed to recover the wal");
 
         let store = Arc::new(KvStore {
-            store: Arc::new(RwLock::new(BTreeMemTable::new())),
+            store: Arc::new(RwLock::new(M::default())),
             flushable_tables: flushable_tables.clone(),
-            read_from_wal: false,
-            wal: Arc::new(Mutex::new(
-                Wal::new().await.expect("failed to open the wal file"),
-            )),
-            sequence_number_counter: AtomicU64::new(0),
-            flush_worker: Arc::new(FlushWorker::new(flushable_tables)),
+            wal: Arc::new(Mutex::new(wal)),
+            durability: options.durability,
+            memtable_size: options.memtable_size,
+            memory_budget: options.memory_budget,
+            memtable_accounting: options.memtable_accounting,
+            memtable_flushed: Notify::new(),
+            wal_syncer: Arc::new(WalSyncer::default()),
+            sequence_number_counter: AtomicU64::new(next_sequence_number),
+            visible_sequence_number: AtomicU64::new(next_sequence_number - 1),
+            snapshots,
+            flush_worker: Arc::new(flush_worker),
             sender: flush_tx,
             lsm_manager,
+            compaction_sender: compaction_tx,
         });
 
-        if store.read_from_wal {
-            let wal_entries = Wal::read_wal().await;
-            for entry in wal_entries.expect("Wal read failed") {
-                match entry {
-                    LogCommand::Put { key, value, .. } => {
-                        store.put_value(&key, &value).await.expect("put failed");
-                    }
-                    LogCommand::Delete { key, .. } => {
-                        store.delete_value(&key).await.0.expect("delete failed");
-                    }
-                }
-            }
-        }
-
         tokio::spawn({
             let flush_worker = store.flush_worker.clone();
             async move {
@@ -90,60 +177,376 @@ impl<const MAX_SIZE: usize> KvStore<MAX_SIZE> {
             store_clone.event_loop(flush_result_rx).await;
         });
 
+        let compaction_worker = CompactionWorker::new(Arc::clone(&store.lsm_manager));
+        tokio::spawn(async move {
+            compaction_worker.run(compaction_rx).await;
+        });
+
+        if let Durability::Interval(interval) = store.durability {
+            let wal = Arc::clone(&store.wal);
+            let wal_syncer = Arc::clone(&store.wal_syncer);
+            tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    if let Err(e) = wal_syncer.sync(&wal).await {
+                        eprintln!("periodic wal sync failed: {e}");
+                    }
+                }
+            });
+        }
+
+        // runs after the workers are up, a long log may fill more than one memtable
+        store.replay_wal(wal_entries).await;
+        // tables loaded on startup may already exceed the level limits
+        store.request_compaction();
+
         store
     }
 
+    /// Replays the WAL into the memtables with the original sequence numbers.
+    /// Entries at or below the highest sequence number persisted in an SSTable are already
+    /// durable and skipped, the counter continues after the highest number seen anywhere.
+    async fn replay_wal(&self, entries: Vec<LogCommand>) {
+        let persisted = self.lsm_manager.read().await.max_sequence_number();
+        let mut wal = self.wal.lock().await;
+
+        for entry in entries {
+            let seq_number = entry.seq_number();
+            self.sequence_number_counter.fetch_max(
+                entry.last_seq_number() + 1,
+                std::sync::atomic::Ordering::Relaxed,
+            );
+            // a batch is always frozen and flushed as a whole, it is persisted entirely or not at all
+            if persisted.is_some_and(|persisted| entry.last_seq_number() <= persisted) {
+                continue;
+            }
+
+            match entry {
+                LogCommand::Put { key, value, .. } => {
+                    if !self.has_capacity(M::encoded_len(&key, &value)).await {
+                        // the entries are already logged, the frozen memtable keeps its segments
+                        wal.freeze(seq_number);
+                        self.freeze_memtable(seq_number).await;
+                    }
+                    self.active_memtable()
+                        .await
+                        .insert(&key, &value, seq_number);
+                }
+                LogCommand::Delete { key, .. } => {
+                    if !self.has_capacity(M::encoded_len(&key, &[])).await {
+                        wal.freeze(seq_number);
+                        self.freeze_memtable(seq_number).await;
+                    }
+                    self.active_memtable().await.delete(&key, seq_number);
+                }
+                LogCommand::Batch { ops, .. } => {
+                    if !self.has_capacity(Self::batch_len(&ops)).await {
+                        wal.freeze(seq_number);
+                        self.freeze_memtable(seq_number).await;
+                    }
+                    self.apply_batch(&ops, seq_number).await;
+                }
+            }
+        }
+
+        self.publish(
+            self.sequence_number_counter
+                .load(std::sync::atomic::Ordering::Relaxed)
+                - 1,
+        );
+    }
+
+    /// Registers flushed tables in memtable order.
+    ///
+    /// The WAL replay skips everything up to the highest sequence number in an SSTable, so a
+    /// table is held back while an older memtable is still unflushed. A failed memtable stays
+    /// in `flushable_tables` and is written again by a retry with exponential backoff.
     async fn event_loop(&self, mut receiver: mpsc::Receiver<FlushResult>) {
+        let mut pending = BTreeMap::<u64, std::path::PathBuf>::new();
+        let mut failures = 0;
+        // one retry at a time, it flushes every memtable that is still unflushed
+        let retry_scheduled = Arc::new(AtomicBool::new(false));
         while let Some(res) = receiver.recv().await {
             match res {
+                // a flush that started before the memtable was registered wrote it once more
+                Ok((id, path)) if !self.flushable_tables.read().await.contains_key(&id) => {
+                    let _ = std::fs::remove_file(path);
+                    continue;
+                }
                 Ok((id, path)) => {
-                    let mut guard = self.flushable_tables.write().await;
-                    guard.remove(&id);
-                    let mut lsm_manager = self.lsm_manager.write().await;
-                    lsm_manager.add_table(&path);
+                    failures = 0;
+                    // the memtable was written again since, the older table is never registered
+                    if let Some(replaced) = pending.insert(id, path) {
+                        let _ = std::fs::remove_file(replaced);
+                    }
                 }
-                Err(_) => {
-                    //check if error can be handled
+                Err(e) => {
+                    eprintln!("memtable flush failed: {e}");
+                    if !retry_scheduled.swap(true, std::sync::atomic::Ordering::AcqRel) {
+                        let delay = FLUSH_RETRY_DELAY
+                            .saturating_mul(1 << failures.min(16))
+                            .min(MAX_FLUSH_RETRY_DELAY);
+                        failures += 1;
+                        let sender = self.sender.clone();
+                        let retry_scheduled = Arc::clone(&retry_scheduled);
+                        tokio::spawn(async move {
+                            tokio::time::sleep(delay).await;
+                            retry_scheduled.store(false, std::sync::atomic::Ordering::Release);
+                            let _ = sender.send(FlushCommand::FlushAll).await;
+                        });
+                    }
                     continue;
                 }
             }
+
+            while let Some((&id, path)) = pending.first_key_value() {
+                let older_unflushed = self
+                    .flushable_tables
+                    .read()
+                    .await
+                    .keys()
+                    .any(|&flushable| flushable < id);
+                if older_unflushed {
+                    break;
+                }
+
+                // register the table before dropping the memtable so readers always find the data
+                let mut lsm_manager = self.lsm_manager.write().await;
+                if let Err(e) = lsm_manager.add_table(path) {
+                    eprintln!("failed to load flushed table {}: {e}", path.display());
+                    break;
+                }
+                drop(lsm_manager);
+                pending.remove(&id);
+
+                let mut guard = self.flushable_tables.write().await;
+                guard.remove(&id);
+                drop(guard);
+                self.memtable_flushed.notify_waiters();
+
+                if let Err(e) = self.wal.lock().await.memtable_flushed(id).await {
+                    eprintln!("failed to remove obsolete wal segments: {e}");
+                }
+
+                self.request_compaction();
+            }
         }
     }
 
-    pub async fn get_value(&self, key: &str) -> Option<String> {
-        let key_bytes = key.as_bytes();
+    /// A full channel means a compaction check is already pending.
+    fn request_compaction(&self) {
+        let _ = self
+            .compaction_sender
+            .try_send(CompactionCommand::MaybeCompact);
+    }
+
+    /// [`KvStore::get_bytes`] for text, a value that is not valid UTF-8 is decoded lossily.
+    pub async fn get_value(&self, key: &str) -> Result<Option<String>, TableError> {
+        Ok(self
+            .get_bytes(key.as_bytes())
+            .await?
+            .map(|value| self.decode_utf8(&value)))
+    }
+
+    pub async fn get_bytes(&self, key: &[u8]) -> Result<Option<Vec<u8>>, TableError> {
+        self.lookup_at(key, u64::MAX).await
+    }
+
+    /// Checks the active memtable, the immutable memtables and the sstables in that order.
+    /// The first tier holding a version up to `snapshot` decides, within a tier the highest
+    /// sequence number wins. A tombstone ends the lookup and is reported as `None`.
+    pub(crate) async fn lookup_at(
+        &self,
+        key: &[u8],
+        snapshot: u64,
+    ) -> Result<Option<Vec<u8>>, TableError> {
         {
             let store = self.store.read().await;
-            if let LookupResult::Found((bytes, _)) = store.get(key_bytes) {
-                return Some(self.decode_utf8(bytes));
+            match store.get_at(key, snapshot) {
+                LookupResult::Found((value, _)) => return Ok(Some(value.to_vec())),
+                LookupResult::Deleted(_) => return Ok(None),
+                LookupResult::NotFound => {}
             }
         }
 
         {
             let flushable_tables = self.flushable_tables.read().await;
 
-            let highest = flushable_tables
+            let newest = flushable_tables
                 .values()
-                .map(|table| table.get(key_bytes))
-                .filter(|res| !matches!(res, LookupResult::NotFound))
-                .max_by_key(|res| match res {
-                    LookupResult::Deleted(seq) => *seq,
-                    LookupResult::Found((_, seq)) => *seq,
-                    LookupResult::NotFound => 0,
-                });
-
-            if let Some(LookupResult::Found((value_bytes, _))) = highest {
-                return Some(self.decode_utf8(value_bytes));
+                .map(|table| table.get_at(key, snapshot))
+                .max_by_key(|res| res.sequence_number());
+
+            match newest {
+                Some(LookupResult::Found((value, _))) => return Ok(Some(value.to_vec())),
+                Some(LookupResult::Deleted(_)) => return Ok(None),
+                Some(LookupResult::NotFound) | None => {}
             }
         }
 
-        {
-            let lsm_manager_lock = self.lsm_manager.read().await;
-            match lsm_manager_lock.get_value(key.as_bytes()) {
-                Some(table_result) => Some(self.decode_utf8(table_result.value)),
-                None => None,
-            }
+        let lsm_manager = self.lsm_manager.read().await;
+        Ok(lsm_manager
+            .get_value_at(key, snapshot)?
+            .and_then(|table_result| table_result.value().map(<[u8]>::to_vec)))
+    }
+
+    /// [`KvStore::scan_bytes`] for text keys, entries are decoded like in [`KvStore::get_value`].
+    pub async fn scan(
+        &self,
+        range: impl RangeBounds<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>, TableError> {
+        self.scan_at(range, limit, u64::MAX).await
+    }
+
+    /// Like [`KvStore::scan`], but ignores versions newer than `snapshot`.
+    pub(crate) async fn scan_at(
+        &self,
+        range: impl RangeBounds<&str>,
+        limit: usize,
+        snapshot: u64,
+    ) -> Result<Vec<(String, String)>, TableError> {
+        let range = (
+            range.start_bound().map(|key| key.as_bytes()),
+            range.end_bound().map(|key| key.as_bytes()),
+        );
+        Ok(self.decode_entries(self.scan_merged(range, None, limit, snapshot).await?))
+    }
+
+    /// Keys and values in `range` in key order, at most `limit` of them.
+    /// Reads the memtables and every table that overlaps the range, newer versions of a key
+    /// shadow older ones and deleted keys are left out.
+    pub async fn scan_bytes(
+        &self,
+        range: impl RangeBounds<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, TableError> {
+        self.scan_bytes_at(range, limit, u64::MAX).await
+    }
+
+    /// Like [`KvStore::scan_bytes`], but ignores versions newer than `snapshot`.
+    pub(crate) async fn scan_bytes_at(
+        &self,
+        range: impl RangeBounds<&[u8]>,
+        limit: usize,
+        snapshot: u64,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, TableError> {
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        self.scan_merged(range, None, limit, snapshot).await
+    }
+
+    /// [`KvStore::scan_prefix_bytes`] for text keys.
+    pub async fn scan_prefix(
+        &self,
+        prefix: &str,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>, TableError> {
+        self.scan_prefix_from(prefix, prefix, limit).await
+    }
+
+    /// Like [`KvStore::scan_prefix`], but starts at `start`, e.g. to continue a previous scan.
+    pub async fn scan_prefix_from(
+        &self,
+        prefix: &str,
+        start: &str,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>, TableError> {
+        Ok(self.decode_entries(
+            self.scan_prefix_bytes_from(prefix.as_bytes(), start.as_bytes(), limit)
+                .await?,
+        ))
+    }
+
+    /// Keys starting with `prefix` and their values in key order, at most `limit` of them.
+    /// Tables whose prefix bloom filter rejects `prefix` are not read, see
+    /// [`StoreOptions::prefix_bloom_len`].
+    pub async fn scan_prefix_bytes(
+        &self,
+        prefix: &[u8],
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, TableError> {
+        self.scan_prefix_bytes_from(prefix, prefix, limit).await
+    }
+
+    /// Like [`KvStore::scan_prefix_bytes`], but starts at `start`.
+    pub async fn scan_prefix_bytes_from(
+        &self,
+        prefix: &[u8],
+        start: &[u8],
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, TableError> {
+        let end = prefix_end(prefix);
+        let range = (
+            Bound::Included(prefix.max(start)),
+            end.as_deref().map_or(Bound::Unbounded, Bound::Excluded),
+        );
+        self.scan_merged(range, Some(prefix), limit, u64::MAX).await
+    }
+
+    async fn scan_merged(
+        &self,
+        range: (Bound<&[u8]>, Bound<&[u8]>),
+        prefix: Option<&[u8]>,
+        limit: usize,
+        snapshot: u64,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, TableError> {
+        if is_empty_range(range) {
+            return Ok(Vec::new());
         }
+
+        // same order as freezing a memtable takes its locks
+        let store = self.store.read().await;
+        let flushable_tables = self.flushable_tables.read().await;
+        let lsm_manager = self.lsm_manager.read().await;
+
+        let mut sources = vec![ScanSource::MemTable(store.range(range))];
+        sources.extend(
+            flushable_tables
+                .values()
+                .map(|table| ScanSource::MemTable(table.range(range))),
+        );
+        sources.extend(lsm_manager.scan_sources(range, prefix));
+
+        let mut merged = MergingIterator::new(sources, range, snapshot);
+        let entries = merged
+            .by_ref()
+            .take(limit)
+            .map(|(key, value)| (key.to_vec(), value.to_vec()))
+            .collect();
+        match merged.take_error() {
+            Some(e) => Err(e),
+            None => Ok(entries),
+        }
+    }
+
+    /// Pins a consistent view of the store at the latest visible write. Reads through the
+    /// snapshot ignore newer writes, flushes and compactions keep the versions it can see
+    /// until it is dropped.
+    pub fn snapshot(self: &Arc<Self>) -> Snapshot<M> {
+        let sequence_number = self.snapshots.acquire(|| {
+            self.visible_sequence_number
+                .load(std::sync::atomic::Ordering::Acquire)
+        });
+        Snapshot::new(Arc::clone(self), sequence_number)
+    }
+
+    pub(crate) fn release_snapshot(&self, sequence_number: u64) {
+        self.snapshots.release(sequence_number);
+    }
+
+    /// Number of WAL syncs shared by writers, see [`Durability::GroupCommit`].
+    pub fn wal_sync_count(&self) -> u64 {
+        self.wal_syncer.sync_count()
+    }
+
+    /// Bloom filter hit/miss counters of all sstables, used to check the false positive rate.
+    pub async fn filter_stats(&self) -> BloomFilterStats {
+        self.lsm_manager.read().await.filter_stats()
+    }
+
+    /// Number of sstables per level, starting with L0.
+    pub async fn level_table_counts(&self) -> Vec<usize> {
+        self.lsm_manager.read().await.level_table_counts()
     }
 
     // helper for consistent utf-8 decoding
@@ -151,10 +554,50 @@ impl<const MAX_SIZE: usize> KvStore<MAX_SIZE> {
         String::from_utf8_lossy(bytes).to_string()
     }
 
+    fn decode_entries(&self, entries: Vec<(Vec<u8>, Vec<u8>)>) -> Vec<(String, String)> {
+        entries
+            .into_iter()
+            .map(|(key, value)| (self.decode_utf8(&key), self.decode_utf8(&value)))
+            .collect()
+    }
+
     pub async fn put_value(&self, key: &str, value: &str) -> Result<u64, std::io::Error> {
+        self.put_value_with_options(key, value, WriteOptions::default())
+            .await
+    }
+
+    pub async fn put_value_with_options(
+        &self,
+        key: &str,
+        value: &str,
+        options: WriteOptions,
+    ) -> Result<u64, std::io::Error> {
+        self.put_bytes_with_options(key.as_bytes(), value.as_bytes(), options)
+            .await
+    }
+
+    pub async fn put_bytes(&self, key: &[u8], value: &[u8]) -> Result<u64, std::io::Error> {
+        self.put_bytes_with_options(key, value, WriteOptions::default())
+            .await
+    }
+
+    /// Returns once the write is as durable as the store's [`Durability`] and `options` demand.
+    pub async fn put_bytes_with_options(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        options: WriteOptions,
+    ) -> Result<u64, std::io::Error> {
+        let encoded_len = M::encoded_len(key, value);
+        let mut wal = self.lock_wal(encoded_len).await;
+        // numbered under the wal lock so the log is in sequence order, recovery relies on it
         let seq_number = self.get_next_sequence_number();
 
-        let mut wal = self.wal.lock().await;
+        if !self.has_capacity(encoded_len).await {
+            // rotate before logging, so the entry lands in the segment of the memtable holding it
+            wal.rotate(seq_number).await?;
+            self.freeze_memtable(seq_number).await;
+        }
 
         wal.append(&LogCommand::Put {
             key: key.into(),
@@ -162,70 +605,303 @@ impl<const MAX_SIZE: usize> KvStore<MAX_SIZE> {
             seq_number,
         })
         .await?;
+        if self.durability == Durability::Always {
+            wal.sync().await?;
+        }
 
-        let encoded_len = BTreeMemTable::<MAX_SIZE>::encoded_len(key.as_bytes(), value.as_bytes());
+        self.active_memtable().await.insert(key, value, seq_number);
+        self.publish(seq_number);
+        drop(wal);
 
-        {
-            let mut store_guard = self.store.write().await;
-
-            if !store_guard.has_capacity(encoded_len) {
-                // Take ownership of current table and replace with new
-                let old_table = std::mem::take(&mut *store_guard);
-                let mut flushables = self.flushable_tables.write().await;
-                flushables.insert(seq_number, Arc::new(old_table));
-                println!("new table was created");
-                //TODO handle result
-                let _ = self.sender.send(FlushCommand::FlushAll).await;
+        self.wait_durable(seq_number, options).await?;
+        Ok(seq_number)
+    }
+
+    /// Makes writes up to `seq_number` visible to new snapshots, called under the wal lock
+    /// once they are in the memtable.
+    fn publish(&self, seq_number: u64) {
+        self.visible_sequence_number
+            .store(seq_number, std::sync::atomic::Ordering::Release);
+    }
+
+    /// Waits for the sync of a record appended without one, other writers can append meanwhile.
+    async fn wait_durable(&self, seq_number: u64, options: WriteOptions) -> std::io::Result<()> {
+        match self.durability {
+            // synced while appending
+            Durability::Always => Ok(()),
+            Durability::Interval(_) if !options.sync => Ok(()),
+            Durability::GroupCommit | Durability::Interval(_) => {
+                self.wal_syncer.wait_durable(&self.wal, seq_number).await
             }
+        }
+    }
 
-            store_guard.insert(key.as_bytes(), value.as_bytes(), seq_number);
+    /// Locks the active memtable for a single write, a concurrent memtable only takes the
+    /// read lock. The caller holds the wal lock, so no rotation happens in between.
+    async fn active_memtable(&self) -> ActiveMemTable<'_, M> {
+        let store = self.store.read().await;
+        if store.concurrent().is_some() {
+            return ActiveMemTable::Shared(store);
         }
+        drop(store);
+        ActiveMemTable::Exclusive(self.store.write().await)
+    }
 
-        Ok(seq_number)
+    /// Moves the active memtable to `flushable_tables` as `id` and starts an empty one.
+    async fn freeze_memtable(&self, id: u64) {
+        let mut store_guard = self.store.write().await;
+        // Take ownership of current table and replace with new
+        let old_table = std::mem::take(&mut *store_guard);
+        let mut flushables = self.flushable_tables.write().await;
+        flushables.insert(id, Arc::new(old_table));
+        println!("new table was created");
+        //TODO handle result
+        let _ = self.sender.send(FlushCommand::FlushAll).await;
+    }
+
+    pub async fn delete_value(
+        &self,
+        key: &str,
+    ) -> std::io::Result<(Option<(String, String)>, u64)> {
+        self.delete_value_with_options(key, WriteOptions::default())
+            .await
+    }
+
+    pub async fn delete_value_with_options(
+        &self,
+        key: &str,
+        options: WriteOptions,
+    ) -> std::io::Result<(Option<(String, String)>, u64)> {
+        let (value, seq_number) = self
+            .delete_bytes_with_options(key.as_bytes(), options)
+            .await?;
+        Ok((
+            value.map(|value| (key.into(), self.decode_utf8(&value))),
+            seq_number,
+        ))
     }
 
-    pub async fn delete_value(&self, key: &str) -> (Option<(String, String)>, u64) {
+    pub async fn delete_bytes(&self, key: &[u8]) -> std::io::Result<(Option<Vec<u8>>, u64)> {
+        self.delete_bytes_with_options(key, WriteOptions::default())
+            .await
+    }
+
+    /// Returns the value the key had in the active memtable, if any, and the sequence number
+    /// of the tombstone.
+    ///
+    /// Like a put, the tombstone is only applied once it is in the WAL.
+    pub async fn delete_bytes_with_options(
+        &self,
+        key: &[u8],
+        options: WriteOptions,
+    ) -> std::io::Result<(Option<Vec<u8>>, u64)> {
+        let encoded_len = M::encoded_len(key, &[]);
+        let mut wal = self.lock_wal(encoded_len).await;
         let seq_number = self.get_next_sequence_number();
-        //TODO return result
+        if !self.has_capacity(encoded_len).await {
+            wal.rotate(seq_number).await?;
+            self.freeze_memtable(seq_number).await;
+        }
+        wal.append(&LogCommand::Delete {
+            key: key.into(),
+            seq_number,
+        })
+        .await?;
+        if self.durability == Durability::Always {
+            wal.sync().await?;
+        }
+        let val = self.active_memtable().await.delete(key, seq_number);
+        self.publish(seq_number);
+        drop(wal);
 
-        let mut wal = self.wal.lock().await;
-        let _result = wal
-            .append(&LogCommand::Delete {
-                key: key.into(),
-                seq_number,
-            })
-            .await;
-        let val = self.store.write().await.delete(key.as_bytes(), seq_number);
+        self.wait_durable(seq_number, options).await?;
 
         match val {
-            Some((Some(value), _)) => (
-                Some((
-                    key.into(),
-                    String::from_utf8(value.to_vec()).expect("Value is not a valid Utf8 String"),
-                )),
-                seq_number,
-            ),
-            _ => (None, seq_number),
+            Some((Some(value), _)) => Ok((Some(value), seq_number)),
+            _ => Ok((None, seq_number)),
         }
     }
 
-    pub async fn get_all(&self) -> Vec<(String, String)> {
-        self.store
+    /// Applies every operation of `batch` or, if the WAL append fails, none of them.
+    ///
+    /// The operations get consecutive sequence numbers in batch order and all land in the same
+    /// memtable, so a later operation on a key overrides an earlier one. Returns the sequence
+    /// number of the first operation.
+    pub async fn write(&self, batch: WriteBatch, options: WriteOptions) -> std::io::Result<u64> {
+        if batch.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "empty write batch",
+            ));
+        }
+
+        let batch_len = Self::batch_len(batch.ops());
+        let mut wal = self.lock_wal(batch_len).await;
+        let seq_number = self.reserve_sequence_numbers(batch.len() as u64);
+
+        if !self.has_capacity(batch_len).await {
+            wal.rotate(seq_number).await?;
+            self.freeze_memtable(seq_number).await;
+        }
+
+        let command = LogCommand::Batch {
+            ops: batch.ops().to_vec(),
+            seq_number,
+        };
+        wal.append(&command).await?;
+        if self.durability == Durability::Always {
+            wal.sync().await?;
+        }
+
+        self.apply_batch(batch.ops(), seq_number).await;
+        self.publish(command.last_seq_number());
+        drop(wal);
+
+        self.wait_durable(command.last_seq_number(), options)
+            .await?;
+        Ok(seq_number)
+    }
+
+    /// Whether the active memtable takes another `additional` encoded bytes.
+    async fn has_capacity(&self, additional: usize) -> bool {
+        let active = self.store.read().await;
+        active.usage(self.memtable_accounting) + additional <= self.memtable_size
+            // the other half of the budget is left to the frozen memtables
+            && self
+                .memory_budget
+                .is_none_or(|budget| active.memory_usage() + additional <= budget / 2)
+    }
+
+    /// Locks the WAL for a write of `additional` encoded bytes. If the write freezes the active
+    /// memtable and the frozen ones would then take more than half the memory budget, waits
+    /// for flushes first.
+    async fn lock_wal(&self, additional: usize) -> MutexGuard<'_, Wal> {
+        loop {
+            let flushed = self.memtable_flushed.notified();
+            let mut flushed = std::pin::pin!(flushed);
+            // registered before the check, so a flush finishing in between still wakes us
+            flushed.as_mut().enable();
+
+            let wal = self.wal.lock().await;
+            if self.within_memory_budget(additional).await {
+                return wal;
+            }
+            // the flush result is applied under the wal lock
+            drop(wal);
+            flushed.await;
+        }
+    }
+
+    async fn within_memory_budget(&self, additional: usize) -> bool {
+        let Some(budget) = self.memory_budget else {
+            return true;
+        };
+        if self.has_capacity(additional).await {
+            return true;
+        }
+        // the budget limits the heap, whatever the memtable size is measured in
+        let active = self.store.read().await.memory_usage();
+        let frozen: usize = self
+            .flushable_tables
             .read()
             .await
-            .get_all()
-            .into_iter()
-            .map(|value| {
-                (
-                    String::from_utf8(value.0).expect("value not valid utf8"),
-                    String::from_utf8(value.1).expect("value not valid utf8"),
-                )
+            .values()
+            .map(|table| table.memory_usage())
+            .sum();
+        // with nothing frozen there is no flush to wait for
+        frozen == 0 || frozen + active <= budget / 2
+    }
+
+    /// Memtable space taken by the operations of a batch.
+    fn batch_len(ops: &[BatchOp]) -> usize {
+        ops.iter()
+            .map(|op| match op {
+                BatchOp::Put { key, value } => M::encoded_len(key, value),
+                BatchOp::Delete { key } => M::encoded_len(key, &[]),
             })
-            .collect()
+            .sum()
+    }
+
+    /// Inserts the operations under the write lock of the memtable, even one that takes
+    /// concurrent writers, so readers see all of them or none.
+    async fn apply_batch(&self, ops: &[BatchOp], first_seq_number: u64) {
+        let mut store = self.store.write().await;
+        for (seq_number, op) in (first_seq_number..).zip(ops) {
+            match op {
+                BatchOp::Put { key, value } => store.insert(key, value, seq_number),
+                BatchOp::Delete { key } => {
+                    store.delete(key, seq_number);
+                }
+            }
+        }
+    }
+
+    /// Every entry of the store as of now, across the memtables and all sstables.
+    ///
+    /// The entries are read in pages of `page_size` from a snapshot taken here, writes made
+    /// while paging are not seen.
+    pub fn get_all(self: &Arc<Self>, page_size: usize) -> SnapshotPages<M> {
+        SnapshotPages::new(self.snapshot(), page_size)
     }
 
     fn get_next_sequence_number(&self) -> u64 {
+        self.reserve_sequence_numbers(1)
+    }
+
+    /// Reserves `count` consecutive sequence numbers and returns the first.
+    fn reserve_sequence_numbers(&self, count: u64) -> u64 {
         self.sequence_number_counter
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+            .fetch_add(count, std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+/// Write access to the active memtable, see [`KvStore::active_memtable`].
+enum ActiveMemTable<'a, M: MemTable> {
+    Shared(RwLockReadGuard<'a, M>),
+    Exclusive(RwLockWriteGuard<'a, M>),
+}
+
+impl<M: MemTable> ActiveMemTable<'_, M> {
+    fn insert(&mut self, key: &[u8], value: &[u8], seq_number: u64) {
+        match self {
+            ActiveMemTable::Shared(table) => table
+                .concurrent()
+                .expect("checked when locking")
+                .insert_shared(key, value, seq_number),
+            ActiveMemTable::Exclusive(table) => table.insert(key, value, seq_number),
+        }
+    }
+
+    fn delete(&mut self, key: &[u8], seq_number: u64) -> Option<MemTableValue> {
+        match self {
+            ActiveMemTable::Shared(table) => table
+                .concurrent()
+                .expect("checked when locking")
+                .delete_shared(key, seq_number),
+            ActiveMemTable::Exclusive(table) => table.delete(key, seq_number),
+        }
+    }
+}
+
+/// Smallest key greater than every key starting with `prefix`, `None` if there is none.
+fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+/// Whether no key can be in `range`, [`std::collections::BTreeMap::range`] panics on those.
+fn is_empty_range((start, end): (Bound<&[u8]>, Bound<&[u8]>)) -> bool {
+    match (start, end) {
+        (Bound::Included(start), Bound::Included(end)) => start > end,
+        (Bound::Included(start), Bound::Excluded(end))
+        | (Bound::Excluded(start), Bound::Included(end))
+        | (Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+        _ => false,
     }
 }
@@ -1,25 +1,48 @@
 #[cfg(test)]
 mod tests {
     use crate::persists::{
-        KvStore,
-        lsm_tree::sorted_string_table::flush_worker::FlushResult,
-        memtable::{btree_map::BTreeMemTable, memtable_trait::MemTable},
+        Durability, KvStore, MemTableAccounting, StoreOptions, WriteBatch, WriteOptions,
+        lsm_tree::sorted_string_table::{flush_worker::FlushResult, sst_writer::SSTableWriter},
+        memtable::{
+            btree_map::BTreeMemTable, memtable_trait::MemTable, skiplist::SkipListMemTable,
+        },
     };
-    use std::sync::Arc;
+    use std::{path::PathBuf, sync::Arc};
     use tokio::task::JoinSet;
 
-    type TestKvStore = KvStore<64>;
+    type TestKvStore = KvStore;
+
+    // holds three puts of a 4 byte key and an 8 byte value plus a tombstone
+    const TEST_MEMTABLE_SIZE: usize = 110;
+
+    fn test_options(root_dir: impl Into<PathBuf>, memtable_size: usize) -> StoreOptions {
+        StoreOptions {
+            memtable_size,
+            ..StoreOptions::new(root_dir)
+        }
+    }
+
+    async fn wait_for_flush<M: MemTable>(store: &KvStore<M>) {
+        for _ in 0..200 {
+            if store.flushable_tables.read().await.is_empty() {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("flushable tables were not flushed in time");
+    }
 
     #[tokio::test]
     async fn test_insert_and_get() {
-        let store = TestKvStore::new().await;
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store = TestKvStore::new(test_options(tmpdir.path(), TEST_MEMTABLE_SIZE)).await;
 
         store
             .put_value("foo", "bar")
             .await
             .expect("Failed to put value");
 
-        match store.get_value("foo").await {
+        match store.get_value("foo").await.unwrap() {
             Some(val) => assert_eq!("bar", val),
             _ => panic!("Expected Found"),
         }
@@ -27,7 +50,16 @@ mod tests {
 
     #[tokio::test]
     async fn test_will_be_flushed() {
-        let store = TestKvStore::new().await;
+        let tmpdir = tempfile::tempdir().unwrap();
+        // flush results never reach the event loop, so the frozen memtable stays in place
+        let (flush_result_tx, _) = tokio::sync::mpsc::channel(16);
+        let (_, flush_result_rx) = tokio::sync::mpsc::channel(16);
+        let store = TestKvStore::new_with_channels(
+            test_options(tmpdir.path(), TEST_MEMTABLE_SIZE),
+            flush_result_tx,
+            flush_result_rx,
+        )
+        .await;
         let value = "abcdefgh";
 
         let _ = store.put_value("key1", value).await;
@@ -62,9 +94,94 @@ mod tests {
         assert_eq!(flush_keys, vec!["key1", "key2", "key3"]);
     }
 
+    #[tokio::test]
+    async fn overwrites_of_one_key_still_fill_the_memtable() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        // flush results never reach the event loop, so the frozen memtables stay in place
+        let (flush_result_tx, _) = tokio::sync::mpsc::channel(16);
+        let (_, flush_result_rx) = tokio::sync::mpsc::channel(16);
+        let store = TestKvStore::new_with_channels(
+            test_options(tmpdir.path(), TEST_MEMTABLE_SIZE),
+            flush_result_tx,
+            flush_result_rx,
+        )
+        .await;
+
+        for round in 0..7 {
+            store
+                .put_value("key", &format!("value{round:03}"))
+                .await
+                .unwrap();
+        }
+        assert_eq!(store.flushable_tables.read().await.len(), 2);
+        assert_eq!(
+            store.get_value("key").await.unwrap(),
+            Some("value006".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn memory_budget_stalls_writes_until_a_flush_finishes() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        // flush results are passed on to the event loop by hand
+        let (flush_result_tx, mut flushed_rx) = tokio::sync::mpsc::channel(16);
+        let (flushed_tx, flush_result_rx) = tokio::sync::mpsc::channel(16);
+        let value = "abcdefgh";
+        let mut full_memtable = BTreeMemTable::new();
+        for (seq, key) in ["key1", "key2", "key3"].into_iter().enumerate() {
+            full_memtable.insert(key.as_bytes(), value.as_bytes(), seq as u64 + 1);
+        }
+        // half the budget holds the active memtable but not another frozen one
+        let options = StoreOptions {
+            memory_budget: Some(3 * full_memtable.memory_usage()),
+            ..test_options(tmpdir.path(), TEST_MEMTABLE_SIZE)
+        };
+        let store = TestKvStore::new_with_channels(options, flush_result_tx, flush_result_rx).await;
+
+        // key4 freezes the first memtable, key7 would freeze a second one
+        for key in ["key1", "key2", "key3", "key4", "key5", "key6"] {
+            store.put_value(key, value).await.unwrap();
+        }
+        let stalled = tokio::spawn({
+            let store = Arc::clone(&store);
+            async move { store.put_value("key7", value).await.unwrap() }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(!stalled.is_finished());
+        assert_eq!(store.flushable_tables.read().await.len(), 1);
+
+        let flushed = flushed_rx.recv().await.unwrap();
+        flushed_tx.send(flushed).await.unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(5), stalled)
+            .await
+            .expect("the write still waits after the flush")
+            .unwrap();
+        assert_eq!(store.get_value("key7").await.unwrap(), Some(value.into()));
+        assert_eq!(store.get_value("key1").await.unwrap(), Some(value.into()));
+    }
+
+    #[tokio::test]
+    async fn memory_budget_limits_the_heap_of_the_active_memtable() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let options = StoreOptions {
+            memory_budget: Some(4096),
+            ..test_options(tmpdir.path(), 640_000)
+        };
+        let store = TestKvStore::new(options).await;
+
+        for i in 0..100 {
+            store.put_value(&format!("key{i}"), "value").await.unwrap();
+            assert!(store.store.read().await.memory_usage() <= 2048);
+        }
+        wait_for_flush(&store).await;
+        let counts = store.level_table_counts().await;
+        assert!(counts.iter().sum::<usize>() > 0);
+    }
+
     #[tokio::test]
     async fn seq_numbers_are_unique_and_monotone() {
-        let store = KvStore::<640_000>::new().await;
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store = TestKvStore::new(test_options(tmpdir.path(), 640_000)).await;
 
         let value = "value";
 
@@ -88,7 +205,8 @@ mod tests {
 
     #[tokio::test]
     async fn seq_numbers_are_unique_and_monotone_parallel_insert() {
-        let store = Arc::new(KvStore::<640_000>::new().await);
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store = Arc::new(TestKvStore::new(test_options(tmpdir.path(), 640_000)).await);
         let value = "value";
 
         let mut join_set = JoinSet::new();
@@ -124,9 +242,11 @@ mod tests {
 
     #[tokio::test]
     async fn value_from_mem_is_returned_over_flushable() {
-        let store = Arc::new(TestKvStore::new().await);
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store =
+            Arc::new(TestKvStore::new(test_options(tmpdir.path(), TEST_MEMTABLE_SIZE)).await);
 
-        let mut active_memtable = BTreeMemTable::<64>::new();
+        let mut active_memtable = BTreeMemTable::new();
         active_memtable.insert(b"key1", b"correct_value", 300);
 
         {
@@ -134,12 +254,12 @@ mod tests {
             *store_guard = active_memtable;
         }
 
-        let mut flush1 = BTreeMemTable::<64>::new();
+        let mut flush1 = BTreeMemTable::new();
         flush1.insert(b"key1", b"outdated_low", 100);
 
         let flush_arc1 = Arc::new(flush1);
 
-        let mut flush2 = BTreeMemTable::<64>::new();
+        let mut flush2 = BTreeMemTable::new();
         flush2.insert(b"key1", b"outdated_high", 200);
 
         let flush_arc2 = Arc::new(flush2);
@@ -150,18 +270,20 @@ mod tests {
             flushables_guard.insert(2, flush_arc2);
         }
 
-        let result = store.get_value("key1").await;
+        let result = store.get_value("key1").await.unwrap();
         assert_eq!(result, Some("correct_value".into()));
     }
     #[tokio::test]
     async fn value_is_selected_from_highest_seq_flushable_when_memtable_empty() {
-        let store = Arc::new(TestKvStore::new().await);
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store =
+            Arc::new(TestKvStore::new(test_options(tmpdir.path(), TEST_MEMTABLE_SIZE)).await);
         println!("hier0");
-        let mut flush1 = BTreeMemTable::<64>::new();
+        let mut flush1 = BTreeMemTable::new();
         flush1.insert(b"key1", b"outdated_low", 100);
         let flush_arc1 = Arc::new(flush1);
 
-        let mut flush2 = BTreeMemTable::<64>::new();
+        let mut flush2 = BTreeMemTable::new();
         flush2.insert(b"key1", b"correct_value", 200);
         let flush_arc2 = Arc::new(flush2);
 
@@ -171,7 +293,7 @@ mod tests {
             flushables_guard.insert(2, flush_arc2);
         }
 
-        let result = store.get_value("key1").await;
+        let result = store.get_value("key1").await.unwrap();
         assert_eq!(result, Some("correct_value".into()));
     }
 
@@ -179,17 +301,26 @@ mod tests {
     async fn test_event_loop_removes_table_after_flushresult() {
         let (flush_result_tx, flush_result_rx) = tokio::sync::mpsc::channel(16);
 
-        let store =
-            KvStore::<640>::new_with_channels(flush_result_tx.clone(), flush_result_rx).await;
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store = TestKvStore::new_with_channels(
+            test_options(tmpdir.path(), 640),
+            flush_result_tx.clone(),
+            flush_result_rx,
+        )
+        .await;
 
         let id = 1337;
-        let dummy_table = Arc::new(BTreeMemTable::<640>::new());
+        let dummy_table = Arc::new(BTreeMemTable::new());
         {
             let mut guard = store.flushable_tables.write().await;
             guard.insert(id, Arc::clone(&dummy_table));
         }
 
-        let path = std::path::PathBuf::from(format!("L0_{}.sst", uuid::Uuid::new_v4()));
+        let path = {
+            let lsm_manager = store.lsm_manager.read().await;
+            lsm_manager.file_numbers().new_table_path(lsm_manager.dir())
+        };
+        SSTableWriter::write_to_file(&path, dummy_table.flush(&[]), 0).unwrap();
 
         let _ = flush_result_tx.send(FlushResult::Ok((1337, path))).await;
 
@@ -201,4 +332,838 @@ mod tests {
             "Table with ID {id} should have been removed by event_loop"
         );
     }
+
+    #[tokio::test]
+    async fn event_loop_drops_a_second_table_of_a_registered_memtable() {
+        let (flush_result_tx, flush_result_rx) = tokio::sync::mpsc::channel(16);
+
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store = TestKvStore::new_with_channels(
+            test_options(tmpdir.path(), 640),
+            flush_result_tx.clone(),
+            flush_result_rx,
+        )
+        .await;
+
+        let id = 1337;
+        let mut table = BTreeMemTable::new();
+        table.insert(b"key1", b"value", 1);
+        let table = Arc::new(table);
+        store
+            .flushable_tables
+            .write()
+            .await
+            .insert(id, Arc::clone(&table));
+
+        // two flushes that both picked up the memtable before it was registered
+        let mut paths = Vec::new();
+        for _ in 0..2 {
+            let path = {
+                let lsm_manager = store.lsm_manager.read().await;
+                lsm_manager.file_numbers().new_table_path(lsm_manager.dir())
+            };
+            SSTableWriter::write_to_file(&path, table.flush(&[]), 0).unwrap();
+            flush_result_tx
+                .send(FlushResult::Ok((id, path.clone())))
+                .await
+                .unwrap();
+            paths.push(path);
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let lsm_manager = store.lsm_manager.read().await;
+        assert_eq!(lsm_manager.level_table_counts().iter().sum::<usize>(), 1);
+        assert!(paths[0].exists());
+        assert!(!paths[1].exists(), "the second table is deleted");
+    }
+
+    #[tokio::test]
+    async fn failed_flush_holds_back_newer_tables_until_it_is_flushed() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let options = test_options(tmpdir.path(), TEST_MEMTABLE_SIZE);
+        let value = "abcdefgh";
+
+        {
+            // flush results are passed on to the event loop by hand
+            let (flush_result_tx, mut flushed_rx) = tokio::sync::mpsc::channel(16);
+            let (flushed_tx, flush_result_rx) = tokio::sync::mpsc::channel(16);
+            let store =
+                TestKvStore::new_with_channels(options.clone(), flush_result_tx, flush_result_rx)
+                    .await;
+
+            // key4 freezes the memtable holding key1..key3, key7 the one holding key4..key6
+            let mut newer = 0;
+            for key in ["key1", "key2", "key3", "key4", "key5", "key6", "key7"] {
+                newer = store.put_value(key, value).await.unwrap();
+            }
+            loop {
+                let flushed = flushed_rx.recv().await.unwrap();
+                if let Ok((id, _)) = &flushed
+                    && *id == newer
+                {
+                    // the older memtable failed, the newer one is written
+                    flushed_tx.send(Err("disk full".into())).await.unwrap();
+                    flushed_tx.send(flushed).await.unwrap();
+                    break;
+                }
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+            assert_eq!(store.flushable_tables.read().await.len(), 2);
+            let lsm_manager = store.lsm_manager.read().await;
+            assert_eq!(lsm_manager.level_table_counts().iter().sum::<usize>(), 0);
+        }
+
+        // the wal still holds both memtables, nothing was skipped as persisted
+        let store = TestKvStore::new(options).await;
+        for key in ["key1", "key2", "key3", "key4", "key5", "key6", "key7"] {
+            assert_eq!(store.get_value(key).await.unwrap(), Some(value.into()));
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_flush_is_retried() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        // flush results are passed on to the event loop by hand
+        let (flush_result_tx, mut flushed_rx) = tokio::sync::mpsc::channel(16);
+        let (flushed_tx, flush_result_rx) = tokio::sync::mpsc::channel(16);
+        let store = TestKvStore::new_with_channels(
+            test_options(tmpdir.path(), TEST_MEMTABLE_SIZE),
+            flush_result_tx,
+            flush_result_rx,
+        )
+        .await;
+
+        // key4 freezes the memtable holding key1..key3
+        for key in ["key1", "key2", "key3", "key4"] {
+            store.put_value(key, "abcdefgh").await.unwrap();
+        }
+        flushed_rx.recv().await.unwrap().unwrap();
+        flushed_tx.send(Err("disk full".into())).await.unwrap();
+
+        // no further write freezes a memtable, the retry writes it again
+        let retried = tokio::time::timeout(std::time::Duration::from_secs(5), flushed_rx.recv())
+            .await
+            .expect("the failed flush was not retried")
+            .unwrap();
+        flushed_tx.send(retried).await.unwrap();
+        wait_for_flush(&store).await;
+        assert_eq!(store.level_table_counts().await, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn tombstone_in_memtable_hides_flushable_value() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store = TestKvStore::new(test_options(tmpdir.path(), TEST_MEMTABLE_SIZE)).await;
+
+        let mut flush = BTreeMemTable::new();
+        flush.insert(b"key1", b"old_value", 100);
+        store
+            .flushable_tables
+            .write()
+            .await
+            .insert(1, Arc::new(flush));
+
+        store.store.write().await.delete(b"key1", 200);
+
+        assert_eq!(store.get_value("key1").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn newest_tombstone_across_flushables_wins() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store = TestKvStore::new(test_options(tmpdir.path(), TEST_MEMTABLE_SIZE)).await;
+
+        let mut flush1 = BTreeMemTable::new();
+        flush1.insert(b"key1", b"old_value", 100);
+
+        let mut flush2 = BTreeMemTable::new();
+        flush2.delete(b"key1", 200);
+
+        {
+            let mut flushables_guard = store.flushable_tables.write().await;
+            flushables_guard.insert(1, Arc::new(flush1));
+            flushables_guard.insert(2, Arc::new(flush2));
+        }
+
+        assert_eq!(store.get_value("key1").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn delete_in_memtable_hides_flushed_value() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store = TestKvStore::new(test_options(tmpdir.path(), TEST_MEMTABLE_SIZE)).await;
+        let value = "abcdefgh";
+
+        store.put_value("key1", value).await.unwrap();
+        store.put_value("key2", value).await.unwrap();
+        store.put_value("key3", value).await.unwrap();
+        // rotates the memtable holding key1
+        store.put_value("key4", value).await.unwrap();
+        wait_for_flush(&store).await;
+
+        assert_eq!(store.get_value("key1").await.unwrap(), Some(value.into()));
+
+        store.delete_value("key1").await.unwrap();
+        assert_eq!(store.get_value("key1").await.unwrap(), None);
+        assert_eq!(store.get_value("key2").await.unwrap(), Some(value.into()));
+    }
+
+    #[tokio::test]
+    async fn flushed_tombstone_hides_older_flushed_value() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store = TestKvStore::new(test_options(tmpdir.path(), TEST_MEMTABLE_SIZE)).await;
+        let value = "abcdefgh";
+
+        store.put_value("key1", value).await.unwrap();
+        store.put_value("key2", value).await.unwrap();
+        store.put_value("key3", value).await.unwrap();
+        store.put_value("key4", value).await.unwrap();
+        wait_for_flush(&store).await;
+
+        store.delete_value("key1").await.unwrap();
+        store.put_value("key5", value).await.unwrap();
+        store.put_value("key6", value).await.unwrap();
+        // rotates the memtable holding the tombstone for key1
+        store.put_value("key7", value).await.unwrap();
+        wait_for_flush(&store).await;
+
+        assert!(
+            store
+                .store
+                .read()
+                .await
+                .iter_all()
+                .all(|(k, _)| k != b"key1")
+        );
+        assert_eq!(store.get_value("key1").await.unwrap(), None);
+        assert_eq!(store.get_value("key4").await.unwrap(), Some(value.into()));
+    }
+
+    #[tokio::test]
+    async fn newer_flushed_value_wins_over_older_flushed_value() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store = TestKvStore::new(test_options(tmpdir.path(), TEST_MEMTABLE_SIZE)).await;
+        let value = "abcdefgh";
+
+        store.put_value("key1", "old_val1").await.unwrap();
+        store.put_value("key2", value).await.unwrap();
+        store.put_value("key3", value).await.unwrap();
+        store.put_value("key4", value).await.unwrap();
+        wait_for_flush(&store).await;
+
+        store.put_value("key1", "new_val1").await.unwrap();
+        store.put_value("key5", value).await.unwrap();
+        store.put_value("key6", value).await.unwrap();
+        store.put_value("key7", value).await.unwrap();
+        wait_for_flush(&store).await;
+
+        assert_eq!(
+            store.get_value("key1").await.unwrap(),
+            Some("new_val1".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn files_are_kept_below_the_root_dir() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let options = test_options(tmpdir.path().join("store"), TEST_MEMTABLE_SIZE);
+        let store = TestKvStore::new(options.clone()).await;
+        let value = "abcdefgh";
+
+        for key in ["key1", "key2", "key3", "key4"] {
+            store.put_value(key, value).await.unwrap();
+        }
+        wait_for_flush(&store).await;
+
+        assert!(options.manifest_path().is_file());
+        assert!(options.wal_dir().join("000002.log").is_file());
+        let tables = std::fs::read_dir(options.sst_dir()).unwrap().count();
+        assert_eq!(tables, 1);
+        assert_eq!(
+            std::fs::read_dir(&options.root_dir).unwrap().count(),
+            3,
+            "only MANIFEST, wal/ and sst/ belong in the root dir"
+        );
+    }
+
+    #[tokio::test]
+    async fn unflushed_writes_are_recovered_from_the_wal() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let options = test_options(tmpdir.path(), 640_000);
+
+        let last_seq = {
+            let store = TestKvStore::new(options.clone()).await;
+            store.put_value("key1", "value1").await.unwrap();
+            store.put_value("key2", "value2").await.unwrap();
+            store.delete_value("key1").await.unwrap();
+            store.put_value("key3", "value3").await.unwrap()
+        };
+
+        let store = TestKvStore::new(options).await;
+        assert_eq!(store.get_value("key1").await.unwrap(), None);
+        assert_eq!(
+            store.get_value("key2").await.unwrap(),
+            Some("value2".into())
+        );
+        assert_eq!(
+            store.get_value("key3").await.unwrap(),
+            Some("value3".into())
+        );
+        assert_eq!(
+            store.put_value("key4", "value4").await.unwrap(),
+            last_seq + 1
+        );
+    }
+
+    #[tokio::test]
+    async fn snapshot_ignores_newer_writes() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store = TestKvStore::new(test_options(tmpdir.path(), 640_000)).await;
+        let empty = store.snapshot();
+
+        store.put_value("key1", "v1").await.unwrap();
+        store.put_value("key2", "v1").await.unwrap();
+        let snapshot = store.snapshot();
+
+        store.put_value("key1", "v2").await.unwrap();
+        store.delete_value("key2").await.unwrap();
+        store.put_value("key3", "v1").await.unwrap();
+
+        assert_eq!(snapshot.get("key1").await.unwrap(), Some("v1".into()));
+        assert_eq!(snapshot.get("key2").await.unwrap(), Some("v1".into()));
+        assert_eq!(snapshot.get("key3").await.unwrap(), None);
+        assert_eq!(empty.get("key1").await.unwrap(), None);
+        assert_eq!(store.get_value("key1").await.unwrap(), Some("v2".into()));
+        assert_eq!(store.get_value("key2").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn snapshot_survives_flush_and_compaction() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        // overwrites only grow the heap, the encoded size stays at one version per key
+        let options = StoreOptions {
+            memtable_accounting: MemTableAccounting::HeapUsage,
+            ..test_options(tmpdir.path(), TEST_MEMTABLE_SIZE)
+        };
+        let store = TestKvStore::new(options).await;
+        let value = |round: usize| format!("value{round:03}");
+
+        store.put_value("key", &value(0)).await.unwrap();
+        let snapshot = store.snapshot();
+        // every put freezes the memtable, the versions end up in separate tables
+        for round in 1..=12 {
+            store.put_value("key", &value(round)).await.unwrap();
+            store.put_value("other", &value(round)).await.unwrap();
+        }
+        wait_for_flush(&store).await;
+        for _ in 0..200 {
+            if store.level_table_counts().await[0] < 4 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let counts = store.level_table_counts().await;
+        assert!(counts.len() > 1, "nothing was compacted: {counts:?}");
+
+        assert_eq!(snapshot.get("key").await.unwrap(), Some(value(0)));
+        assert_eq!(snapshot.get("other").await.unwrap(), None);
+        assert_eq!(store.get_value("key").await.unwrap(), Some(value(12)));
+
+        let sequence_number = snapshot.sequence_number();
+        drop(snapshot);
+        assert!(
+            !store
+                .lsm_manager
+                .read()
+                .await
+                .snapshots()
+                .sequence_numbers()
+                .contains(&sequence_number)
+        );
+    }
+
+    #[tokio::test]
+    async fn skiplist_memtable_serves_concurrent_readers_and_writers() {
+        type SkipListKvStore = KvStore<SkipListMemTable>;
+        let tmpdir = tempfile::tempdir().unwrap();
+        let options = test_options(tmpdir.path(), 256);
+
+        {
+            let store = SkipListKvStore::new(options.clone()).await;
+            let mut join_set = JoinSet::new();
+            for writer in 0..4 {
+                let store = Arc::clone(&store);
+                join_set.spawn(async move {
+                    for i in 0..25 {
+                        let key = format!("key{writer}-{i:02}");
+                        store.put_value(&key, "value").await.unwrap();
+                        assert_eq!(store.get_value(&key).await.unwrap(), Some("value".into()));
+                    }
+                });
+            }
+            join_set.join_all().await;
+            store.delete_value("key0-00").await.unwrap();
+            let snapshot = store.snapshot();
+            store.put_value("key0-01", "newer").await.unwrap();
+
+            assert_eq!(snapshot.get("key0-01").await.unwrap(), Some("value".into()));
+            assert_eq!(store.scan_prefix("key", 200).await.unwrap().len(), 99);
+        }
+
+        // flushed sstables and the wal of the last memtables are read back
+        let store = SkipListKvStore::new(options).await;
+        assert_eq!(store.get_value("key0-00").await.unwrap(), None);
+        assert_eq!(
+            store.get_value("key0-01").await.unwrap(),
+            Some("newer".into())
+        );
+        assert_eq!(
+            store.get_value("key3-24").await.unwrap(),
+            Some("value".into())
+        );
+        assert_eq!(store.scan(.., 200).await.unwrap().len(), 99);
+    }
+
+    #[tokio::test]
+    async fn scan_merges_memtables_and_sstables() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store = TestKvStore::new(test_options(tmpdir.path(), 256)).await;
+        let mut expected = std::collections::BTreeMap::new();
+
+        // older rounds end up in sstables, the last one partly stays in the memtables
+        for round in 0..3 {
+            for key in (round..20).step_by(round + 1) {
+                let (key, value) = (format!("key{key:02}"), format!("value{key:02}_{round}"));
+                store.put_value(&key, &value).await.unwrap();
+                expected.insert(key, value);
+            }
+        }
+        for key in ["key05", "key12"] {
+            store.delete_value(key).await.unwrap();
+            expected.remove(key);
+        }
+        wait_for_flush(&store).await;
+        store.put_value("key07", "newest").await.unwrap();
+        expected.insert("key07".into(), "newest".into());
+
+        let entries = |range: std::ops::Range<&str>| -> Vec<(String, String)> {
+            expected
+                .range::<str, _>((
+                    std::ops::Bound::Included(range.start),
+                    std::ops::Bound::Excluded(range.end),
+                ))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()
+        };
+
+        assert_eq!(store.scan(.., usize::MAX).await.unwrap(), entries("".."z"));
+        assert_eq!(
+            store.scan("key03".."key09", 3).await.unwrap(),
+            entries("key03".."key07")
+        );
+        assert!(store.scan("key09".."key03", 10).await.unwrap().is_empty());
+        assert_eq!(
+            store
+                .scan(
+                    (
+                        std::ops::Bound::Excluded("key18"),
+                        std::ops::Bound::Unbounded
+                    ),
+                    10
+                )
+                .await
+                .unwrap(),
+            entries("key19".."z")
+        );
+    }
+
+    #[tokio::test]
+    async fn scan_prefix_returns_matching_keys_in_order() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let options = StoreOptions {
+            prefix_bloom_len: Some("user:0:".len()),
+            ..test_options(tmpdir.path(), 256)
+        };
+        let store = TestKvStore::new(options).await;
+
+        for id in 0..8 {
+            for field in ["profile", "settings"] {
+                store
+                    .put_value(&format!("user:{id}:{field}"), &format!("{id}-{field}"))
+                    .await
+                    .unwrap();
+            }
+        }
+        store.put_value("users", "not a user").await.unwrap();
+        store.delete_value("user:3:settings").await.unwrap();
+        wait_for_flush(&store).await;
+
+        assert_eq!(
+            store.scan_prefix("user:3:", 10).await.unwrap(),
+            vec![("user:3:profile".into(), "3-profile".into())]
+        );
+
+        let first_page = store.scan_prefix("user:", 5).await.unwrap();
+        let keys: Vec<_> = first_page.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(
+            keys,
+            [
+                "user:0:profile",
+                "user:0:settings",
+                "user:1:profile",
+                "user:1:settings",
+                "user:2:profile"
+            ]
+        );
+        let rest = store
+            .scan_prefix_from("user:", "user:2:settings", 100)
+            .await
+            .unwrap();
+        assert_eq!(rest.len(), 15 - 5);
+        assert!(rest.iter().all(|(key, _)| key.starts_with("user:")));
+        assert!(store.scan_prefix("user:9", 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn snapshot_scan_ignores_newer_writes() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store = TestKvStore::new(test_options(tmpdir.path(), TEST_MEMTABLE_SIZE)).await;
+        store.put_value("a", "1").await.unwrap();
+        store.put_value("b", "1").await.unwrap();
+        let snapshot = store.snapshot();
+
+        store.put_value("a", "2").await.unwrap();
+        store.delete_value("b").await.unwrap();
+        store.put_value("c", "2").await.unwrap();
+        wait_for_flush(&store).await;
+
+        assert_eq!(
+            snapshot.scan(.., 10).await.unwrap(),
+            vec![("a".into(), "1".into()), ("b".into(), "1".into())]
+        );
+        assert_eq!(
+            store.scan(.., 10).await.unwrap(),
+            vec![("a".into(), "2".into()), ("c".into(), "2".into())]
+        );
+    }
+
+    #[tokio::test]
+    async fn get_all_pages_through_every_tier_at_one_snapshot() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store = TestKvStore::new(test_options(tmpdir.path(), TEST_MEMTABLE_SIZE)).await;
+        // the tiny memtable rotates after every few writes
+        for i in 0..20 {
+            store
+                .put_value(&format!("key{i:02}"), &format!("v{i}"))
+                .await
+                .unwrap();
+        }
+        store.delete_value("key05").await.unwrap();
+        wait_for_flush(&store).await;
+        store.put_value("key00", "new").await.unwrap();
+
+        let mut pages = store.get_all(3);
+        let mut entries = Vec::new();
+        let mut first = true;
+        while let Some(page) = pages.next_page().await.unwrap() {
+            assert!(page.len() <= 3);
+            entries.extend(page);
+            if first {
+                // neither the pages already read nor the ones to come see this
+                store.put_value("key99", "late").await.unwrap();
+                store.delete_value("key19").await.unwrap();
+                first = false;
+            }
+        }
+
+        let mut expected: Vec<(Vec<u8>, Vec<u8>)> = (0..20)
+            .filter(|&i| i != 5)
+            .map(|i| (format!("key{i:02}").into(), format!("v{i}").into()))
+            .collect();
+        expected[0].1 = b"new".to_vec();
+        assert_eq!(entries, expected);
+        assert_eq!(
+            store
+                .get_all(100)
+                .next_page()
+                .await
+                .unwrap()
+                .unwrap()
+                .last(),
+            Some(&(b"key99".to_vec(), b"late".to_vec()))
+        );
+    }
+
+    #[tokio::test]
+    async fn write_batch_uses_consecutive_sequence_numbers() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let store = TestKvStore::new(test_options(tmpdir.path(), 640_000)).await;
+        store.put_value("stale", "old").await.unwrap();
+
+        let mut batch = WriteBatch::new();
+        batch
+            .put("key1", "value1")
+            .put("key2", "value2")
+            .delete("stale")
+            .put("key1", "value1b");
+        let first_seq = store.write(batch, WriteOptions::default()).await.unwrap();
+
+        assert_eq!(
+            store.get_value("key1").await.unwrap(),
+            Some("value1b".into())
+        );
+        assert_eq!(
+            store.get_value("key2").await.unwrap(),
+            Some("value2".into())
+        );
+        assert_eq!(store.get_value("stale").await.unwrap(), None);
+        assert_eq!(
+            store.put_value("key3", "value3").await.unwrap(),
+            first_seq + 4
+        );
+        assert!(
+            store
+                .write(WriteBatch::new(), WriteOptions::default())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn write_batch_is_recovered_from_the_wal() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let options = test_options(tmpdir.path(), TEST_MEMTABLE_SIZE);
+
+        let first_seq = {
+            let store = TestKvStore::new(options.clone()).await;
+            store.put_value("key1", "value1").await.unwrap();
+            let mut batch = WriteBatch::new();
+            // larger than the memtable, the active one is frozen and the batch still kept together
+            for i in 0..8 {
+                batch.put(format!("batch{i}"), "abcdefgh");
+            }
+            batch.delete("key1");
+            store.write(batch, WriteOptions::default()).await.unwrap()
+        };
+
+        let store = TestKvStore::new(options).await;
+        for i in 0..8 {
+            assert_eq!(
+                store.get_value(&format!("batch{i}")).await.unwrap(),
+                Some("abcdefgh".into())
+            );
+        }
+        assert_eq!(store.get_value("key1").await.unwrap(), None);
+        assert_eq!(
+            store.put_value("key2", "value2").await.unwrap(),
+            first_seq + 9
+        );
+    }
+
+    #[tokio::test]
+    async fn binary_keys_and_values_round_trip_through_every_tier() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let options = test_options(tmpdir.path(), TEST_MEMTABLE_SIZE);
+        let entry = |i: u8| (vec![0xff, i, 0x00], vec![0x89, b'P', b'N', b'G', 0xfe, i]);
+
+        {
+            let store = TestKvStore::new(options.clone()).await;
+            for i in 0..6 {
+                let (key, value) = entry(i);
+                store.put_bytes(&key, &value).await.unwrap();
+            }
+            wait_for_flush(&store).await;
+            // still in the wal when the store is reopened
+            let (key, value) = entry(6);
+            store.put_bytes(&key, &value).await.unwrap();
+            assert_eq!(
+                store.delete_bytes(&entry(6).0).await.unwrap().0,
+                Some(entry(6).1),
+                "the value is returned unchanged"
+            );
+            store.put_bytes(&key, &value).await.unwrap();
+        }
+
+        let store = TestKvStore::new(options).await;
+        for i in 0..7 {
+            let (key, value) = entry(i);
+            assert_eq!(store.get_bytes(&key).await.unwrap(), Some(value));
+        }
+        let prefix = store.scan_prefix_bytes(&[0xff], 10).await.unwrap();
+        assert_eq!(prefix, (0..7).map(entry).collect::<Vec<_>>());
+        // the text api never panics on binary data
+        assert!(store.scan(.., 10).await.unwrap()[0].1.contains('\u{fffd}'));
+    }
+
+    #[tokio::test]
+    async fn legacy_wal_outside_the_store_is_migrated() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        // older versions wrote the log to the working directory
+        let legacy_path = tmpdir.path().join("wal.log");
+        std::fs::write(
+            &legacy_path,
+            concat!(
+                r#"{"Put":{"key":"key1","value":"value1","seq_number":1}}"#,
+                "\n",
+                r#"{"Delete":{"key":"key2","seq_number":2}}"#,
+                "\n",
+            ),
+        )
+        .unwrap();
+        let options = StoreOptions {
+            legacy_wal_path: Some(legacy_path.clone()),
+            ..test_options(tmpdir.path().join("store"), TEST_MEMTABLE_SIZE)
+        };
+
+        let store = TestKvStore::new(options).await;
+        assert_eq!(
+            store.get_value("key1").await.unwrap(),
+            Some("value1".into())
+        );
+        assert_eq!(store.put_value("key3", "value3").await.unwrap(), 3);
+        assert!(!legacy_path.exists());
+    }
+
+    #[tokio::test]
+    async fn recovery_skips_entries_already_in_sstables() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let options = test_options(tmpdir.path(), TEST_MEMTABLE_SIZE);
+        let value = "abcdefgh";
+
+        let last_seq = {
+            let store = TestKvStore::new(options.clone()).await;
+            for key in ["key1", "key2", "key3"] {
+                store.put_value(key, value).await.unwrap();
+            }
+            // rotates the memtable holding key1..key3
+            let last_seq = store.put_value("key4", value).await.unwrap();
+            wait_for_flush(&store).await;
+            last_seq
+        };
+
+        let store = TestKvStore::new(options).await;
+        let replayed: Vec<_> = store
+            .store
+            .read()
+            .await
+            .iter_all()
+            .map(|(key, _)| key.to_vec())
+            .collect();
+        assert_eq!(replayed, vec![b"key4".to_vec()]);
+        for key in ["key1", "key2", "key3", "key4"] {
+            assert_eq!(store.get_value(key).await.unwrap(), Some(value.into()));
+        }
+        assert!(store.put_value("key5", value).await.unwrap() > last_seq);
+    }
+
+    #[tokio::test]
+    async fn wal_segments_are_removed_after_flush() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let options = test_options(tmpdir.path(), TEST_MEMTABLE_SIZE);
+        let store = TestKvStore::new(options.clone()).await;
+        let value = "abcdefgh";
+
+        for key in ["key1", "key2", "key3"] {
+            store.put_value(key, value).await.unwrap();
+        }
+        // rotates the memtable and starts segment 2
+        store.put_value("key4", value).await.unwrap();
+        wait_for_flush(&store).await;
+
+        let mut segments = Vec::new();
+        for _ in 0..200 {
+            segments = wal_segments(&options);
+            if segments.len() == 1 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(segments, vec!["000002.log"]);
+
+        // key4 is only in the remaining segment and survives a restart
+        drop(store);
+        let store = TestKvStore::new(options.clone()).await;
+        assert_eq!(store.get_value("key4").await.unwrap(), Some(value.into()));
+        assert_eq!(store.get_value("key1").await.unwrap(), Some(value.into()));
+    }
+
+    fn wal_segments(options: &StoreOptions) -> Vec<String> {
+        let mut segments: Vec<_> = std::fs::read_dir(options.wal_dir())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        segments.sort();
+        segments
+    }
+
+    #[tokio::test]
+    async fn group_commit_shares_syncs_between_concurrent_writers() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let options = StoreOptions {
+            durability: Durability::GroupCommit,
+            ..test_options(tmpdir.path(), 640_000)
+        };
+        let store = TestKvStore::new(options).await;
+
+        let mut join_set = JoinSet::new();
+        for i in 0..64 {
+            let store = Arc::clone(&store);
+            join_set.spawn(async move { store.put_value(&i.to_string(), "value").await });
+        }
+        while let Some(result) = join_set.join_next().await {
+            result.expect("task panicked").expect("put failed");
+        }
+
+        let syncs = store.wal_sync_count();
+        assert!((1..64).contains(&syncs), "{syncs} syncs for 64 writes");
+    }
+
+    #[tokio::test]
+    async fn interval_durability_only_syncs_on_request() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let options = StoreOptions {
+            durability: Durability::Interval(std::time::Duration::from_secs(3600)),
+            ..test_options(tmpdir.path(), 640_000)
+        };
+        let store = TestKvStore::new(options.clone()).await;
+
+        store.put_value("key1", "value1").await.unwrap();
+        assert_eq!(store.wal_sync_count(), 0);
+
+        store
+            .put_value_with_options("key2", "value2", WriteOptions { sync: true })
+            .await
+            .unwrap();
+        assert_eq!(store.wal_sync_count(), 1);
+
+        // unsynced writes still reached the OS and survive a process restart
+        let store = TestKvStore::new(options).await;
+        assert_eq!(
+            store.get_value("key1").await.unwrap(),
+            Some("value1".into())
+        );
+    }
+
+    #[tokio::test]
+    async fn always_durability_syncs_every_write() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let options = StoreOptions {
+            durability: Durability::Always,
+            ..test_options(tmpdir.path(), 640_000)
+        };
+        let store = TestKvStore::new(options.clone()).await;
+
+        store.put_value("key1", "value1").await.unwrap();
+        store.delete_value("key1").await.unwrap();
+        store.put_value("key2", "value2").await.unwrap();
+
+        let store = TestKvStore::new(options).await;
+        assert_eq!(store.get_value("key1").await.unwrap(), None);
+        assert_eq!(
+            store.get_value("key2").await.unwrap(),
+            Some("value2".into())
+        );
+    }
 }
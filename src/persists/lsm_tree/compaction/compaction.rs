@@ -1,60 +1,166 @@
-use crate::persists::lsm_tree::sorted_string_table::{
-    sorted_string_table::SortedStringTable, sst_writer::SSTableWriter, table_result::TableResult,
+use crate::persists::lsm_tree::{
+    manifest::FileNumbers,
+    sorted_string_table::{
+        sorted_string_table::SortedStringTable,
+        sst_writer::{SSTableWriter, TableOptions},
+        table_error::TableError,
+        table_result::TableResult,
+    },
 };
-use std::{cmp::Ordering, collections::BinaryHeap, path::PathBuf};
+use std::{cmp::Ordering, collections::BinaryHeap, path::PathBuf, sync::Arc};
 
-fn compact(tables: Vec<SortedStringTable>) -> Result<PathBuf, std::io::Error> {
-    let mut heap = BinaryHeap::<CompactionHeapEntry>::with_capacity(tables.len());
-    let mut writer = SSTableWriter::new(std::path::PathBuf::from(format!(
-        "L0_{}.sst",
-        uuid::Uuid::new_v4()
-    )))
-    .expect("failed to create writer");
+/// Tables picked for one compaction run, all of them are replaced by the outputs.
+pub struct CompactionTask {
+    pub inputs: Vec<Arc<SortedStringTable>>,
+    pub output_level: usize,
+}
+
+/// Where the merged tables are written and which versions they have to preserve.
+#[derive(Clone)]
+pub struct CompactionOutput {
+    pub dir: PathBuf,
+    /// Names the output tables, shared with the flush worker.
+    pub file_numbers: Arc<FileNumbers>,
+    pub level: usize,
+    /// A new output table is started at the first new key once the current one reaches this
+    /// size, all versions of a key stay in one table.
+    pub target_file_size: u64,
+    /// Options of the output tables, the level is taken from `level`.
+    pub table_options: TableOptions,
+    /// Sequence numbers of open snapshots in ascending order.
+    /// The newest version of a key visible to each of them is kept.
+    pub snapshots: Vec<u64>,
+    /// Tables that are not compacted. A tombstone is only dropped if none of them
+    /// can hold an older version of its key.
+    pub other_tables: Vec<Arc<SortedStringTable>>,
+}
 
-    let mut iters = Vec::new();
+/// Entry counts of a single compaction run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionStats {
+    pub input_entries: u64,
+    pub output_entries: u64,
+    /// Older versions that no open snapshot can see.
+    pub dropped_versions: u64,
+    /// Tombstones that no longer hide anything.
+    pub dropped_tombstones: u64,
+}
 
-    tables.iter().enumerate().for_each(|(index, table)| {
-        let mut iter = table.iter();
+/// Merges `tables` into one or more sorted, non-overlapping tables.
+///
+/// Versions of a key are grouped by the oldest snapshot that can see them, only the newest
+/// version of each group is kept. Without snapshots that leaves the newest version.
+/// A tombstone is dropped once no snapshot predates it and no table outside the
+/// compaction may hold an older version of its key.
+///
+/// Partially written outputs are removed on error.
+pub fn compact(
+    tables: &[Arc<SortedStringTable>],
+    output: &CompactionOutput,
+) -> Result<(Vec<PathBuf>, CompactionStats), TableError> {
+    let mut created = Vec::new();
+    let result = merge_into(tables, output, &mut created);
+    if result.is_err() {
+        for path in &created {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+    result.map(|stats| (created, stats))
+}
+
+fn merge_into(
+    tables: &[Arc<SortedStringTable>],
+    output: &CompactionOutput,
+    created: &mut Vec<PathBuf>,
+) -> Result<CompactionStats, TableError> {
+    let mut heap = BinaryHeap::<CompactionHeapEntry>::with_capacity(tables.len());
+    let mut iters: Vec<_> = tables.iter().map(|table| table.iter()).collect();
+
+    for (index, iter) in iters.iter_mut().enumerate() {
         if let Some(first) = iter.next() {
-            println!("pushed {:?}", first);
             heap.push(CompactionHeapEntry {
                 table_result: first,
                 table_index: index,
             });
         }
-        iters.push(iter);
-    });
-
-    let mut last_added = heap
-        .pop()
-        .expect("There should be minimum one value to compact");
-
-    writer.append_entry(&last_added.table_result);
-
-    if let Some(next_value) = iters[last_added.table_index].next() {
-        println!("pushed {:?}", next_value);
-        heap.push(CompactionHeapEntry {
-            table_result: next_value,
-            table_index: last_added.table_index,
-        });
     }
 
+    let options = TableOptions {
+        level: output.level as u32,
+        ..output.table_options.clone()
+    };
+    let mut writer: Option<SSTableWriter> = None;
+    let mut stats = CompactionStats::default();
+    let mut last_key: Option<&[u8]> = None;
+    let mut last_stripe = 0;
+    let mut last_written: Option<&[u8]> = None;
+
+    // the heap yields keys in ascending order, the newest version of a key first
     while let Some(next) = heap.pop() {
         if let Some(next_from_same) = iters[next.table_index].next() {
-            println!("pushed {:?}", next_from_same);
             heap.push(CompactionHeapEntry {
                 table_result: next_from_same,
                 table_index: next.table_index,
             });
         }
 
-        if next.table_result.key != last_added.table_result.key {
-            writer.append_entry(&next.table_result);
-            last_added = next;
+        let entry = &next.table_result;
+        stats.input_entries += 1;
+
+        // index of the oldest snapshot that sees this version, `snapshots.len()` for the latest state
+        let stripe = output
+            .snapshots
+            .partition_point(|&snapshot| snapshot < entry.sequence_number);
+        if last_key == Some(entry.key) && last_stripe == stripe {
+            stats.dropped_versions += 1;
+            continue;
+        }
+        last_key = Some(entry.key);
+        last_stripe = stripe;
+
+        // older versions in the same stripe are dropped as shadowed right after
+        if entry.is_tombstone() && stripe == 0 && !older_version_may_exist(output, entry) {
+            stats.dropped_tombstones += 1;
+            continue;
         }
+
+        // cutting between versions of a key would give the outputs overlapping key ranges
+        if last_written != Some(entry.key)
+            && let Some(full) =
+                writer.take_if(|current| current.written_bytes() >= output.target_file_size)
+        {
+            full.finalize()?;
+        }
+
+        let current = match &mut writer {
+            Some(current) => current,
+            None => {
+                let path = output.file_numbers.new_table_path(&output.dir);
+                created.push(path.clone());
+                writer.insert(SSTableWriter::new_with_options(path, &options)?)
+            }
+        };
+        current.append_entry(entry)?;
+        last_written = Some(entry.key);
+        stats.output_entries += 1;
+    }
+
+    // an iterator that hit a corrupted block stops early, the merged table would be missing entries
+    if let Some(e) = iters.iter_mut().find_map(|iter| iter.take_error()) {
+        return Err(e);
+    }
+
+    if let Some(last) = writer {
+        last.finalize()?;
     }
+    Ok(stats)
+}
 
-    writer.finalize()
+fn older_version_may_exist(output: &CompactionOutput, entry: &TableResult) -> bool {
+    output.other_tables.iter().any(|table| {
+        table.properties().min_sequence_number < entry.sequence_number
+            && table.may_contain(entry.key)
+    })
 }
 
 struct CompactionHeapEntry<'a> {
@@ -72,7 +178,7 @@ impl<'a> Eq for CompactionHeapEntry<'a> {}
 
 impl<'a> PartialOrd for CompactionHeapEntry<'a> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.table_result.partial_cmp(&other.table_result)
+        Some(self.cmp(other))
     }
 }
 
@@ -89,11 +195,40 @@ mod tests {
 
     type Entry = (Vec<u8>, (Option<Vec<u8>>, u64));
 
-    fn build_table(dir: &tempfile::TempDir, name: &str, entries: &[Entry]) -> SortedStringTable {
+    fn build_table(
+        dir: &tempfile::TempDir,
+        name: &str,
+        entries: &[Entry],
+    ) -> Arc<SortedStringTable> {
         let path = dir.path().join(name);
 
         SSTableWriter::write_to_file(&path, entries.to_vec(), 4 * 1024).unwrap();
-        SortedStringTable::new(&path).unwrap()
+        Arc::new(SortedStringTable::new(&path).unwrap())
+    }
+
+    fn output(dir: &tempfile::TempDir, target_file_size: u64) -> CompactionOutput {
+        CompactionOutput {
+            dir: dir.path().to_path_buf(),
+            file_numbers: Arc::new(FileNumbers::new(100)),
+            level: 1,
+            target_file_size,
+            table_options: TableOptions::default(),
+            snapshots: Vec::new(),
+            other_tables: Vec::new(),
+        }
+    }
+
+    fn entries(table: &SortedStringTable) -> Vec<(Vec<u8>, Option<Vec<u8>>, u64)> {
+        table
+            .iter()
+            .map(|entry| {
+                (
+                    entry.key.to_vec(),
+                    entry.value().map(<[u8]>::to_vec),
+                    entry.sequence_number,
+                )
+            })
+            .collect()
     }
 
     #[test]
@@ -119,9 +254,12 @@ mod tests {
             ],
         );
 
-        let out_path = compact(vec![t1, t2]).unwrap();
+        let (outputs, stats) = compact(&[t1, t2], &output(&tmpdir, u64::MAX)).unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(stats.dropped_versions, 1);
+        let out_path = &outputs[0];
 
-        let merged = SortedStringTable::new(&out_path).unwrap();
+        let merged = SortedStringTable::new(out_path).unwrap();
         let keys: Vec<_> = merged
             .iter()
             .map(|r| String::from_utf8_lossy(r.key).into_owned())
@@ -130,7 +268,7 @@ mod tests {
         println!("{:?}", keys);
         assert_eq!(keys, ["a", "b", "c"]);
 
-        let merged = SortedStringTable::new(&out_path).unwrap();
+        let merged = SortedStringTable::new(out_path).unwrap();
         let values: Vec<_> = merged
             .iter()
             .map(|r| String::from_utf8_lossy(r.value).into_owned())
@@ -139,4 +277,198 @@ mod tests {
         println!("{:?}", values);
         assert_eq!(values, ["new", "x", "y"]);
     }
+
+    #[test]
+    fn compact_keeps_newer_tombstone() {
+        let tmpdir = tempdir().unwrap();
+
+        let t1 = build_table(
+            &tmpdir,
+            "tbl1.sst",
+            &[(b"a".to_vec(), (Some(b"old".to_vec()), 1))],
+        );
+        let t2 = build_table(&tmpdir, "tbl2.sst", &[(b"a".to_vec(), (None, 2))]);
+        // a deeper table still holds an even older version of "a"
+        let older = build_table(
+            &tmpdir,
+            "older.sst",
+            &[(b"a".to_vec(), (Some(b"oldest".to_vec()), 0))],
+        );
+
+        let mut output = output(&tmpdir, u64::MAX);
+        output.other_tables.push(older);
+        let (outputs, stats) = compact(&[t1, t2], &output).unwrap();
+
+        let merged = SortedStringTable::new(&outputs[0]).unwrap();
+        let entry = merged
+            .get(b"a")
+            .unwrap()
+            .expect("tombstone should survive compaction");
+        assert!(entry.is_tombstone());
+        assert_eq!(entry.sequence_number, 2);
+        assert_eq!(stats.dropped_tombstones, 0);
+    }
+
+    #[test]
+    fn compact_drops_tombstone_without_older_data() {
+        let tmpdir = tempdir().unwrap();
+
+        let t1 = build_table(
+            &tmpdir,
+            "tbl1.sst",
+            &[
+                (b"a".to_vec(), (Some(b"old".to_vec()), 1)),
+                (b"b".to_vec(), (Some(b"x".to_vec()), 1)),
+            ],
+        );
+        let t2 = build_table(&tmpdir, "tbl2.sst", &[(b"a".to_vec(), (None, 2))]);
+        // holds "a" but only newer than the tombstone, so it cannot be resurrected
+        let newer = build_table(
+            &tmpdir,
+            "newer.sst",
+            &[(b"a".to_vec(), (Some(b"newer".to_vec()), 3))],
+        );
+
+        let mut output = output(&tmpdir, u64::MAX);
+        output.other_tables.push(newer);
+        let (outputs, stats) = compact(&[t1, t2], &output).unwrap();
+
+        let merged = SortedStringTable::new(&outputs[0]).unwrap();
+        assert_eq!(entries(&merged), [(b"b".to_vec(), Some(b"x".to_vec()), 1)]);
+        assert_eq!(
+            stats,
+            CompactionStats {
+                input_entries: 3,
+                output_entries: 1,
+                dropped_versions: 1,
+                dropped_tombstones: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn compact_keeps_versions_visible_to_snapshots() {
+        let tmpdir = tempdir().unwrap();
+
+        let t1 = build_table(
+            &tmpdir,
+            "tbl1.sst",
+            &[
+                (b"a".to_vec(), (Some(b"v1".to_vec()), 1)),
+                (b"b".to_vec(), (Some(b"v1".to_vec()), 2)),
+            ],
+        );
+        let t2 = build_table(
+            &tmpdir,
+            "tbl2.sst",
+            &[
+                (b"a".to_vec(), (Some(b"v2".to_vec()), 4)),
+                (b"b".to_vec(), (None, 5)),
+            ],
+        );
+        let t3 = build_table(
+            &tmpdir,
+            "tbl3.sst",
+            &[(b"a".to_vec(), (Some(b"v3".to_vec()), 6))],
+        );
+
+        // the snapshot at 3 sees a@1 and b@2, the latest state sees a@6 and the deletion of b
+        let mut output = output(&tmpdir, u64::MAX);
+        output.snapshots = vec![3];
+        let (outputs, stats) = compact(&[t1, t2, t3], &output).unwrap();
+
+        let merged = SortedStringTable::new(&outputs[0]).unwrap();
+        assert_eq!(
+            entries(&merged),
+            [
+                (b"a".to_vec(), Some(b"v3".to_vec()), 6),
+                (b"a".to_vec(), Some(b"v1".to_vec()), 1),
+                (b"b".to_vec(), None, 5),
+                (b"b".to_vec(), Some(b"v1".to_vec()), 2),
+            ]
+        );
+        assert_eq!(stats.dropped_versions, 1);
+        assert_eq!(stats.dropped_tombstones, 0);
+    }
+
+    #[test]
+    fn compact_splits_outputs_by_target_size() {
+        let tmpdir = tempdir().unwrap();
+
+        let entries: Vec<Entry> = (0..2000)
+            .map(|i| {
+                (
+                    format!("key{i:05}").into_bytes(),
+                    (Some(vec![b'v'; 100]), i as u64),
+                )
+            })
+            .collect();
+        let (even, odd): (Vec<_>, Vec<_>) = entries
+            .iter()
+            .cloned()
+            .partition(|(key, _)| key.last().is_some_and(|byte| byte % 2 == 0));
+        let t1 = build_table(&tmpdir, "even.sst", &even);
+        let t2 = build_table(&tmpdir, "odd.sst", &odd);
+
+        let (outputs, _) = compact(&[t1, t2], &output(&tmpdir, 32 * 1024)).unwrap();
+        assert!(outputs.len() > 1);
+
+        let tables: Vec<_> = outputs
+            .iter()
+            .map(|path| SortedStringTable::new(path).unwrap())
+            .collect();
+        for pair in tables.windows(2) {
+            assert!(pair[0].properties().max_key < pair[1].properties().min_key);
+        }
+
+        let keys: Vec<_> = tables
+            .iter()
+            .flat_map(|table| {
+                table
+                    .iter()
+                    .map(|entry| entry.key.to_vec())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let expected: Vec<_> = entries.into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn compact_keeps_versions_of_a_key_in_one_output() {
+        let tmpdir = tempdir().unwrap();
+        // a value per data block, so the output grows with every entry
+        let value = |version: u8| Some(vec![version; 5 * 1024]);
+
+        let t1 = build_table(
+            &tmpdir,
+            "tbl1.sst",
+            &[
+                (b"a".to_vec(), (value(1), 1)),
+                (b"b".to_vec(), (value(1), 2)),
+            ],
+        );
+        let t2 = build_table(&tmpdir, "tbl2.sst", &[(b"a".to_vec(), (value(2), 4))]);
+        let t3 = build_table(&tmpdir, "tbl3.sst", &[(b"a".to_vec(), (value(3), 6))]);
+
+        // the snapshots keep all versions of "a", each of them fills an output on its own
+        let mut output = output(&tmpdir, 1);
+        output.snapshots = vec![3, 5];
+        let (outputs, _) = compact(&[t1, t2, t3], &output).unwrap();
+
+        let tables: Vec<_> = outputs
+            .iter()
+            .map(|path| SortedStringTable::new(path).unwrap())
+            .collect();
+        assert_eq!(tables.len(), 2);
+        assert_eq!(
+            entries(&tables[0]),
+            [
+                (b"a".to_vec(), value(3), 6),
+                (b"a".to_vec(), value(2), 4),
+                (b"a".to_vec(), value(1), 1),
+            ]
+        );
+        assert_eq!(entries(&tables[1]), [(b"b".to_vec(), value(1), 2)]);
+    }
 }
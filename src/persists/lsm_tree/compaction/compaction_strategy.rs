@@ -0,0 +1,11 @@
+use crate::persists::lsm_tree::{compaction::compaction::CompactionTask, lsm_manager::TreeLevel};
+
+/// Decides which tables are merged next. [`LsmManager`](crate::persists::lsm_tree::lsm_manager::LsmManager)
+/// consults it after every change to the tree until it returns `None`.
+pub trait CompactionStrategy: Send + Sync {
+    /// Picks the input tables and output level of the next compaction.
+    fn pick(&self, levels: &[TreeLevel]) -> Option<CompactionTask>;
+
+    /// Compaction outputs are split into tables of roughly this size.
+    fn target_file_size(&self) -> u64;
+}
@@ -0,0 +1,241 @@
+use std::sync::Arc;
+
+use tokio::sync::{RwLock, mpsc};
+
+use crate::persists::lsm_tree::{
+    compaction::compaction::{CompactionStats, compact},
+    lsm_manager::LsmManager,
+    sorted_string_table::{sorted_string_table::SortedStringTable, table_error::TableError},
+};
+
+pub enum CompactionCommand {
+    /// Sent after the tree changed, runs compactions until no level exceeds its limits.
+    MaybeCompact,
+}
+
+pub struct CompactionWorker {
+    lsm_manager: Arc<RwLock<LsmManager>>,
+}
+
+impl CompactionWorker {
+    pub fn new(lsm_manager: Arc<RwLock<LsmManager>>) -> Self {
+        Self { lsm_manager }
+    }
+
+    pub async fn run(&self, mut rx: mpsc::Receiver<CompactionCommand>) {
+        while let Some(cmd) = rx.recv().await {
+            match cmd {
+                CompactionCommand::MaybeCompact => {
+                    if let Err(e) = self.compact_until_balanced().await {
+                        eprintln!("compaction failed: {e}");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs compactions until the tree is balanced, returns the stats of every run.
+    /// The merge runs without holding the lock, readers keep using the input tables
+    /// until the outputs are swapped in.
+    pub async fn compact_until_balanced(&self) -> Result<Vec<CompactionStats>, TableError> {
+        let mut runs = Vec::new();
+
+        loop {
+            let Some((task, output)) = self.lsm_manager.read().await.pick_compaction() else {
+                return Ok(runs);
+            };
+
+            let (task, result) = tokio::task::spawn_blocking(move || {
+                let result = compact(&task.inputs, &output).and_then(|(paths, stats)| {
+                    let tables = paths
+                        .iter()
+                        .map(|path| SortedStringTable::new(path))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    Ok((tables, stats))
+                });
+                (task, result)
+            })
+            .await
+            .map_err(|e| TableError::Io(std::io::Error::other(e)))?;
+            let (outputs, stats) = result?;
+
+            self.lsm_manager
+                .write()
+                .await
+                .apply_compaction(&task, outputs)?;
+
+            // open mappings stay valid after the file is unlinked
+            for table in &task.inputs {
+                if let Err(e) = std::fs::remove_file(table.path()) {
+                    eprintln!(
+                        "failed to remove compacted table {}: {e}",
+                        table.path().display()
+                    );
+                }
+            }
+            runs.push(stats);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persists::{
+        lsm_tree::{
+            compaction::{leveled::LeveledCompaction, size_tiered::SizeTieredCompaction},
+            sorted_string_table::sst_writer::SSTableWriter,
+        },
+        store_options::StoreOptions,
+    };
+    use tempfile::tempdir;
+
+    async fn write_table(
+        lsm_manager: &RwLock<LsmManager>,
+        start: u64,
+        count: u64,
+    ) -> std::path::PathBuf {
+        let path = {
+            let lsm_manager = lsm_manager.read().await;
+            lsm_manager.file_numbers().new_table_path(lsm_manager.dir())
+        };
+        let entries = (start..start + count)
+            .map(|i| {
+                (
+                    format!("key{:05}", i % 500).into_bytes(),
+                    (Some(format!("value{i}").into_bytes()), i),
+                )
+            })
+            .collect::<std::collections::BTreeMap<_, _>>()
+            .into_iter()
+            .collect();
+        SSTableWriter::write_to_file(&path, entries, 0).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn level0_is_compacted_into_level1() {
+        let tmpdir = tempdir().unwrap();
+        let strategy = LeveledCompaction {
+            level0_file_trigger: 3,
+            target_file_size: 4 * 1024,
+            ..LeveledCompaction::default()
+        };
+        let lsm_manager = Arc::new(RwLock::new(
+            LsmManager::open(&StoreOptions::new(tmpdir.path()), Box::new(strategy)).unwrap(),
+        ));
+        let worker = CompactionWorker::new(Arc::clone(&lsm_manager));
+
+        let mut inputs = Vec::new();
+        for round in 0..3 {
+            let path = write_table(&lsm_manager, round * 300, 300).await;
+            lsm_manager.write().await.add_table(&path).unwrap();
+            inputs.push(path);
+        }
+
+        let runs = worker.compact_until_balanced().await.unwrap();
+        assert_eq!(runs.len(), 1);
+        // 400 of the 500 keys were written in two rounds
+        assert_eq!(runs[0].input_entries, 900);
+        assert_eq!(runs[0].dropped_versions, 400);
+
+        let lsm_manager = lsm_manager.read().await;
+        let counts = lsm_manager.level_table_counts();
+        assert_eq!(counts[0], 0);
+        assert!(counts[1] > 1, "outputs should be split: {counts:?}");
+        assert!(inputs.iter().all(|path| !path.exists()));
+
+        // key00100 was written in rounds 0 (seq 100) and 1 (seq 600)
+        let entry = lsm_manager.get_value(b"key00100").unwrap().unwrap();
+        assert_eq!(entry.sequence_number, 600);
+        assert_eq!(entry.value, b"value600");
+        for i in 0..500 {
+            let key = format!("key{i:05}");
+            assert!(lsm_manager.get_value(key.as_bytes()).unwrap().is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_level_is_pushed_down() {
+        let tmpdir = tempdir().unwrap();
+        let strategy = LeveledCompaction {
+            level0_file_trigger: 1,
+            level1_target_size: 1,
+            ..LeveledCompaction::default()
+        };
+        let lsm_manager = Arc::new(RwLock::new(
+            LsmManager::open(&StoreOptions::new(tmpdir.path()), Box::new(strategy)).unwrap(),
+        ));
+        let worker = CompactionWorker::new(Arc::clone(&lsm_manager));
+
+        let path = write_table(&lsm_manager, 0, 100).await;
+        lsm_manager.write().await.add_table(&path).unwrap();
+
+        // L0 -> L1, then L1 is over its target and moves on until the last level
+        worker.compact_until_balanced().await.unwrap();
+
+        let lsm_manager = lsm_manager.read().await;
+        let counts = lsm_manager.level_table_counts();
+        assert_eq!(counts.iter().sum::<usize>(), 1);
+        assert_eq!(counts.last(), Some(&1));
+        assert!(lsm_manager.get_value(b"key00042").unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn open_snapshot_keeps_older_versions() {
+        let tmpdir = tempdir().unwrap();
+        let strategy = LeveledCompaction {
+            level0_file_trigger: 2,
+            ..LeveledCompaction::default()
+        };
+        let lsm_manager = Arc::new(RwLock::new(
+            LsmManager::open(&StoreOptions::new(tmpdir.path()), Box::new(strategy)).unwrap(),
+        ));
+        let worker = CompactionWorker::new(Arc::clone(&lsm_manager));
+
+        // key00000..key00099 at seq 0..99, then again at seq 500..599
+        for start in [0, 500] {
+            let path = write_table(&lsm_manager, start, 100).await;
+            lsm_manager.write().await.add_table(&path).unwrap();
+        }
+        lsm_manager.write().await.register_snapshot(250);
+
+        let runs = worker.compact_until_balanced().await.unwrap();
+        assert_eq!(runs[0].dropped_versions, 0);
+
+        lsm_manager.write().await.release_snapshot(250);
+        for start in [1000, 1001] {
+            let path = write_table(&lsm_manager, start, 1).await;
+            lsm_manager.write().await.add_table(&path).unwrap();
+        }
+
+        // the new tables overwrite key00000 and key00001, so those lose two versions each
+        let runs = worker.compact_until_balanced().await.unwrap();
+        assert_eq!(runs[0].dropped_versions, 102);
+    }
+
+    #[tokio::test]
+    async fn size_tiered_merges_similar_tables_within_level0() {
+        let tmpdir = tempdir().unwrap();
+        let strategy = SizeTieredCompaction {
+            min_threshold: 4,
+            ..SizeTieredCompaction::default()
+        };
+        let lsm_manager = Arc::new(RwLock::new(
+            LsmManager::open(&StoreOptions::new(tmpdir.path()), Box::new(strategy)).unwrap(),
+        ));
+        let worker = CompactionWorker::new(Arc::clone(&lsm_manager));
+
+        for round in 0..4 {
+            let path = write_table(&lsm_manager, round * 200, 200).await;
+            lsm_manager.write().await.add_table(&path).unwrap();
+        }
+
+        assert_eq!(worker.compact_until_balanced().await.unwrap().len(), 1);
+
+        let lsm_manager = lsm_manager.read().await;
+        assert_eq!(lsm_manager.level_table_counts(), vec![1]);
+        let entry = lsm_manager.get_value(b"key00100").unwrap().unwrap();
+        assert_eq!(entry.sequence_number, 600);
+    }
+}
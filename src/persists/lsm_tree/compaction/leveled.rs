@@ -0,0 +1,201 @@
+use std::sync::Arc;
+
+use crate::persists::lsm_tree::{
+    compaction::{compaction::CompactionTask, compaction_strategy::CompactionStrategy},
+    lsm_manager::TreeLevel,
+    sorted_string_table::sorted_string_table::SortedStringTable,
+};
+
+/// Leveled compaction:
+/// - once L0 reaches the file trigger, all L0 tables and the overlapping L1 tables are merged into L1
+/// - otherwise the first level over its size target pushes one table into the next level
+#[derive(Debug, Clone)]
+pub struct LeveledCompaction {
+    /// Number of L0 tables that triggers a compaction into L1.
+    pub level0_file_trigger: usize,
+    /// Size target of L1 in bytes.
+    pub level1_target_size: u64,
+    /// Each level below L1 may hold this many times the bytes of the level above.
+    pub level_size_multiplier: u64,
+    /// Tables in the last level are never compacted further.
+    pub max_levels: usize,
+    /// Compaction outputs are split into tables of roughly this size.
+    pub target_file_size: u64,
+}
+
+impl Default for LeveledCompaction {
+    fn default() -> Self {
+        Self {
+            level0_file_trigger: 4,
+            level1_target_size: 10 * 1024 * 1024,
+            level_size_multiplier: 10,
+            max_levels: 7,
+            target_file_size: 2 * 1024 * 1024,
+        }
+    }
+}
+
+impl LeveledCompaction {
+    /// Size target of `level`, L0 is bounded by file count instead.
+    pub fn target_size(&self, level: usize) -> u64 {
+        let exponent = level.saturating_sub(1) as u32;
+        self.level1_target_size
+            .saturating_mul(self.level_size_multiplier.saturating_pow(exponent))
+    }
+}
+
+impl CompactionStrategy for LeveledCompaction {
+    fn pick(&self, levels: &[TreeLevel]) -> Option<CompactionTask> {
+        if let Some(level0) = levels.first()
+            && level0.tables().len() >= self.level0_file_trigger.max(1)
+        {
+            let mut inputs = level0.tables().to_vec();
+            if let Some((min_key, max_key)) = key_range(&inputs) {
+                inputs.extend(overlapping(levels.get(1), &min_key, &max_key));
+            }
+            return Some(CompactionTask {
+                inputs,
+                output_level: 1,
+            });
+        }
+
+        (1..levels.len())
+            .take_while(|level| level + 1 < self.max_levels)
+            .find(|&level| levels[level].size() > self.target_size(level))
+            .and_then(|level| {
+                let table = levels[level].tables().first()?;
+                let properties = table.properties();
+
+                let mut inputs = vec![Arc::clone(table)];
+                inputs.extend(overlapping(
+                    levels.get(level + 1),
+                    &properties.min_key,
+                    &properties.max_key,
+                ));
+                Some(CompactionTask {
+                    inputs,
+                    output_level: level + 1,
+                })
+            })
+    }
+
+    fn target_file_size(&self) -> u64 {
+        self.target_file_size
+    }
+}
+
+/// Smallest and largest key over all non-empty `tables`.
+fn key_range(tables: &[Arc<SortedStringTable>]) -> Option<(Vec<u8>, Vec<u8>)> {
+    let non_empty = tables
+        .iter()
+        .map(|table| table.properties())
+        .filter(|properties| properties.entry_count > 0);
+
+    let min_key = non_empty.clone().map(|p| &p.min_key).min()?.clone();
+    let max_key = non_empty.map(|p| &p.max_key).max()?.clone();
+    Some((min_key, max_key))
+}
+
+fn overlapping(
+    level: Option<&TreeLevel>,
+    min_key: &[u8],
+    max_key: &[u8],
+) -> Vec<Arc<SortedStringTable>> {
+    level
+        .into_iter()
+        .flat_map(TreeLevel::tables)
+        .filter(|table| table.overlaps(min_key, max_key))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persists::lsm_tree::sorted_string_table::sst_writer::SSTableWriter;
+    use tempfile::tempdir;
+
+    fn build_table(dir: &tempfile::TempDir, keys: &[&str]) -> Arc<SortedStringTable> {
+        let path = dir.path().join(format!("{}.sst", uuid::Uuid::new_v4()));
+        let entries = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (key.as_bytes().to_vec(), (Some(vec![b'v'; 64]), i as u64)))
+            .collect();
+        SSTableWriter::write_to_file(&path, entries, 0).unwrap();
+        Arc::new(SortedStringTable::new(&path).unwrap())
+    }
+
+    fn level(tables: Vec<Arc<SortedStringTable>>) -> TreeLevel {
+        let mut level = TreeLevel::default();
+        for table in tables {
+            level.add(table);
+        }
+        level
+    }
+
+    #[test]
+    fn level0_below_trigger_is_left_alone() {
+        let tmpdir = tempdir().unwrap();
+        let levels = vec![level(vec![
+            build_table(&tmpdir, &["a", "c"]),
+            build_table(&tmpdir, &["b", "d"]),
+        ])];
+
+        assert!(LeveledCompaction::default().pick(&levels).is_none());
+    }
+
+    #[test]
+    fn level0_trigger_includes_overlapping_level1_tables() {
+        let tmpdir = tempdir().unwrap();
+        let l1_overlapping = build_table(&tmpdir, &["c", "e"]);
+        let l1_disjoint = build_table(&tmpdir, &["x", "z"]);
+        let levels = vec![
+            level(vec![
+                build_table(&tmpdir, &["a", "c"]),
+                build_table(&tmpdir, &["b", "d"]),
+            ]),
+            level(vec![l1_overlapping.clone(), l1_disjoint.clone()]),
+        ];
+        let strategy = LeveledCompaction {
+            level0_file_trigger: 2,
+            ..LeveledCompaction::default()
+        };
+
+        let task = strategy.pick(&levels).unwrap();
+        assert_eq!(task.output_level, 1);
+        assert_eq!(task.inputs.len(), 3);
+        assert!(task.inputs.iter().any(|t| Arc::ptr_eq(t, &l1_overlapping)));
+        assert!(!task.inputs.iter().any(|t| Arc::ptr_eq(t, &l1_disjoint)));
+    }
+
+    #[test]
+    fn oversized_level_pushes_one_table_down() {
+        let tmpdir = tempdir().unwrap();
+        let first = build_table(&tmpdir, &["a", "b"]);
+        let levels = vec![
+            level(Vec::new()),
+            level(vec![first.clone(), build_table(&tmpdir, &["m", "n"])]),
+            level(vec![build_table(&tmpdir, &["b", "c"])]),
+        ];
+        let strategy = LeveledCompaction {
+            level1_target_size: 1,
+            ..LeveledCompaction::default()
+        };
+
+        let task = strategy.pick(&levels).unwrap();
+        assert_eq!(task.output_level, 2);
+        assert_eq!(task.inputs.len(), 2);
+        assert!(Arc::ptr_eq(&task.inputs[0], &first));
+    }
+
+    #[test]
+    fn level_targets_grow_by_multiplier() {
+        let strategy = LeveledCompaction::default();
+        assert_eq!(strategy.target_size(1), strategy.level1_target_size);
+        assert_eq!(
+            strategy.target_size(3),
+            strategy.level1_target_size * strategy.level_size_multiplier.pow(2)
+        );
+    }
+}
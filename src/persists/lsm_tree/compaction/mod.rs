@@ -1 +1,6 @@
-mod compaction;
+#[allow(clippy::module_inception)]
+pub mod compaction;
+pub mod compaction_strategy;
+pub mod compaction_worker;
+pub mod leveled;
+pub mod size_tiered;
@@ -0,0 +1,216 @@
+use std::sync::Arc;
+
+use crate::persists::lsm_tree::{
+    compaction::{compaction::CompactionTask, compaction_strategy::CompactionStrategy},
+    lsm_manager::TreeLevel,
+    sorted_string_table::{sorted_string_table::SortedStringTable, sst_writer::MAX_TABLE_SIZE},
+};
+
+/// Size-tiered compaction: tables stay in L0 and similarly sized tables are grouped into
+/// buckets. Once a bucket holds enough tables they are merged into one larger table,
+/// so every entry is rewritten only about once per size tier.
+#[derive(Debug, Clone)]
+pub struct SizeTieredCompaction {
+    /// Minimum number of tables in a bucket before it is compacted.
+    pub min_threshold: usize,
+    /// Maximum number of tables merged in one compaction.
+    pub max_threshold: usize,
+    /// A table joins a bucket if its size is within `bucket_low..=bucket_high` times the bucket average.
+    pub bucket_low: f64,
+    pub bucket_high: f64,
+    /// Tables below this size all share one bucket, no matter how different their sizes are.
+    pub min_table_size: u64,
+    /// Merges are split into tables of this size. Tables of at least half of it are not
+    /// merged again, their merge would not reduce the number of tables.
+    pub max_table_size: u64,
+}
+
+impl Default for SizeTieredCompaction {
+    fn default() -> Self {
+        Self {
+            min_threshold: 4,
+            max_threshold: 32,
+            bucket_low: 0.5,
+            bucket_high: 1.5,
+            min_table_size: 1024 * 1024,
+            max_table_size: MAX_TABLE_SIZE,
+        }
+    }
+}
+
+struct Bucket {
+    tables: Vec<Arc<SortedStringTable>>,
+    total_size: u64,
+}
+
+impl Bucket {
+    fn average_size(&self) -> u64 {
+        self.total_size / self.tables.len() as u64
+    }
+}
+
+impl SizeTieredCompaction {
+    /// Groups `tables` into buckets of similar size, smallest tables first.
+    /// Tables near `max_table_size` are left out.
+    fn buckets(&self, tables: &[Arc<SortedStringTable>]) -> Vec<Bucket> {
+        let mut sorted: Vec<_> = tables
+            .iter()
+            .filter(|table| table.file_size() < self.max_table_size / 2)
+            .cloned()
+            .collect();
+        sorted.sort_by_key(|table| table.file_size());
+
+        let mut buckets: Vec<Bucket> = Vec::new();
+        for table in sorted {
+            let size = table.file_size();
+            let fits = buckets.last().is_some_and(|bucket| {
+                let average = bucket.average_size();
+                (size < self.min_table_size && average < self.min_table_size)
+                    || (size as f64 >= average as f64 * self.bucket_low
+                        && size as f64 <= average as f64 * self.bucket_high)
+            });
+
+            match buckets.last_mut() {
+                Some(bucket) if fits => {
+                    bucket.total_size += size;
+                    bucket.tables.push(table);
+                }
+                _ => buckets.push(Bucket {
+                    tables: vec![table],
+                    total_size: size,
+                }),
+            }
+        }
+        buckets
+    }
+}
+
+impl CompactionStrategy for SizeTieredCompaction {
+    /// Merges the bucket of the smallest tables that reached `min_threshold`.
+    fn pick(&self, levels: &[TreeLevel]) -> Option<CompactionTask> {
+        let level0 = levels.first()?;
+
+        let mut bucket = self
+            .buckets(level0.tables())
+            .into_iter()
+            .find(|bucket| bucket.tables.len() >= self.min_threshold.max(2))?;
+        bucket.tables.truncate(self.max_threshold.max(2));
+
+        Some(CompactionTask {
+            inputs: bucket.tables,
+            output_level: 0,
+        })
+    }
+
+    /// Each merge produces a single table of the next tier, unless it outgrows a table.
+    fn target_file_size(&self) -> u64 {
+        self.max_table_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persists::lsm_tree::sorted_string_table::sst_writer::SSTableWriter;
+    use tempfile::tempdir;
+
+    fn build_table(dir: &tempfile::TempDir, entry_count: usize) -> Arc<SortedStringTable> {
+        let path = dir.path().join(format!("L0_{}.sst", uuid::Uuid::new_v4()));
+        let entries = (0..entry_count)
+            .map(|i| {
+                (
+                    format!("key{i:06}").into_bytes(),
+                    (Some(vec![b'v'; 100]), i as u64),
+                )
+            })
+            .collect();
+        SSTableWriter::write_to_file(&path, entries, 0).unwrap();
+        Arc::new(SortedStringTable::new(&path).unwrap())
+    }
+
+    fn level0(tables: &[Arc<SortedStringTable>]) -> Vec<TreeLevel> {
+        let mut level = TreeLevel::new(0);
+        for table in tables {
+            level.add(Arc::clone(table));
+        }
+        vec![level]
+    }
+
+    fn strategy() -> SizeTieredCompaction {
+        SizeTieredCompaction {
+            min_threshold: 3,
+            min_table_size: 0,
+            ..SizeTieredCompaction::default()
+        }
+    }
+
+    #[test]
+    fn similar_sizes_share_a_bucket() {
+        let tmpdir = tempdir().unwrap();
+        let small: Vec<_> = (0..3).map(|_| build_table(&tmpdir, 100)).collect();
+        let large: Vec<_> = (0..2).map(|_| build_table(&tmpdir, 2000)).collect();
+
+        let all: Vec<_> = small.iter().chain(&large).cloned().collect();
+        let buckets = strategy().buckets(&all);
+
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].tables.len(), 3);
+        assert_eq!(buckets[1].tables.len(), 2);
+    }
+
+    #[test]
+    fn full_bucket_is_picked_without_other_tiers() {
+        let tmpdir = tempdir().unwrap();
+        let small: Vec<_> = (0..3).map(|_| build_table(&tmpdir, 100)).collect();
+        let large = build_table(&tmpdir, 2000);
+
+        let mut tables = small.clone();
+        tables.push(Arc::clone(&large));
+        let task = strategy().pick(&level0(&tables)).unwrap();
+
+        assert_eq!(task.output_level, 0);
+        assert_eq!(task.inputs.len(), 3);
+        assert!(!task.inputs.iter().any(|table| Arc::ptr_eq(table, &large)));
+    }
+
+    #[test]
+    fn nothing_is_picked_below_threshold() {
+        let tmpdir = tempdir().unwrap();
+        let tables = vec![build_table(&tmpdir, 100), build_table(&tmpdir, 100)];
+
+        assert!(strategy().pick(&level0(&tables)).is_none());
+    }
+
+    #[test]
+    fn small_tables_are_grouped_regardless_of_size() {
+        let tmpdir = tempdir().unwrap();
+        let tables = vec![
+            build_table(&tmpdir, 1),
+            build_table(&tmpdir, 50),
+            build_table(&tmpdir, 400),
+        ];
+        let strategy = SizeTieredCompaction {
+            min_threshold: 3,
+            ..SizeTieredCompaction::default()
+        };
+
+        assert_eq!(strategy.pick(&level0(&tables)).unwrap().inputs.len(), 3);
+    }
+
+    #[test]
+    fn tables_near_the_maximum_size_are_not_merged() {
+        let tmpdir = tempdir().unwrap();
+        let tables: Vec<_> = (0..3).map(|_| build_table(&tmpdir, 100)).collect();
+        let strategy = SizeTieredCompaction {
+            max_table_size: 2 * tables[0].file_size(),
+            ..strategy()
+        };
+
+        assert!(strategy.pick(&level0(&tables)).is_none());
+        let strategy = SizeTieredCompaction {
+            max_table_size: 2 * tables[0].file_size() + 2,
+            ..strategy
+        };
+        assert_eq!(strategy.pick(&level0(&tables)).unwrap().inputs.len(), 3);
+    }
+}
@@ -1,84 +1,388 @@
-use std::{error::Error, fs, path::Path};
+use std::{
+    fs,
+    ops::Bound,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use crate::persists::lsm_tree::sorted_string_table::{
-    sorted_string_table::SortedStringTable, table_result::TableResult,
+use crate::persists::{
+    lsm_tree::{
+        compaction::{
+            compaction::{CompactionOutput, CompactionTask},
+            compaction_strategy::CompactionStrategy,
+        },
+        manifest::{FileNumbers, Manifest, VersionEdit, parse_table_file_name, table_file_name},
+        merging_iterator::ScanSource,
+        snapshot_list::SnapshotList,
+        sorted_string_table::{
+            bloom_filter::BloomFilterStats,
+            sorted_string_table::SortedStringTable,
+            sst_writer::{MAX_TABLE_SIZE, TableOptions},
+            table_error::TableError,
+            table_result::TableResult,
+        },
+    },
+    store_options::StoreOptions,
 };
 
+/// Owns the tables of the tree. Every change to the set of tables is logged to the
+/// MANIFEST before it becomes visible, so [`LsmManager::open`] restores the
+/// exact shape of the tree after a restart.
 pub struct LsmManager {
+    /// Directory holding the table files.
+    dir: PathBuf,
+    manifest: Manifest,
+    file_numbers: Arc<FileNumbers>,
     tree: Vec<TreeLevel>,
+    compaction_strategy: Box<dyn CompactionStrategy>,
+    /// Options of flushed and compacted tables.
+    table_options: TableOptions,
+    /// Open snapshots, compaction keeps the versions they can see.
+    snapshots: Arc<SnapshotList>,
+    last_sequence_number: Option<u64>,
 }
 
 impl LsmManager {
-    pub fn new() -> Self {
-        LsmManager { tree: Vec::new() }
-    }
-
-    pub async fn initialize(&mut self) -> Result<(), Box<dyn Error>> {
-        //load config
-        // load tables
+    /// Replays the MANIFEST of `options` and opens every table it lists.
+    /// Table files that are not listed, left behind by a crash during a flush or compaction,
+    /// are deleted once the listed tables are open. Their data is still in the WAL or in the
+    /// compaction inputs.
+    pub fn open(
+        options: &StoreOptions,
+        compaction_strategy: Box<dyn CompactionStrategy>,
+    ) -> Result<Self, TableError> {
+        options.create_dirs()?;
+        let dir = options.sst_dir();
+        let manifest_path = options.manifest_path();
+        let mut state = Manifest::recover(&manifest_path)?;
 
-        let path = "./some_folder"; // Path to the folder
+        let mut stray_files = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "sst") {
+                continue;
+            }
+            let file_number = parse_table_file_name(&path);
+            if file_number.is_some_and(|number| state.tables.contains_key(&number)) {
+                continue;
+            }
+            // never hand out a number that is already taken on disk
+            if let Some(number) = file_number {
+                state.next_file_number = state.next_file_number.max(number + 1);
+            }
+            eprintln!(
+                "removing stray table {}, it is not listed in the manifest",
+                path.display()
+            );
+            stray_files.push(path);
+        }
 
-        if Path::new(path).is_dir() {
-            for entry in fs::read_dir(path)? {
-                let entry = entry?;
-                let path = entry.path();
-                println!("{}", path.display());
+        let mut lsm_manager = LsmManager {
+            dir: dir.clone(),
+            manifest: Manifest::create(&manifest_path, &state.snapshot())?,
+            file_numbers: Arc::new(FileNumbers::new(state.next_file_number)),
+            tree: Vec::new(),
+            compaction_strategy,
+            table_options: options.table_options(),
+            snapshots: Arc::default(),
+            last_sequence_number: state.last_sequence_number,
+        };
 
-                match TreeLevel::new(&path) {
-                    Ok(tree) => self.tree.push(tree),
-                    Err(_) => todo!(),
+        for (file_number, level) in &state.tables {
+            let path = dir.join(table_file_name(*file_number));
+            let table = SortedStringTable::new(&path).map_err(|e| match e {
+                TableError::Io(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    TableError::Io(std::io::Error::new(
+                        e.kind(),
+                        format!("table {} listed in the manifest is missing", path.display()),
+                    ))
                 }
+                e => e,
+            })?;
+            lsm_manager.level_mut(*level).add(Arc::new(table));
+        }
+
+        for path in stray_files {
+            if let Err(e) = fs::remove_file(&path) {
+                eprintln!("failed to remove stray table {}: {e}", path.display());
             }
         }
 
+        Ok(lsm_manager)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn file_numbers(&self) -> Arc<FileNumbers> {
+        Arc::clone(&self.file_numbers)
+    }
+
+    pub fn table_options(&self) -> TableOptions {
+        self.table_options.clone()
+    }
+
+    pub fn snapshots(&self) -> Arc<SnapshotList> {
+        Arc::clone(&self.snapshots)
+    }
+
+    /// Adds a flushed table to L0, the table has to be named by [`FileNumbers::new_table_path`].
+    pub fn add_table(&mut self, path: &Path) -> Result<(), TableError> {
+        let file_number = table_file_number(path)?;
+        let table = SortedStringTable::new(path)?;
+
+        let properties = table.properties();
+        let table_sequence_number =
+            (properties.entry_count > 0).then_some(properties.max_sequence_number);
+        let last_sequence_number = self.last_sequence_number.max(table_sequence_number);
+
+        self.manifest.append(&VersionEdit {
+            added_tables: vec![(0, file_number)],
+            last_sequence_number,
+            next_file_number: Some(self.file_numbers.peek()),
+            ..VersionEdit::default()
+        })?;
+
+        self.last_sequence_number = last_sequence_number;
+        self.level_mut(0).add(Arc::new(table));
         Ok(())
     }
-    pub fn add_table(&mut self, path: &Path) {
-        //TODO handle error
-        let table = SortedStringTable::new(path).unwrap();
-        self.tree[0].add(table);
+
+    fn level_mut(&mut self, level: usize) -> &mut TreeLevel {
+        while self.tree.len() <= level {
+            let next = self.tree.len();
+            self.tree.push(TreeLevel::new(next));
+        }
+        &mut self.tree[level]
+    }
+
+    /// Returns the newest entry of `key`, tombstones included so callers can stop the lookup.
+    /// Every level holds only newer versions than the levels below it, so the first level
+    /// with a version decides. A corrupted block in a probed table fails the whole lookup.
+    #[allow(dead_code)]
+    pub fn get_value(&self, key: &[u8]) -> Result<Option<TableResult<'_>>, TableError> {
+        self.get_value_at(key, u64::MAX)
     }
 
-    pub fn get_value(&self, key: &[u8]) -> Option<TableResult<'_>> {
+    /// Like [`LsmManager::get_value`], but ignores versions newer than `snapshot`.
+    pub fn get_value_at(
+        &self,
+        key: &[u8],
+        snapshot: u64,
+    ) -> Result<Option<TableResult<'_>>, TableError> {
         for tree_level in &self.tree {
-            if let Some(table_result) = tree_level.get_value(key) {
-                return Some(table_result);
-            };
+            if let Some(result) = tree_level.get_value_at(key, snapshot)? {
+                return Ok(Some(result));
+            }
         }
-        None
+        Ok(None)
+    }
+
+    /// Iterators over every table that may hold keys in `range`, each positioned at the
+    /// start of the range. With a `prefix`, tables whose prefix filter rejects it are skipped.
+    pub fn scan_sources(
+        &self,
+        range: (Bound<&[u8]>, Bound<&[u8]>),
+        prefix: Option<&[u8]>,
+    ) -> Vec<ScanSource<'_>> {
+        let start = match range.0 {
+            Bound::Included(start) | Bound::Excluded(start) => start,
+            Bound::Unbounded => &[],
+        };
+        self.tree
+            .iter()
+            .flat_map(|tree_level| &tree_level.tables)
+            .filter(|table| {
+                let properties = table.properties();
+                let before_end = match range.1 {
+                    Bound::Included(end) => properties.min_key.as_slice() <= end,
+                    Bound::Excluded(end) => properties.min_key.as_slice() < end,
+                    Bound::Unbounded => true,
+                };
+                properties.entry_count > 0
+                    && properties.max_key.as_slice() >= start
+                    && before_end
+                    && prefix.is_none_or(|prefix| table.may_contain_prefix(prefix))
+            })
+            .map(|table| ScanSource::Table(table.iter_from(start)))
+            .collect()
+    }
+
+    /// Highest sequence number persisted in any table, read from the table properties
+    /// and the manifest, which remembers it even after compaction dropped the entry.
+    pub fn max_sequence_number(&self) -> Option<u64> {
+        self.tree
+            .iter()
+            .flat_map(|tree_level| &tree_level.tables)
+            .filter(|table| table.properties().entry_count > 0)
+            .map(|table| table.properties().max_sequence_number)
+            .max()
+            .max(self.last_sequence_number)
     }
+
+    /// Bloom filter counters summed over every table in the tree.
+    pub fn filter_stats(&self) -> BloomFilterStats {
+        self.tree
+            .iter()
+            .flat_map(|tree_level| &tree_level.tables)
+            .map(|table| table.filter_stats())
+            .fold(BloomFilterStats::default(), |total, stats| total + stats)
+    }
+
+    /// Number of tables per level, starting with L0.
+    pub fn level_table_counts(&self) -> Vec<usize> {
+        self.tree.iter().map(|level| level.tables.len()).collect()
+    }
+
+    #[allow(dead_code)]
+    pub fn register_snapshot(&mut self, sequence_number: u64) {
+        self.snapshots.register(sequence_number);
+    }
+
+    #[allow(dead_code)]
+    pub fn release_snapshot(&mut self, sequence_number: u64) {
+        self.snapshots.release(sequence_number);
+    }
+
+    /// Next compaction to run, or `None` if every level is within its limits.
+    pub fn pick_compaction(&self) -> Option<(CompactionTask, CompactionOutput)> {
+        let task = self.compaction_strategy.pick(&self.tree)?;
+        let other_tables = self
+            .tree
+            .iter()
+            .flat_map(TreeLevel::tables)
+            .filter(|table| !task.inputs.iter().any(|input| Arc::ptr_eq(input, table)))
+            .cloned()
+            .collect();
+
+        let output = CompactionOutput {
+            dir: self.dir.clone(),
+            file_numbers: self.file_numbers(),
+            level: task.output_level,
+            target_file_size: self
+                .compaction_strategy
+                .target_file_size()
+                .min(MAX_TABLE_SIZE),
+            table_options: self.table_options(),
+            snapshots: self.snapshots.sequence_numbers(),
+            other_tables,
+        };
+        Some((task, output))
+    }
+
+    /// Replaces the inputs of `task` with `outputs` in a single step,
+    /// logged to the manifest as one edit.
+    pub fn apply_compaction(
+        &mut self,
+        task: &CompactionTask,
+        outputs: Vec<SortedStringTable>,
+    ) -> Result<(), TableError> {
+        let mut edit = VersionEdit {
+            next_file_number: Some(self.file_numbers.peek()),
+            ..VersionEdit::default()
+        };
+        for (level, tree_level) in self.tree.iter().enumerate() {
+            for table in &tree_level.tables {
+                if task.inputs.iter().any(|input| Arc::ptr_eq(input, table)) {
+                    edit.removed_tables
+                        .push((level, table_file_number(table.path())?));
+                }
+            }
+        }
+        for table in &outputs {
+            edit.added_tables
+                .push((task.output_level, table_file_number(table.path())?));
+        }
+        self.manifest.append(&edit)?;
+
+        for tree_level in &mut self.tree {
+            tree_level
+                .tables
+                .retain(|table| !task.inputs.iter().any(|input| Arc::ptr_eq(input, table)));
+        }
+
+        let output_level = self.level_mut(task.output_level);
+        for table in outputs {
+            output_level.add(Arc::new(table));
+        }
+        Ok(())
+    }
+}
+
+fn table_file_number(path: &Path) -> Result<u64, TableError> {
+    parse_table_file_name(path).ok_or_else(|| {
+        TableError::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} is not named after a file number", path.display()),
+        ))
+    })
 }
 
-struct TreeLevel {
-    tables: Vec<SortedStringTable>,
+/// Tables of one level. L0 keeps tables in flush order and they may overlap,
+/// deeper levels are sorted by key and hold non-overlapping tables.
+#[derive(Default)]
+pub struct TreeLevel {
+    tables: Vec<Arc<SortedStringTable>>,
+    non_overlapping: bool,
 }
 
 impl TreeLevel {
-    pub fn new(path: &Path) -> Result<Self, Box<dyn Error>> {
-        if !path.is_dir() {
-            return Err(format!("Given path is not a directory: {}", path.display()).into());
+    pub fn new(level: usize) -> Self {
+        TreeLevel {
+            tables: Vec::new(),
+            non_overlapping: level > 0,
         }
+    }
 
-        let mut tables = Vec::new();
-
-        for entry in fs::read_dir(path)? {
-            let file_path = entry?.path();
-            let table = SortedStringTable::new(&file_path)?;
-            tables.push(table);
+    pub fn add(&mut self, table: Arc<SortedStringTable>) {
+        if self.non_overlapping {
+            let position = self.tables.partition_point(|existing| {
+                existing.properties().min_key < table.properties().min_key
+            });
+            self.tables.insert(position, table);
+        } else {
+            self.tables.push(table);
         }
+    }
 
-        Ok(TreeLevel { tables })
+    pub fn tables(&self) -> &[Arc<SortedStringTable>] {
+        &self.tables
     }
 
-    pub fn add(&mut self, table: SortedStringTable) {
-        self.tables.push(table);
+    /// Total file size of all tables in bytes.
+    pub fn size(&self) -> u64 {
+        self.tables.iter().map(|table| table.file_size()).sum()
     }
 
-    pub fn get_value(&self, key: &[u8]) -> Option<TableResult<'_>> {
-        self.tables
+    /// Newest version of `key` up to `snapshot`.
+    pub fn get_value_at(
+        &self,
+        key: &[u8],
+        snapshot: u64,
+    ) -> Result<Option<TableResult<'_>>, TableError> {
+        if self.non_overlapping {
+            // compaction only cuts outputs between keys, so the first table whose max key
+            // is >= key holds every version of it
+            let position = self
+                .tables
+                .partition_point(|table| table.properties().max_key.as_slice() < key);
+            return match self.tables.get(position) {
+                Some(table) if table.properties().min_key.as_slice() <= key => {
+                    table.get_at(key, snapshot)
+                }
+                _ => Ok(None),
+            };
+        }
+
+        let results = self
+            .tables
             .iter()
-            .filter_map(|table| table.get(key)) // skips None, unwraps Some
-            .max_by_key(|result| result.sequence_number)
+            .map(|table| table.get_at(key, snapshot))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(results
+            .into_iter()
+            .flatten() // skips None, unwraps Some
+            .max_by_key(|result| result.sequence_number))
     }
 }
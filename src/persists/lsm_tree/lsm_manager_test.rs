@@ -0,0 +1,170 @@
+use std::path::{Path, PathBuf};
+
+use crate::persists::{
+    lsm_tree::{
+        compaction::{
+            compaction::compact, compaction_strategy::CompactionStrategy,
+            leveled::LeveledCompaction,
+        },
+        lsm_manager::LsmManager,
+        manifest::table_file_name,
+        sorted_string_table::{sorted_string_table::SortedStringTable, sst_writer::SSTableWriter},
+    },
+    store_options::StoreOptions,
+};
+
+fn strategy() -> Box<dyn CompactionStrategy> {
+    Box::new(LeveledCompaction {
+        level0_file_trigger: 2,
+        ..LeveledCompaction::default()
+    })
+}
+
+fn flush_table(lsm_manager: &mut LsmManager, keys: &[&str], first_seq: u64) -> PathBuf {
+    let path = lsm_manager.file_numbers().new_table_path(lsm_manager.dir());
+    let entries = keys
+        .iter()
+        .zip(first_seq..)
+        .map(|(key, seq)| (key.as_bytes().to_vec(), (Some(b"v".to_vec()), seq)))
+        .collect();
+    SSTableWriter::write_to_file(&path, entries, 0).unwrap();
+    lsm_manager.add_table(&path).unwrap();
+    path
+}
+
+fn run_compaction(lsm_manager: &mut LsmManager) {
+    let (task, output) = lsm_manager.pick_compaction().unwrap();
+    let (paths, _) = compact(&task.inputs, &output).unwrap();
+    let outputs = paths
+        .iter()
+        .map(|path| SortedStringTable::new(path).unwrap())
+        .collect();
+    lsm_manager.apply_compaction(&task, outputs).unwrap();
+    for table in &task.inputs {
+        std::fs::remove_file(table.path()).unwrap();
+    }
+}
+
+#[test]
+fn reopen_restores_the_level_shape() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    {
+        let mut lsm_manager =
+            LsmManager::open(&StoreOptions::new(tmpdir.path()), strategy()).unwrap();
+        flush_table(&mut lsm_manager, &["a", "c"], 0);
+        flush_table(&mut lsm_manager, &["b", "d"], 2);
+        run_compaction(&mut lsm_manager);
+        flush_table(&mut lsm_manager, &["e"], 4);
+        assert_eq!(lsm_manager.level_table_counts(), vec![1, 1]);
+    }
+
+    let lsm_manager = LsmManager::open(&StoreOptions::new(tmpdir.path()), strategy()).unwrap();
+    assert_eq!(lsm_manager.level_table_counts(), vec![1, 1]);
+    assert_eq!(lsm_manager.max_sequence_number(), Some(4));
+    for key in ["a", "b", "c", "d", "e"] {
+        assert!(lsm_manager.get_value(key.as_bytes()).unwrap().is_some());
+    }
+}
+
+#[test]
+fn lookup_stops_at_the_first_level_with_the_key() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let mut lsm_manager = LsmManager::open(&StoreOptions::new(tmpdir.path()), strategy()).unwrap();
+    flush_table(&mut lsm_manager, &["a", "b"], 0);
+    flush_table(&mut lsm_manager, &["b", "c"], 2);
+    run_compaction(&mut lsm_manager);
+    // overlapping L0 tables, the newest version wins whatever order they were added in
+    flush_table(&mut lsm_manager, &["b"], 10);
+    flush_table(&mut lsm_manager, &["b"], 5);
+    assert_eq!(lsm_manager.level_table_counts(), vec![2, 1]);
+
+    assert_eq!(
+        lsm_manager
+            .get_value(b"b")
+            .unwrap()
+            .unwrap()
+            .sequence_number,
+        10
+    );
+    assert_eq!(
+        lsm_manager
+            .get_value_at(b"b", 4)
+            .unwrap()
+            .unwrap()
+            .sequence_number,
+        2
+    );
+    assert_eq!(
+        lsm_manager
+            .get_value(b"a")
+            .unwrap()
+            .unwrap()
+            .sequence_number,
+        0
+    );
+    assert!(lsm_manager.get_value(b"d").unwrap().is_none());
+}
+
+#[test]
+fn file_numbers_are_not_reused_after_reopen() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let first = {
+        let mut lsm_manager =
+            LsmManager::open(&StoreOptions::new(tmpdir.path()), strategy()).unwrap();
+        flush_table(&mut lsm_manager, &["a"], 0)
+    };
+
+    let mut lsm_manager = LsmManager::open(&StoreOptions::new(tmpdir.path()), strategy()).unwrap();
+    let second = flush_table(&mut lsm_manager, &["b"], 1);
+    assert_ne!(first, second);
+}
+
+#[test]
+fn unlisted_tables_are_removed_as_stray() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    {
+        let mut lsm_manager =
+            LsmManager::open(&StoreOptions::new(tmpdir.path()), strategy()).unwrap();
+        flush_table(&mut lsm_manager, &["a"], 0);
+    }
+    // a flush that crashed before its table was logged
+    let sst_dir = StoreOptions::new(tmpdir.path()).sst_dir();
+    let stray = sst_dir.join(table_file_name(7));
+    SSTableWriter::write_to_file(&stray, vec![(b"z".to_vec(), (Some(b"v".to_vec()), 9))], 0)
+        .unwrap();
+
+    let mut lsm_manager = LsmManager::open(&StoreOptions::new(tmpdir.path()), strategy()).unwrap();
+    assert!(!stray.exists());
+    assert_eq!(lsm_manager.level_table_counts(), vec![1]);
+    assert!(lsm_manager.get_value(b"z").unwrap().is_none());
+    // the number of the stray file is not handed out again
+    let next = flush_table(&mut lsm_manager, &["b"], 1);
+    assert!(next > stray);
+}
+
+#[test]
+fn missing_table_fails_the_open() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = {
+        let mut lsm_manager =
+            LsmManager::open(&StoreOptions::new(tmpdir.path()), strategy()).unwrap();
+        flush_table(&mut lsm_manager, &["a"], 0)
+    };
+    std::fs::remove_file(&path).unwrap();
+
+    let error = LsmManager::open(&StoreOptions::new(tmpdir.path()), strategy())
+        .err()
+        .expect("open should fail");
+    assert!(error.to_string().contains("missing"), "{error}");
+}
+
+#[test]
+fn tables_have_to_be_named_by_file_number() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let mut lsm_manager = LsmManager::open(&StoreOptions::new(tmpdir.path()), strategy()).unwrap();
+    let path = lsm_manager.dir().join("flushed.sst");
+    SSTableWriter::write_to_file(&path, vec![(b"a".to_vec(), (Some(b"v".to_vec()), 0))], 0)
+        .unwrap();
+
+    assert!(lsm_manager.add_table(Path::new(&path)).is_err());
+}
@@ -0,0 +1,179 @@
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::persists::lsm_tree::sorted_string_table::table_error::TableError;
+
+pub const MANIFEST_FILE_NAME: &str = "MANIFEST";
+const TABLE_EXTENSION: &str = "sst";
+
+/// One change to the set of live tables, written as a single JSON line so it is applied
+/// completely or not at all.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionEdit {
+    /// `(level, file_number)` of tables that became part of the tree.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added_tables: Vec<(usize, u64)>,
+    /// `(level, file_number)` of tables that were compacted away.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed_tables: Vec<(usize, u64)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_sequence_number: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_file_number: Option<u64>,
+}
+
+/// State rebuilt by replaying every edit of a manifest.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ManifestState {
+    /// Level of every live table by file number.
+    pub tables: BTreeMap<u64, usize>,
+    pub last_sequence_number: Option<u64>,
+    pub next_file_number: u64,
+}
+
+impl ManifestState {
+    pub fn apply(&mut self, edit: &VersionEdit) {
+        for (_, file_number) in &edit.removed_tables {
+            self.tables.remove(file_number);
+        }
+        for (level, file_number) in &edit.added_tables {
+            self.tables.insert(*file_number, *level);
+            self.next_file_number = self.next_file_number.max(file_number + 1);
+        }
+        if let Some(seq) = edit.last_sequence_number {
+            self.last_sequence_number = self.last_sequence_number.max(Some(seq));
+        }
+        if let Some(next) = edit.next_file_number {
+            self.next_file_number = self.next_file_number.max(next);
+        }
+    }
+
+    /// A single edit that recreates this state, used to start a fresh manifest.
+    pub fn snapshot(&self) -> VersionEdit {
+        VersionEdit {
+            added_tables: self
+                .tables
+                .iter()
+                .map(|(file_number, level)| (*level, *file_number))
+                .collect(),
+            removed_tables: Vec::new(),
+            last_sequence_number: self.last_sequence_number,
+            next_file_number: Some(self.next_file_number),
+        }
+    }
+}
+
+/// Append-only log of [`VersionEdit`]s describing which table files make up the tree.
+pub struct Manifest {
+    file: File,
+}
+
+impl Manifest {
+    /// Replays the manifest at `path`, a missing file yields an empty state.
+    /// A torn last line from a crash during append is ignored, any other unreadable
+    /// line is reported as corruption.
+    pub fn recover(path: &Path) -> Result<ManifestState, TableError> {
+        let mut state = ManifestState::default();
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(state),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut lines = BufReader::new(file).lines().enumerate().peekable();
+        while let Some((line_number, line)) = lines.next() {
+            let line = line?;
+            match serde_json::from_str::<VersionEdit>(&line) {
+                Ok(edit) => state.apply(&edit),
+                Err(_) if lines.peek().is_none() => {
+                    eprintln!("ignoring torn last manifest record in {}", path.display());
+                }
+                Err(_) => {
+                    return Err(TableError::corruption(path, "manifest record", line_number));
+                }
+            }
+        }
+
+        Ok(state)
+    }
+
+    /// Atomically replaces the manifest at `path` with one holding only `snapshot`.
+    pub fn create(path: &Path, snapshot: &VersionEdit) -> std::io::Result<Self> {
+        let tmp_path = path.with_extension("tmp");
+        let mut tmp = File::create(&tmp_path)?;
+        write_edit(&mut tmp, snapshot)?;
+        drop(tmp);
+        std::fs::rename(&tmp_path, path)?;
+        if let Some(dir) = path.parent() {
+            sync_dir(dir)?;
+        }
+
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Self { file })
+    }
+
+    /// Appends `edit` and syncs it before returning.
+    pub fn append(&mut self, edit: &VersionEdit) -> std::io::Result<()> {
+        write_edit(&mut self.file, edit)
+    }
+}
+
+fn write_edit(file: &mut File, edit: &VersionEdit) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(edit)?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    file.sync_data()
+}
+
+/// Makes a rename or a new file inside `dir` durable.
+pub(crate) fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    File::open(dir)?.sync_all()
+}
+
+/// Hands out the numbers table files are named after, shared by flushes and compactions.
+#[derive(Debug, Default)]
+pub struct FileNumbers(AtomicU64);
+
+impl FileNumbers {
+    pub fn new(next: u64) -> Self {
+        Self(AtomicU64::new(next))
+    }
+
+    pub fn allocate(&self) -> u64 {
+        self.0.fetch_add(1, Ordering::SeqCst)
+    }
+
+    /// The number the next allocation returns.
+    pub fn peek(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Path of a new table file in `dir`.
+    pub fn new_table_path(&self, dir: &Path) -> PathBuf {
+        dir.join(table_file_name(self.allocate()))
+    }
+}
+
+pub fn table_file_name(file_number: u64) -> String {
+    format!("{file_number:06}.{TABLE_EXTENSION}")
+}
+
+/// Inverse of [`table_file_name`], `None` for files not named by a file number.
+pub fn parse_table_file_name(path: &Path) -> Option<u64> {
+    if path.extension()? != TABLE_EXTENSION {
+        return None;
+    }
+    path.file_stem()?.to_str()?.parse().ok()
+}
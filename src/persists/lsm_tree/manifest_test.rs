@@ -0,0 +1,105 @@
+use std::{io::Write, path::Path};
+
+use crate::persists::lsm_tree::manifest::{
+    FileNumbers, MANIFEST_FILE_NAME, Manifest, ManifestState, VersionEdit, parse_table_file_name,
+    table_file_name,
+};
+
+fn added(level: usize, file_number: u64) -> VersionEdit {
+    VersionEdit {
+        added_tables: vec![(level, file_number)],
+        next_file_number: Some(file_number + 1),
+        ..VersionEdit::default()
+    }
+}
+
+#[test]
+fn recover_replays_every_edit() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = tmpdir.path().join(MANIFEST_FILE_NAME);
+
+    let mut manifest = Manifest::create(&path, &VersionEdit::default()).unwrap();
+    manifest.append(&added(0, 1)).unwrap();
+    manifest.append(&added(0, 2)).unwrap();
+    manifest
+        .append(&VersionEdit {
+            added_tables: vec![(1, 3)],
+            removed_tables: vec![(0, 1), (0, 2)],
+            last_sequence_number: Some(42),
+            next_file_number: Some(4),
+        })
+        .unwrap();
+
+    let state = Manifest::recover(&path).unwrap();
+    assert_eq!(state.tables.into_iter().collect::<Vec<_>>(), vec![(3, 1)]);
+    assert_eq!(state.last_sequence_number, Some(42));
+    assert_eq!(state.next_file_number, 4);
+}
+
+#[test]
+fn missing_manifest_is_an_empty_tree() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let state = Manifest::recover(&tmpdir.path().join(MANIFEST_FILE_NAME)).unwrap();
+    assert_eq!(state, ManifestState::default());
+}
+
+#[test]
+fn torn_last_record_is_ignored() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = tmpdir.path().join(MANIFEST_FILE_NAME);
+
+    let mut manifest = Manifest::create(&path, &added(0, 1)).unwrap();
+    manifest.append(&added(0, 2)).unwrap();
+    drop(manifest);
+    append_raw(&path, b"{\"added_tables\":[[0,");
+
+    let state = Manifest::recover(&path).unwrap();
+    assert_eq!(state.tables.len(), 2);
+}
+
+#[test]
+fn corrupted_record_before_the_tail_is_reported() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = tmpdir.path().join(MANIFEST_FILE_NAME);
+
+    let manifest = Manifest::create(&path, &added(0, 1)).unwrap();
+    drop(manifest);
+    append_raw(&path, b"garbage\n");
+    append_raw(&path, b"{\"added_tables\":[[0,2]]}\n");
+
+    let error = Manifest::recover(&path).unwrap_err();
+    assert!(error.is_corruption(), "unexpected error: {error}");
+}
+
+#[test]
+fn snapshot_recreates_the_state() {
+    let mut state = ManifestState::default();
+    state.apply(&added(0, 5));
+    state.apply(&added(2, 7));
+    state.apply(&VersionEdit {
+        removed_tables: vec![(0, 5)],
+        last_sequence_number: Some(9),
+        ..VersionEdit::default()
+    });
+
+    let mut recreated = ManifestState::default();
+    recreated.apply(&state.snapshot());
+    assert_eq!(recreated, state);
+}
+
+#[test]
+fn table_file_names_round_trip() {
+    let file_numbers = FileNumbers::new(12);
+    let path = file_numbers.new_table_path(Path::new("data"));
+
+    assert_eq!(path, Path::new("data").join(table_file_name(12)));
+    assert_eq!(parse_table_file_name(&path), Some(12));
+    assert_eq!(file_numbers.peek(), 13);
+    assert_eq!(parse_table_file_name(Path::new("L0_abc.sst")), None);
+    assert_eq!(parse_table_file_name(Path::new("000001.log")), None);
+}
+
+fn append_raw(path: &Path, bytes: &[u8]) {
+    let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+    file.write_all(bytes).unwrap();
+}
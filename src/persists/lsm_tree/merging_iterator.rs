@@ -0,0 +1,185 @@
+use std::{cmp::Ordering, collections::BinaryHeap, ops::Bound};
+
+use crate::persists::{
+    lsm_tree::sorted_string_table::{
+        sorted_string_table::SSTableIterator, table_error::TableError,
+    },
+    memtable::memtable_trait::LookupResult,
+};
+
+/// A single version of a key from one of the merged sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanEntry<'a> {
+    pub key: &'a [u8],
+    /// `None` for a tombstone.
+    pub value: Option<&'a [u8]>,
+    pub sequence_number: u64,
+}
+
+/// Input of a [`MergingIterator`], yielding keys in ascending order with the newest
+/// version of a key first.
+pub enum ScanSource<'a> {
+    MemTable(Box<dyn Iterator<Item = (&'a [u8], LookupResult<'a>)> + 'a>),
+    Table(SSTableIterator<'a>),
+}
+
+impl<'a> ScanSource<'a> {
+    fn next(&mut self) -> Option<ScanEntry<'a>> {
+        match self {
+            ScanSource::MemTable(iter) => iter.find_map(|(key, result)| match result {
+                LookupResult::Found((value, sequence_number)) => Some(ScanEntry {
+                    key,
+                    value: Some(value),
+                    sequence_number,
+                }),
+                LookupResult::Deleted(sequence_number) => Some(ScanEntry {
+                    key,
+                    value: None,
+                    sequence_number,
+                }),
+                LookupResult::NotFound => None,
+            }),
+            ScanSource::Table(iter) => iter.next().map(|entry| ScanEntry {
+                key: entry.key,
+                value: entry.value(),
+                sequence_number: entry.sequence_number,
+            }),
+        }
+    }
+
+    fn take_error(&mut self) -> Option<TableError> {
+        match self {
+            ScanSource::MemTable(_) => None,
+            ScanSource::Table(iter) => iter.take_error(),
+        }
+    }
+}
+
+/// K-way merge of memtables and tables into the visible state of a key range.
+///
+/// Yields each key once with its newest value up to `snapshot`. Shadowed versions and
+/// keys whose newest visible version is a tombstone are skipped. A table that hits a
+/// corrupted block ends early, check [`MergingIterator::take_error`] once done.
+pub struct MergingIterator<'a> {
+    sources: Vec<ScanSource<'a>>,
+    heap: BinaryHeap<HeapEntry<'a>>,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    snapshot: u64,
+    last_key: Option<&'a [u8]>,
+}
+
+impl<'a> MergingIterator<'a> {
+    /// The sources have to be positioned at or shortly before the start of `range`,
+    /// only keys within the range are returned.
+    pub fn new(
+        mut sources: Vec<ScanSource<'a>>,
+        range: (Bound<&[u8]>, Bound<&[u8]>),
+        snapshot: u64,
+    ) -> Self {
+        let mut heap = BinaryHeap::with_capacity(sources.len());
+        for (source_index, source) in sources.iter_mut().enumerate() {
+            if let Some(entry) = source.next() {
+                heap.push(HeapEntry {
+                    entry,
+                    source_index,
+                });
+            }
+        }
+        Self {
+            sources,
+            heap,
+            start: range.0.map(<[u8]>::to_vec),
+            end: range.1.map(<[u8]>::to_vec),
+            snapshot,
+            last_key: None,
+        }
+    }
+
+    pub fn take_error(&mut self) -> Option<TableError> {
+        self.sources.iter_mut().find_map(ScanSource::take_error)
+    }
+
+    fn before_start(&self, key: &[u8]) -> bool {
+        match &self.start {
+            Bound::Included(start) => key < start.as_slice(),
+            Bound::Excluded(start) => key <= start.as_slice(),
+            Bound::Unbounded => false,
+        }
+    }
+
+    fn past_end(&self, key: &[u8]) -> bool {
+        match &self.end {
+            Bound::Included(end) => key > end.as_slice(),
+            Bound::Excluded(end) => key >= end.as_slice(),
+            Bound::Unbounded => false,
+        }
+    }
+}
+
+impl<'a> Iterator for MergingIterator<'a> {
+    /// Key and value.
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(HeapEntry {
+            entry,
+            source_index,
+        }) = self.heap.pop()
+        {
+            if self.past_end(entry.key) {
+                self.heap.clear();
+                return None;
+            }
+            if let Some(next) = self.sources[source_index].next() {
+                self.heap.push(HeapEntry {
+                    entry: next,
+                    source_index,
+                });
+            }
+
+            // versions arrive newest first, only the first visible one of a key counts
+            if entry.sequence_number > self.snapshot
+                || self.last_key == Some(entry.key)
+                || self.before_start(entry.key)
+            {
+                continue;
+            }
+            self.last_key = Some(entry.key);
+            if let Some(value) = entry.value {
+                return Some((entry.key, value));
+            }
+        }
+        None
+    }
+}
+
+struct HeapEntry<'a> {
+    entry: ScanEntry<'a>,
+    source_index: usize,
+}
+
+impl PartialEq for HeapEntry<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry<'_> {}
+
+impl PartialOrd for HeapEntry<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapEntry<'_> {
+    /// The max-heap pops the smallest key first and, within a key, the newest version.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .entry
+            .key
+            .cmp(self.entry.key)
+            .then(self.entry.sequence_number.cmp(&other.entry.sequence_number))
+    }
+}
@@ -1,3 +1,10 @@
-mod compaction;
+pub mod compaction;
 pub mod lsm_manager;
+#[cfg(test)]
+mod lsm_manager_test;
+pub mod manifest;
+#[cfg(test)]
+mod manifest_test;
+pub mod merging_iterator;
+pub mod snapshot_list;
 pub mod sorted_string_table;
@@ -0,0 +1,45 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+/// Open snapshots by sequence number with their reference count.
+///
+/// Shared by the store, the flush worker and the tree, flushes and compactions keep
+/// the versions these snapshots can see. Synchronous so a snapshot can release itself on drop.
+#[derive(Debug, Default)]
+pub struct SnapshotList {
+    snapshots: Mutex<BTreeMap<u64, usize>>,
+}
+
+impl SnapshotList {
+    /// Registers a snapshot at the sequence number returned by `current`, which is read under
+    /// the lock so no flush or compaction can miss a snapshot that is being taken.
+    pub fn acquire(&self, current: impl FnOnce() -> u64) -> u64 {
+        let mut snapshots = self.snapshots.lock().expect("snapshot list poisoned");
+        let sequence_number = current();
+        *snapshots.entry(sequence_number).or_default() += 1;
+        sequence_number
+    }
+
+    pub fn register(&self, sequence_number: u64) {
+        self.acquire(|| sequence_number);
+    }
+
+    pub fn release(&self, sequence_number: u64) {
+        let mut snapshots = self.snapshots.lock().expect("snapshot list poisoned");
+        if let Some(count) = snapshots.get_mut(&sequence_number) {
+            *count -= 1;
+            if *count == 0 {
+                snapshots.remove(&sequence_number);
+            }
+        }
+    }
+
+    /// Sequence numbers of the open snapshots in ascending order.
+    pub fn sequence_numbers(&self) -> Vec<u64> {
+        self.snapshots
+            .lock()
+            .expect("snapshot list poisoned")
+            .keys()
+            .copied()
+            .collect()
+    }
+}
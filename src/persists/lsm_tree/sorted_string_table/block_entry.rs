@@ -1,18 +1,37 @@
 use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
 
-use super::sst_table_block::SSTableBlock;
+use super::{
+    sst_table_block::{HEADER_SIZE, SSTableBlock},
+    table_result::EntryKind,
+};
+
+/// Bytes an entry takes besides its key and value: both lengths, the kind and the sequence number.
+pub const ENTRY_OVERHEAD: usize = 2 * HEADER_SIZE + 1 + 8;
 
 pub struct BlockEntry {
     buffer: Vec<u8>,
 }
 
 impl BlockEntry {
-    pub fn from_parts(key: &[u8], value: &[u8], &seq_number: &u64) -> Self {
-        let mut buffer = Vec::with_capacity(4 + key.len() + 4 + value.len());
+    /// Size of the encoded entry, a tombstone has an empty value.
+    pub const fn encoded_len(key_len: usize, value_len: usize) -> usize {
+        ENTRY_OVERHEAD + key_len + value_len
+    }
+
+    /// Encodes an entry as `key_len | key | kind | value_len | value | seq_number`.
+    /// A `None` value is written as a tombstone with an empty value.
+    pub fn from_parts(key: &[u8], value: Option<&[u8]>, &seq_number: &u64) -> Self {
+        let (kind, value) = match value {
+            Some(value) => (EntryKind::Value, value),
+            None => (EntryKind::Tombstone, &[][..]),
+        };
+
+        let mut buffer = Vec::with_capacity(Self::encoded_len(key.len(), value.len()));
 
         //TODO handle errors
         buffer.write_u32::<LittleEndian>(key.len() as u32).unwrap();
         buffer.extend_from_slice(key);
+        buffer.write_u8(kind.as_byte()).unwrap();
         buffer
             .write_u32::<LittleEndian>(value.len() as u32)
             .unwrap();
@@ -27,9 +46,10 @@ impl BlockEntry {
         &self.buffer[4..4 + key_len]
     }
 
+    #[allow(dead_code)]
     pub fn value(&self) -> &[u8] {
         let key_len = LittleEndian::read_u32(&self.buffer[0..4]) as usize;
-        let value_len_offset = 4 + key_len;
+        let value_len_offset = 4 + key_len + 1;
         let value_len =
             LittleEndian::read_u32(&self.buffer[value_len_offset..value_len_offset + 4]) as usize;
         &self.buffer[value_len_offset + 4..value_len_offset + 4 + value_len]
@@ -0,0 +1,137 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Default number of filter bits spent per key, gives roughly a 1% false positive rate.
+pub const DEFAULT_BITS_PER_KEY: usize = 10;
+
+/// Builds the filter block: `bit array | number of hash functions (u8)`.
+pub struct BloomFilterBuilder {
+    bits_per_key: usize,
+    key_hashes: Vec<u32>,
+}
+
+impl BloomFilterBuilder {
+    pub fn new(bits_per_key: usize) -> Self {
+        Self {
+            bits_per_key,
+            key_hashes: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, key: &[u8]) {
+        self.key_hashes.push(bloom_hash(key));
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        // k = ln(2) * bits_per_key minimises the false positive rate
+        let num_hashes = ((self.bits_per_key as f64 * 0.69) as usize).clamp(1, 30);
+
+        // tiny filters have a very high false positive rate, so enforce a minimum length
+        let num_bits = (self.key_hashes.len() * self.bits_per_key).max(64);
+        let num_bytes = num_bits.div_ceil(8);
+        let num_bits = num_bytes * 8;
+
+        let mut filter = vec![0u8; num_bytes + 1];
+        for hash in self.key_hashes {
+            for bit in probe_positions(hash, num_hashes, num_bits) {
+                filter[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+        filter[num_bytes] = num_hashes as u8;
+
+        filter
+    }
+}
+
+/// Returns `false` if `key` is definitely not part of the filter.
+pub fn may_contain(filter: &[u8], key: &[u8]) -> bool {
+    let Some((&num_hashes, bits)) = filter.split_last() else {
+        return true;
+    };
+    if bits.is_empty() {
+        return true;
+    }
+
+    let num_bits = bits.len() * 8;
+    probe_positions(bloom_hash(key), num_hashes as usize, num_bits)
+        .all(|bit| bits[bit / 8] & (1 << (bit % 8)) != 0)
+}
+
+/// Double hashing, every probe is derived from a single hash of the key.
+fn probe_positions(hash: u32, num_hashes: usize, num_bits: usize) -> impl Iterator<Item = usize> {
+    let delta = hash.rotate_right(17);
+    (0..num_hashes as u32)
+        .map(move |i| hash.wrapping_add(i.wrapping_mul(delta)) as usize % num_bits)
+}
+
+/// 32-bit FNV-1a with a murmur3 finalizer, stable across builds so filters written to disk stay valid.
+fn bloom_hash(key: &[u8]) -> u32 {
+    let mut hash = key.iter().fold(0x811c_9dc5u32, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    });
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^ (hash >> 16)
+}
+
+/// Counts how often the filter of a table answered a point lookup.
+#[derive(Debug, Default)]
+pub struct BloomFilterCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    false_positives: AtomicU64,
+}
+
+impl BloomFilterCounters {
+    pub fn record_miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_hit(&self, found: bool) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        if !found {
+            self.false_positives.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> BloomFilterStats {
+        BloomFilterStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            false_positives: self.false_positives.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// `hits` are lookups the filter let through, `misses` are lookups it rejected.
+/// A hit for a key the table does not hold is counted as a false positive.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BloomFilterStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub false_positives: u64,
+}
+
+impl BloomFilterStats {
+    /// Share of lookups for absent keys that the filter failed to reject.
+    pub fn false_positive_rate(&self) -> f64 {
+        let absent_lookups = self.false_positives + self.misses;
+        if absent_lookups == 0 {
+            return 0.0;
+        }
+        self.false_positives as f64 / absent_lookups as f64
+    }
+}
+
+impl std::ops::Add for BloomFilterStats {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            hits: self.hits + other.hits,
+            misses: self.misses + other.misses,
+            false_positives: self.false_positives + other.false_positives,
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use crate::persists::lsm_tree::sorted_string_table::{
+    bloom_filter::{BloomFilterBuilder, DEFAULT_BITS_PER_KEY, may_contain},
+    sorted_string_table::SortedStringTable,
+    sst_writer::{SSTableWriter, TableOptions},
+};
+
+#[test]
+fn filter_has_no_false_negatives() {
+    let mut builder = BloomFilterBuilder::new(DEFAULT_BITS_PER_KEY);
+    for i in 0..10_000 {
+        builder.add(format!("key{i}").as_bytes());
+    }
+    let filter = builder.finish();
+
+    for i in 0..10_000 {
+        assert!(may_contain(&filter, format!("key{i}").as_bytes()));
+    }
+}
+
+#[test]
+fn filter_false_positive_rate_is_low() {
+    let mut builder = BloomFilterBuilder::new(DEFAULT_BITS_PER_KEY);
+    for i in 0..10_000 {
+        builder.add(format!("key{i}").as_bytes());
+    }
+    let filter = builder.finish();
+
+    let false_positives = (0..10_000)
+        .filter(|i| may_contain(&filter, format!("missing{i}").as_bytes()))
+        .count();
+
+    // 10 bits per key should be close to 1%
+    assert!(
+        false_positives < 300,
+        "false positive rate too high: {false_positives} / 10000"
+    );
+}
+
+#[test]
+fn table_counts_filter_hits_and_misses() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = tmpdir.path().join("filtered.sst");
+
+    let entries = (0..100)
+        .map(|i| {
+            (
+                format!("key{i:03}").into_bytes(),
+                (Some(b"value".to_vec()), i as u64),
+            )
+        })
+        .collect();
+    SSTableWriter::write_to_file(&path, entries, 0).unwrap();
+
+    let table = SortedStringTable::new(&path).unwrap();
+
+    for i in 0..100 {
+        assert!(
+            table
+                .get(format!("key{i:03}").as_bytes())
+                .unwrap()
+                .is_some()
+        );
+    }
+    for i in 0..1000 {
+        // inside the key range so the first/last key check does not reject them
+        assert!(
+            table
+                .get(format!("key{:03}_{i}", i % 99).as_bytes())
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    let stats = table.filter_stats();
+    assert_eq!(stats.hits - stats.false_positives, 100);
+    assert_eq!(stats.misses + stats.false_positives, 1000);
+    assert!(stats.false_positive_rate() < 0.05);
+}
+
+#[test]
+fn table_without_filter_still_answers_lookups() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = tmpdir.path().join("unfiltered.sst");
+
+    let entries = vec![
+        (b"a".to_vec(), (Some(b"1".to_vec()), 1)),
+        (b"c".to_vec(), (Some(b"3".to_vec()), 2)),
+    ];
+    let options = TableOptions {
+        bits_per_key: 0,
+        ..TableOptions::default()
+    };
+    SSTableWriter::write_to_file_with_options(&path, entries, 0, &options).unwrap();
+
+    let table = SortedStringTable::new(&path).unwrap();
+    assert_eq!(table.get(b"a").unwrap().unwrap().value, b"1");
+    assert!(table.get(b"b").unwrap().is_none());
+    assert_eq!(table.filter_stats().hits + table.filter_stats().misses, 0);
+}
+
+fn write_users(path: &std::path::Path, prefix_len: Option<usize>) -> SortedStringTable {
+    // user:000:profile .. user:998:profile, only even user ids
+    let entries = (0..1000)
+        .step_by(2)
+        .map(|id| {
+            (
+                format!("user:{id:03}:profile").into_bytes(),
+                (Some(b"v".to_vec()), id as u64),
+            )
+        })
+        .collect();
+    let options = TableOptions {
+        prefix_len,
+        ..TableOptions::default()
+    };
+    SSTableWriter::write_to_file_with_options(path, entries, 0, &options).unwrap();
+    SortedStringTable::new(path).unwrap()
+}
+
+#[test]
+fn prefix_filter_rejects_absent_prefixes() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let table = write_users(&tmpdir.path().join("prefixed.sst"), Some("user:000:".len()));
+
+    for id in (0..1000).step_by(2) {
+        assert!(table.may_contain_prefix(format!("user:{id:03}:").as_bytes()));
+        assert!(table.may_contain_prefix(format!("user:{id:03}:prof").as_bytes()));
+    }
+    let rejected = (1..1000)
+        .step_by(2)
+        .filter(|id| !table.may_contain_prefix(format!("user:{id:03}:").as_bytes()))
+        .count();
+    assert!(
+        rejected > 450,
+        "only {rejected} of 500 absent prefixes rejected"
+    );
+
+    // too short for the filter, only the key range applies
+    assert!(table.may_contain_prefix(b"user:"));
+    assert!(!table.may_contain_prefix(b"admin:"));
+    assert!(!table.may_contain_prefix(b"zebra"));
+}
+
+#[test]
+fn table_without_prefix_filter_only_checks_the_key_range() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let table = write_users(&tmpdir.path().join("plain.sst"), None);
+
+    assert!(table.may_contain_prefix(b"user:001:"));
+    assert!(table.may_contain_prefix(b"u"));
+    assert!(!table.may_contain_prefix(b"v"));
+}
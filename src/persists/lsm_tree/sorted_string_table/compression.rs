@@ -0,0 +1,55 @@
+use std::borrow::Cow;
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Codec applied to the data blocks of a table, recorded per block in its trailer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CompressionType {
+    #[default]
+    None = 0,
+    Lz4 = 1,
+    Zstd = 2,
+}
+
+impl CompressionType {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(CompressionType::None),
+            1 => Some(CompressionType::Lz4),
+            2 => Some(CompressionType::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn as_byte(self) -> u8 {
+        self as u8
+    }
+
+    /// Compresses `block`, falling back to storing it as is when compression does not pay off.
+    /// Returns the bytes to write together with the codec that was actually used.
+    pub fn compress(self, block: &[u8]) -> std::io::Result<(Cow<'_, [u8]>, CompressionType)> {
+        let compressed = match self {
+            CompressionType::None => return Ok((Cow::Borrowed(block), CompressionType::None)),
+            CompressionType::Lz4 => lz4_flex::compress_prepend_size(block),
+            CompressionType::Zstd => zstd::bulk::compress(block, ZSTD_LEVEL)?,
+        };
+
+        if compressed.len() < block.len() {
+            Ok((Cow::Owned(compressed), self))
+        } else {
+            Ok((Cow::Borrowed(block), CompressionType::None))
+        }
+    }
+
+    /// Returns `None` if `block` is not valid for this codec.
+    pub fn decompress(self, block: &[u8]) -> Option<Cow<'_, [u8]>> {
+        match self {
+            CompressionType::None => Some(Cow::Borrowed(block)),
+            CompressionType::Lz4 => lz4_flex::decompress_size_prepended(block)
+                .ok()
+                .map(Cow::Owned),
+            CompressionType::Zstd => zstd::decode_all(block).ok().map(Cow::Owned),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::path::Path;
+
+use crate::persists::lsm_tree::sorted_string_table::{
+    compression::CompressionType,
+    sorted_string_table::SortedStringTable,
+    sst_writer::{SSTableWriter, TableOptions},
+};
+
+type Entry = (Vec<u8>, (Option<Vec<u8>>, u64));
+
+fn compressible_entries(count: usize) -> Vec<Entry> {
+    (0..count)
+        .map(|i| {
+            (
+                format!("key{i:06}").into_bytes(),
+                (
+                    Some(format!("value-{}", i % 10).repeat(8).into_bytes()),
+                    i as u64,
+                ),
+            )
+        })
+        .collect()
+}
+
+fn write_table(path: &Path, entries: Vec<Entry>, compression: CompressionType) {
+    let options = TableOptions {
+        compression,
+        ..TableOptions::default()
+    };
+    SSTableWriter::write_to_file_with_options(path, entries, 0, &options).unwrap();
+}
+
+#[test]
+fn codecs_round_trip() {
+    let tmpdir = tempfile::tempdir().unwrap();
+
+    for compression in [
+        CompressionType::None,
+        CompressionType::Lz4,
+        CompressionType::Zstd,
+    ] {
+        let path = tmpdir.path().join(format!("{compression:?}.sst"));
+        let mut entries = compressible_entries(2000);
+        entries[10].1.0 = None;
+        write_table(&path, entries.clone(), compression);
+
+        let table = SortedStringTable::new(&path).unwrap();
+        for (key, (value, seq)) in &entries {
+            let entry = table.get(key).unwrap().expect("key not found");
+            assert_eq!(entry.value().map(<[u8]>::to_vec), *value, "{compression:?}");
+            assert_eq!(entry.sequence_number, *seq);
+        }
+
+        let keys: Vec<_> = table.iter().map(|entry| entry.key.to_vec()).collect();
+        let expected: Vec<_> = entries.into_iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, expected, "{compression:?}");
+    }
+}
+
+#[test]
+fn compressed_tables_are_smaller() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let size_with = |compression: CompressionType| {
+        let path = tmpdir.path().join(format!("{compression:?}.sst"));
+        write_table(&path, compressible_entries(2000), compression);
+        std::fs::metadata(&path).unwrap().len()
+    };
+
+    let uncompressed = size_with(CompressionType::None);
+    assert!(size_with(CompressionType::Lz4) < uncompressed / 2);
+    assert!(size_with(CompressionType::Zstd) < uncompressed / 2);
+}
+
+#[test]
+fn incompressible_blocks_are_stored_raw() {
+    // xorshift output, random enough that lz4 cannot shrink it
+    let mut state = 0x2545_f491_u32;
+    let block: Vec<u8> = (0..4096)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect();
+
+    let (stored, compression) = CompressionType::Lz4.compress(&block).unwrap();
+    assert_eq!(compression, CompressionType::None);
+    assert_eq!(stored.as_ref(), block.as_slice());
+}
+
+#[test]
+fn corrupted_compressed_block_is_reported() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = tmpdir.path().join("corrupt.sst");
+    write_table(&path, compressible_entries(2000), CompressionType::Zstd);
+
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[8] ^= 0xff;
+    std::fs::write(&path, bytes).unwrap();
+
+    let table = SortedStringTable::new(&path).unwrap();
+    assert!(table.get(b"key000000").unwrap_err().is_corruption());
+}
@@ -1,10 +1,14 @@
-use std::{collections::HashMap, error::Error, sync::Arc};
+use std::{collections::HashMap, error::Error, path::PathBuf, sync::Arc};
 
 use tokio::sync::{RwLock, mpsc};
 
 use crate::persists::{
-    lsm_tree::sorted_string_table::sst_writer::SSTableWriter,
-    memtable::{btree_map::BTreeMemTable, memtable_trait::MemTable},
+    lsm_tree::{
+        manifest::{FileNumbers, sync_dir},
+        snapshot_list::SnapshotList,
+        sorted_string_table::sst_writer::{SSTableWriter, TableOptions},
+    },
+    memtable::memtable_trait::MemTable,
 };
 
 pub enum FlushCommand {
@@ -13,22 +17,29 @@ pub enum FlushCommand {
 
 pub type FlushResult = Result<(u64, std::path::PathBuf), Box<dyn std::error::Error + Send + Sync>>;
 
-pub struct FlushWorker<const MAX_SIZE: usize> {
-    flushable_tables: Arc<RwLock<HashMap<u64, Arc<BTreeMemTable<{ MAX_SIZE }>>>>>,
-    table_writer: SSTableWriter,
+pub struct FlushWorker<M: MemTable> {
+    flushable_tables: Arc<RwLock<HashMap<u64, Arc<M>>>>,
+    dir: PathBuf,
+    file_numbers: Arc<FileNumbers>,
+    /// Older versions visible to these snapshots are written along with the newest.
+    snapshots: Arc<SnapshotList>,
+    table_options: TableOptions,
 }
 
-impl<const MAX_SIZE: usize> FlushWorker<MAX_SIZE> {
+impl<M: MemTable> FlushWorker<M> {
     pub fn new(
-        flushable_tables: Arc<RwLock<HashMap<u64, Arc<BTreeMemTable<{ MAX_SIZE }>>>>>,
+        flushable_tables: Arc<RwLock<HashMap<u64, Arc<M>>>>,
+        dir: PathBuf,
+        file_numbers: Arc<FileNumbers>,
+        snapshots: Arc<SnapshotList>,
+        table_options: TableOptions,
     ) -> Self {
         Self {
             flushable_tables,
-            table_writer: SSTableWriter::new(std::path::PathBuf::from(format!(
-                "L0_{}.sst",
-                uuid::Uuid::new_v4()
-            )))
-            .expect("failed to open new file"),
+            dir,
+            file_numbers,
+            snapshots,
+            table_options,
         }
     }
 
@@ -45,20 +56,33 @@ impl<const MAX_SIZE: usize> FlushWorker<MAX_SIZE> {
     }
 
     pub async fn flush_all(&self, tx: &mut mpsc::Sender<FlushResult>) {
-        let to_flush = {
+        let mut to_flush: Vec<_> = {
             let guard = self.flushable_tables.read().await;
-            guard.clone() // shallow clone: nur Arcs
+            guard
+                .iter()
+                .map(|(id, table)| (*id, Arc::clone(table))) // shallow clone: nur Arcs
+                .collect()
         };
+        // oldest memtable first, so a table registered later never holds older versions than
+        // the ones a running compaction is looking at
+        to_flush.sort_by_key(|(id, _)| *id);
 
         for (id, table) in &to_flush {
-            let buffer = table.flush();
-            let path = std::path::PathBuf::from(format!("L0_{}.sst", uuid::Uuid::new_v4()));
+            let buffer = table.flush(&self.snapshots.sequence_numbers());
+            let path = self.file_numbers.new_table_path(&self.dir);
 
             //TODO use new file writer here
-            let result: FlushResult =
-                SSTableWriter::write_to_file(&path, buffer, table.bytes_used() as u32)
-                    .map(|_| (*id, path.clone()))
-                    .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>);
+            // the wal segments of the memtable are deleted once the table is registered,
+            // so the table has to be durable before its result is sent
+            let result: FlushResult = SSTableWriter::write_to_file_with_options(
+                &path,
+                buffer,
+                table.encoded_bytes() as u32,
+                &self.table_options,
+            )
+            .and_then(|_| sync_dir(&self.dir))
+            .map(|_| (*id, path.clone()))
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>);
 
             let _ = tx.send(result).await;
         }
@@ -3,7 +3,13 @@ use std::{collections::HashMap, sync::Arc};
 use tokio::sync::RwLock;
 
 use crate::persists::{
-    lsm_tree::sorted_string_table::flush_worker::{FlushCommand, FlushWorker},
+    lsm_tree::{
+        manifest::FileNumbers,
+        sorted_string_table::{
+            flush_worker::{FlushCommand, FlushWorker},
+            sst_writer::TableOptions,
+        },
+    },
     memtable::{btree_map::BTreeMemTable, memtable_trait::MemTable},
 };
 
@@ -12,7 +18,7 @@ async fn flush_worker() {
     let flushable_tables = Arc::new(RwLock::new(HashMap::new()));
 
     for j in 0..2 {
-        let mut table = BTreeMemTable::<640>::new();
+        let mut table = BTreeMemTable::new();
 
         for i in 0..10 {
             table.insert(
@@ -28,7 +34,14 @@ async fn flush_worker() {
 
     let (flush_result_tx, mut flush_result_rx) = tokio::sync::mpsc::channel(16);
 
-    let worker = Arc::new(FlushWorker::<640>::new(flushable_tables));
+    let tmpdir = tempfile::tempdir().unwrap();
+    let worker = Arc::new(FlushWorker::new(
+        flushable_tables,
+        tmpdir.path().to_path_buf(),
+        Arc::new(FileNumbers::default()),
+        Arc::default(),
+        TableOptions::default(),
+    ));
     let worker_clone = Arc::clone(&worker); // explizit vor tokio::spawn
 
     tokio::spawn(async move {
@@ -0,0 +1,197 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+};
+
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+
+use super::{
+    compression::CompressionType,
+    sst_table_block::{
+        CHECKSUM_FORMAT_VERSION, COMPRESSION_FORMAT_VERSION, FILTERED_FORMAT_VERSION,
+        FORMAT_VERSION, HEADER_SIZE, INDEXED_FORMAT_VERSION, PREFIX_FILTER_FORMAT_VERSION,
+        PROPERTIES_FORMAT_VERSION,
+    },
+};
+
+/// Trailing `metadata_offset | version`, present in every format version.
+pub const TRAILER_SIZE: usize = 2 * HEADER_SIZE;
+const HANDLE_SIZE: usize = 2 * HEADER_SIZE;
+/// CRC32C stored after every block, not counted in the block handle.
+pub const CHECKSUM_SIZE: usize = std::mem::size_of::<u32>();
+/// Codec byte and CRC32C stored after every data block, the CRC covers the codec byte too.
+pub const DATA_BLOCK_TRAILER_SIZE: usize = 1 + CHECKSUM_SIZE;
+
+/// Offset and length of a block inside the table file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockHandle {
+    pub offset: usize,
+    pub len: usize,
+}
+
+impl BlockHandle {
+    pub fn range(&self) -> std::ops::Range<usize> {
+        self.offset..self.offset + self.len
+    }
+
+    /// Returns the block contents, or `None` if the block is truncated or fails its checksum.
+    pub fn read<'a>(&self, buffer: &'a [u8], checksummed: bool) -> Option<&'a [u8]> {
+        let block = buffer.get(self.range())?;
+        if checksummed {
+            let stored =
+                buffer.get(self.offset + self.len..self.offset + self.len + CHECKSUM_SIZE)?;
+            verify_checksum(block, stored).then_some(block)
+        } else {
+            Some(block)
+        }
+    }
+
+    /// Returns the stored data block and the codec it was written with.
+    /// Blocks written before compression existed are reported as uncompressed.
+    pub fn read_data_block<'a>(
+        &self,
+        buffer: &'a [u8],
+        version: u32,
+    ) -> Option<(&'a [u8], CompressionType)> {
+        if version < COMPRESSION_FORMAT_VERSION {
+            return self
+                .read(buffer, version >= CHECKSUM_FORMAT_VERSION)
+                .map(|block| (block, CompressionType::None));
+        }
+
+        let trailer_start = self.offset + self.len;
+        let block_with_codec = buffer.get(self.offset..trailer_start + 1)?;
+        let stored = buffer.get(trailer_start + 1..trailer_start + DATA_BLOCK_TRAILER_SIZE)?;
+        if !verify_checksum(block_with_codec, stored) {
+            return None;
+        }
+
+        let compression = CompressionType::from_byte(block_with_codec[self.len])?;
+        Some((&block_with_codec[..self.len], compression))
+    }
+}
+
+fn verify_checksum(block: &[u8], stored: &[u8]) -> bool {
+    crc32c::crc32c(block) == LittleEndian::read_u32(stored)
+}
+
+/// Decoded metadata block and trailer. Handles are `None` for blocks the version predates.
+#[derive(Debug)]
+pub struct Footer {
+    pub metadata_offset: usize,
+    pub version: u32,
+    pub index_handle: Option<BlockHandle>,
+    pub filter_handle: Option<BlockHandle>,
+    pub properties_handle: Option<BlockHandle>,
+    pub prefix_filter_handle: Option<BlockHandle>,
+}
+
+impl Footer {
+    /// Encodes the checksummed metadata block followed by the trailer.
+    pub fn encode(metadata_offset: u32, handles: &[(u32, u32)]) -> std::io::Result<Vec<u8>> {
+        let mut buffer =
+            Vec::with_capacity(handles.len() * HANDLE_SIZE + CHECKSUM_SIZE + TRAILER_SIZE);
+        for (offset, len) in handles {
+            buffer.write_u32::<LittleEndian>(*offset)?;
+            buffer.write_u32::<LittleEndian>(*len)?;
+        }
+        let checksum = crc32c::crc32c(&buffer);
+        buffer.write_u32::<LittleEndian>(checksum)?;
+
+        buffer.write_u32::<LittleEndian>(metadata_offset)?;
+        buffer.write_u32::<LittleEndian>(FORMAT_VERSION)?;
+        Ok(buffer)
+    }
+
+    pub fn is_checksummed(&self) -> bool {
+        self.version >= CHECKSUM_FORMAT_VERSION
+    }
+
+    /// Decodes the footer from a buffer holding the whole table.
+    pub fn decode(buffer: &[u8]) -> Option<Self> {
+        let trailer_offset = buffer.len().checked_sub(TRAILER_SIZE)?;
+        let (metadata_offset, version) = decode_trailer(&buffer[trailer_offset..]);
+
+        let metadata = if version >= INDEXED_FORMAT_VERSION {
+            buffer.get(metadata_offset..trailer_offset)?
+        } else {
+            &[]
+        };
+        Self::from_parts(metadata_offset, version, metadata)
+    }
+
+    /// Reads only the metadata block and trailer from the end of `file`.
+    pub fn read_from(file: &mut File) -> std::io::Result<Self> {
+        let file_len = file.seek(SeekFrom::End(0))? as usize;
+        let trailer_offset = file_len
+            .checked_sub(TRAILER_SIZE)
+            .ok_or_else(|| invalid_data("sstable is too short for a footer"))?;
+
+        let mut trailer = [0u8; TRAILER_SIZE];
+        file.seek(SeekFrom::Start(trailer_offset as u64))?;
+        file.read_exact(&mut trailer)?;
+        let (metadata_offset, version) = decode_trailer(&trailer);
+
+        let mut metadata = Vec::new();
+        if version >= INDEXED_FORMAT_VERSION {
+            let metadata_len = trailer_offset
+                .checked_sub(metadata_offset)
+                .ok_or_else(|| invalid_data("sstable metadata offset is out of bounds"))?;
+            metadata.resize(metadata_len, 0);
+            file.seek(SeekFrom::Start(metadata_offset as u64))?;
+            file.read_exact(&mut metadata)?;
+        }
+
+        Self::from_parts(metadata_offset, version, &metadata)
+            .ok_or_else(|| invalid_data("sstable metadata block is truncated"))
+    }
+
+    fn from_parts(metadata_offset: usize, version: u32, metadata: &[u8]) -> Option<Self> {
+        let metadata = if version >= CHECKSUM_FORMAT_VERSION {
+            let (handles, stored) = metadata.split_at(metadata.len().checked_sub(CHECKSUM_SIZE)?);
+            if !verify_checksum(handles, stored) {
+                return None;
+            }
+            handles
+        } else {
+            metadata
+        };
+
+        let read_handle = |position: usize| {
+            let start = position * HANDLE_SIZE;
+            let handle = metadata.get(start..start + HANDLE_SIZE)?;
+            Some(BlockHandle {
+                offset: LittleEndian::read_u32(&handle[0..4]) as usize,
+                len: LittleEndian::read_u32(&handle[4..8]) as usize,
+            })
+        };
+
+        let handle_since = |position: usize, since_version: u32| {
+            if version >= since_version {
+                read_handle(position).map(Some)
+            } else {
+                Some(None)
+            }
+        };
+
+        Some(Footer {
+            metadata_offset,
+            version,
+            index_handle: handle_since(0, INDEXED_FORMAT_VERSION)?,
+            filter_handle: handle_since(1, FILTERED_FORMAT_VERSION)?,
+            properties_handle: handle_since(2, PROPERTIES_FORMAT_VERSION)?,
+            prefix_filter_handle: handle_since(3, PREFIX_FILTER_FORMAT_VERSION)?,
+        })
+    }
+}
+
+fn decode_trailer(trailer: &[u8]) -> (usize, u32) {
+    (
+        LittleEndian::read_u32(&trailer[0..4]) as usize,
+        LittleEndian::read_u32(&trailer[4..8]),
+    )
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
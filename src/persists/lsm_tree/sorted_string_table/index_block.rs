@@ -0,0 +1,73 @@
+use std::ops::Range;
+
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+
+use super::sst_table_block::HEADER_SIZE;
+
+/// Location of a single data block, `first_key` points into the table buffer.
+#[derive(Debug, Clone)]
+pub struct IndexEntry {
+    pub first_key: Range<usize>,
+    pub offset: usize,
+    pub len: usize,
+}
+
+/// Collects `key_len | first_key | offset | len` for every data block written.
+#[derive(Default)]
+pub struct IndexBlockBuilder {
+    buffer: Vec<u8>,
+}
+
+impl IndexBlockBuilder {
+    pub fn add(&mut self, first_key: &[u8], offset: u32, len: u32) {
+        //TODO handle errors
+        self.buffer
+            .write_u32::<LittleEndian>(first_key.len() as u32)
+            .unwrap();
+        self.buffer.extend_from_slice(first_key);
+        self.buffer.write_u32::<LittleEndian>(offset).unwrap();
+        self.buffer.write_u32::<LittleEndian>(len).unwrap();
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+/// Parses the index block stored in `buffer[start..end]`.
+/// Returns `None` if the block is truncated.
+pub fn parse_index_block(buffer: &[u8], start: usize, end: usize) -> Option<Vec<IndexEntry>> {
+    if end > buffer.len() || start > end {
+        return None;
+    }
+
+    let mut entries = Vec::new();
+    let mut offset = start;
+
+    while offset < end {
+        if offset + HEADER_SIZE > end {
+            return None;
+        }
+        let key_length = LittleEndian::read_u32(&buffer[offset..offset + HEADER_SIZE]) as usize;
+        offset += HEADER_SIZE;
+
+        if offset + key_length + 2 * HEADER_SIZE > end {
+            return None;
+        }
+        let first_key = offset..offset + key_length;
+        offset += key_length;
+
+        let block_offset = LittleEndian::read_u32(&buffer[offset..offset + HEADER_SIZE]) as usize;
+        offset += HEADER_SIZE;
+        let block_len = LittleEndian::read_u32(&buffer[offset..offset + HEADER_SIZE]) as usize;
+        offset += HEADER_SIZE;
+
+        entries.push(IndexEntry {
+            first_key,
+            offset: block_offset,
+            len: block_len,
+        });
+    }
+
+    Some(entries)
+}
@@ -1,9 +1,23 @@
+#[allow(clippy::module_inception)]
 pub mod sorted_string_table;
+#[cfg(test)]
 mod sorted_string_table_test;
 // mod sst;
+pub mod table_error;
+pub mod table_properties;
+#[cfg(test)]
+mod table_properties_test;
 pub mod table_result;
 
-mod block_entry;
+pub(crate) mod block_entry;
+pub mod bloom_filter;
+#[cfg(test)]
+mod bloom_filter_test;
+pub mod compression;
+#[cfg(test)]
+mod compression_test;
+mod footer;
+mod index_block;
 
 pub mod flush_worker;
 #[cfg(test)]
@@ -1,24 +1,36 @@
-use std::{error::Error, fs::File, ops::Range, path::Path, sync::Arc};
+use std::{
+    fs::File,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
 
 use byteorder::{ByteOrder, LittleEndian};
 use memmap2::Mmap;
 
 use crate::persists::lsm_tree::sorted_string_table::{
-    sst_table_block::{BLOCK_SIZE, HEADER_SIZE},
-    table_result::TableResult,
+    bloom_filter::{self, BloomFilterCounters, BloomFilterStats},
+    compression::CompressionType,
+    footer::{BlockHandle, Footer, TRAILER_SIZE},
+    index_block::{IndexEntry, parse_index_block},
+    sst_table_block::{
+        BLOCK_SIZE, ENTRY_KIND_FORMAT_VERSION, FORMAT_VERSION, HEADER_SIZE, LEGACY_FORMAT_VERSION,
+    },
+    table_error::TableError,
+    table_properties::TableProperties,
+    table_result::{EntryKind, TableResult},
 };
 
+/// Position of a single entry relative to the start of its data block.
 struct DataEntryBlock {
-    data_buffer: Arc<Mmap>,
     key_range: Range<usize>,
     value_range: Range<usize>,
-    _offset: usize,
-    //TODO use ref here
     seq_number: u64,
+    kind: EntryKind,
 }
 
 impl DataEntryBlock {
-    fn parse_from_offset(buffer: Arc<Mmap>, mut offset: usize) -> Option<(Self, usize)> {
+    fn parse_from_offset(buffer: &[u8], mut offset: usize, version: u32) -> Option<(Self, usize)> {
         if offset + HEADER_SIZE > buffer.len() {
             return None;
         }
@@ -33,6 +45,18 @@ impl DataEntryBlock {
         let key_range = offset..offset + key_length;
         offset += key_length;
 
+        // legacy tables have no kind byte, deletions there are indistinguishable from empty values
+        let kind = if version < ENTRY_KIND_FORMAT_VERSION {
+            EntryKind::Value
+        } else {
+            if offset + 1 > buffer.len() {
+                return None;
+            }
+            let kind = EntryKind::from_byte(buffer[offset])?;
+            offset += 1;
+            kind
+        };
+
         if offset + HEADER_SIZE > buffer.len() {
             return None;
         }
@@ -40,6 +64,10 @@ impl DataEntryBlock {
         let value_length = LittleEndian::read_u32(&buffer[offset..offset + HEADER_SIZE]) as usize;
         offset += HEADER_SIZE;
 
+        if offset + value_length > buffer.len() {
+            return None;
+        }
+
         let value_range = offset..offset + value_length;
         offset += value_length;
 
@@ -52,163 +80,430 @@ impl DataEntryBlock {
 
         Some((
             DataEntryBlock {
-                data_buffer: buffer,
                 key_range,
                 value_range,
-                _offset: offset,
                 seq_number: seq,
+                kind,
             },
             offset,
         ))
     }
-
-    fn key(&self) -> &[u8] {
-        &self.data_buffer[self.key_range.clone()]
-    }
-
-    fn value(&self) -> &[u8] {
-        &self.data_buffer[self.value_range.clone()]
-    }
-
-    fn get(&self) -> TableResult {
-        TableResult {
-            _mmap: Arc::clone(&self.data_buffer),
-            key: self.key(),
-            value: self.value(),
-            sequence_number: self.seq_number,
-        }
-    }
 }
 
-struct DataBlock {
-    _data_buffer: Arc<Mmap>,
-    blocks: Vec<DataEntryBlock>,
+/// A single decoded data block, entries are kept in key order.
+struct DataBlock<'a> {
+    buffer: &'a [u8],
+    mmap: &'a Arc<Mmap>,
+    entries: Vec<DataEntryBlock>,
 }
 
-impl DataBlock {
-    fn from_buffer(buffer: &Arc<Mmap>, start: usize, end: usize) -> Self {
-        let mut parsed_blocks = Vec::new();
-        let mut offset = 0;
+impl<'a> DataBlock<'a> {
+    /// Decodes a stored block, compressed blocks are decompressed into `decompressed` once
+    /// and later reads of the block reuse it. Returns `None` if decompression fails.
+    fn from_buffer(
+        mmap: &'a Arc<Mmap>,
+        stored: &'a [u8],
+        compression: CompressionType,
+        decompressed: &'a OnceLock<Box<[u8]>>,
+        version: u32,
+    ) -> Option<Self> {
+        let buffer: &'a [u8] = match compression {
+            CompressionType::None => stored,
+            codec => match decompressed.get() {
+                Some(buffer) => buffer,
+                None => {
+                    let buffer = codec.decompress(stored)?.into_owned().into_boxed_slice();
+                    decompressed.get_or_init(|| buffer)
+                }
+            },
+        };
 
-        while start + offset < end {
-            let abs_offset = start + offset;
+        let mut entries = Vec::new();
+        let mut offset = 0;
 
+        while offset < buffer.len() {
             if let Some((entry, next_offset)) =
-                DataEntryBlock::parse_from_offset(Arc::clone(buffer), abs_offset)
+                DataEntryBlock::parse_from_offset(buffer, offset, version)
             {
-                offset = next_offset - start;
-                parsed_blocks.push(entry);
+                offset = next_offset;
+                entries.push(entry);
             } else {
                 break;
             }
         }
 
-        DataBlock {
-            _data_buffer: Arc::clone(buffer),
-            blocks: parsed_blocks,
-        }
+        Some(DataBlock {
+            buffer,
+            mmap,
+            entries,
+        })
     }
 
-    fn get(&self, key: &[u8]) -> Option<TableResult> {
-        self.blocks
-            .iter()
-            .map(|block| block.get())
-            .find(|res| res.key == key)
+    fn key(&self, entry: &DataEntryBlock) -> &'a [u8] {
+        &self.buffer[entry.key_range.clone()]
     }
 
-    pub fn iter(&self) -> DataBlockIterator {
+    fn result(&self, entry: &DataEntryBlock) -> TableResult<'a> {
+        TableResult::new(
+            Arc::clone(self.mmap),
+            self.key(entry),
+            &self.buffer[entry.value_range.clone()],
+            entry.seq_number,
+            entry.kind,
+        )
+    }
+
+    /// Newest version of `key` up to `snapshot`. Versions are stored newest first, they may
+    /// continue in the next block if this block ends with `key`.
+    fn get_at(&self, key: &[u8], snapshot: u64) -> BlockLookup<'a> {
+        let start = self.entries.partition_point(|entry| self.key(entry) < key);
+        for entry in &self.entries[start..] {
+            if self.key(entry) != key {
+                return BlockLookup::NotFound;
+            }
+            if entry.seq_number <= snapshot {
+                return BlockLookup::Found(self.result(entry));
+            }
+        }
+        BlockLookup::Continue
+    }
+
+    fn into_iter(self) -> DataBlockIterator<'a> {
         DataBlockIterator {
-            inner: self.blocks.iter(),
+            block: self,
+            position: 0,
         }
     }
 }
 
+enum BlockLookup<'a> {
+    Found(TableResult<'a>),
+    NotFound,
+    /// The block ended before a version of the key was found.
+    Continue,
+}
+
 pub struct DataBlockIterator<'a> {
-    inner: std::slice::Iter<'a, DataEntryBlock>,
+    block: DataBlock<'a>,
+    position: usize,
 }
 
 impl<'a> Iterator for DataBlockIterator<'a> {
     type Item = TableResult<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next().map(|entry_block| entry_block.get())
+        let entry = self.block.entries.get(self.position)?;
+        self.position += 1;
+        Some(self.block.result(entry))
     }
 }
 
 pub struct SortedStringTable {
-    first_key: String,
-    last_key: String,
-    data_blocks: Vec<DataBlock>,
-    _mmap: Arc<Mmap>,
-    _meta_data: MetaData,
+    path: PathBuf,
+    properties: TableProperties,
+    index: Vec<IndexEntry>,
+    mmap: Arc<Mmap>,
+    footer: Footer,
+    filter_counters: BloomFilterCounters,
+    /// Decompressed data blocks by block index, kept for the lifetime of the table
+    /// because lookup results borrow from them.
+    decompressed_blocks: Vec<OnceLock<Box<[u8]>>>,
 }
 
 impl SortedStringTable {
-    pub fn new(path: &Path) -> Result<Self, Box<dyn Error>> {
+    pub fn new(path: &Path) -> Result<Self, TableError> {
         let file = File::open(path)?;
         let mmap = unsafe { Mmap::map(&file)? };
         let mmap_arc = Arc::new(mmap);
 
-        let meta_data = read_metadata(&mmap_arc);
+        let footer = Footer::decode(&mmap_arc).ok_or_else(|| {
+            TableError::corruption(
+                path,
+                "metadata block",
+                mmap_arc.len().saturating_sub(TRAILER_SIZE),
+            )
+        })?;
+
+        if !(LEGACY_FORMAT_VERSION..=FORMAT_VERSION).contains(&footer.version) {
+            return Err(TableError::UnsupportedVersion {
+                path: path.to_path_buf(),
+                version: footer.version,
+            });
+        }
 
-        println!("metadata offset{}", meta_data.metadata_offset);
+        let checksummed = footer.is_checksummed();
+
+        let index = match footer.index_handle {
+            Some(handle) => handle
+                .read(&mmap_arc, checksummed)
+                .and_then(|_| {
+                    parse_index_block(&mmap_arc, handle.offset, handle.offset + handle.len)
+                })
+                .ok_or_else(|| TableError::corruption(path, "index block", handle.offset))?,
+            None => build_legacy_index(&mmap_arc, footer.metadata_offset, footer.version),
+        };
+
+        if let Some(handle) = footer.filter_handle
+            && handle.read(&mmap_arc, checksummed).is_none()
+        {
+            return Err(TableError::corruption(path, "filter block", handle.offset));
+        }
+        if let Some(handle) = footer.prefix_filter_handle
+            && handle
+                .read(&mmap_arc, checksummed)
+                .is_none_or(|block| !block.is_empty() && block.len() < HEADER_SIZE)
+        {
+            return Err(TableError::corruption(
+                path,
+                "prefix filter block",
+                handle.offset,
+            ));
+        }
 
-        let mut blocks = Vec::new();
-        for i in (0..meta_data.metadata_offset).step_by(BLOCK_SIZE) {
-            let start = i;
-            let end = (i + BLOCK_SIZE).min(meta_data.metadata_offset);
-            let block = DataBlock::from_buffer(&mmap_arc, start, end);
-            blocks.push(block);
+        let properties = match footer.properties_handle {
+            Some(handle) => handle
+                .read(&mmap_arc, checksummed)
+                .and_then(TableProperties::decode)
+                .ok_or_else(|| TableError::corruption(path, "properties block", handle.offset))?,
+            None => TableProperties::default(),
+        };
+
+        let decompressed_blocks = index.iter().map(|_| OnceLock::new()).collect();
+        let mut table = SortedStringTable {
+            path: path.to_path_buf(),
+            properties,
+            index,
+            mmap: mmap_arc,
+            footer,
+            filter_counters: BloomFilterCounters::default(),
+            decompressed_blocks,
+        };
+
+        if table.footer.properties_handle.is_none() {
+            table.properties = table.collect_properties()?;
         }
 
-        println!("found {}", blocks.len());
+        Ok(table)
+    }
 
-        let first_key = blocks
-            .first()
-            .and_then(|b| b.blocks.first())
-            .map(|entry| String::from_utf8_lossy(entry.key()).to_string())
-            .unwrap_or_default();
+    /// Tables written before the properties block existed are scanned once on open.
+    fn collect_properties(&self) -> Result<TableProperties, TableError> {
+        let mut properties = TableProperties::default();
+        let mut iter = self.iter();
+        for entry in &mut iter {
+            properties.add_entry(
+                entry.key,
+                entry.value.len(),
+                entry.sequence_number,
+                entry.kind,
+            );
+        }
+        if let Some(e) = iter.take_error() {
+            return Err(e);
+        }
 
-        let last_key = blocks
-            .last()
-            .and_then(|b| b.blocks.last())
-            .map(|entry| String::from_utf8_lossy(entry.key()).to_string())
+        properties.creation_time = std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs())
             .unwrap_or_default();
+        Ok(properties)
+    }
 
-        Ok(SortedStringTable {
-            first_key,
-            last_key,
-            data_blocks: blocks,
-            _mmap: mmap_arc,
-            _meta_data: meta_data,
-        })
+    pub fn path(&self) -> &Path {
+        &self.path
     }
 
-    pub fn get(&self, key: &[u8]) -> Option<TableResult> {
-        if key < self.first_key.as_bytes() || key > self.last_key.as_bytes() {
-            return None;
+    pub fn properties(&self) -> &TableProperties {
+        &self.properties
+    }
+
+    /// Size of the table file in bytes.
+    pub fn file_size(&self) -> u64 {
+        self.mmap.len() as u64
+    }
+
+    /// Checks the key range and the bloom filter without reading a data block or
+    /// touching the filter counters. `false` means `key` is definitely not stored.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.overlaps(key, key)
+            && self
+                .filter()
+                .is_none_or(|filter| bloom_filter::may_contain(filter, key))
+    }
+
+    /// Whether any key in `min_key..=max_key` may be stored in this table.
+    pub fn overlaps(&self, min_key: &[u8], max_key: &[u8]) -> bool {
+        self.properties.entry_count > 0
+            && self.properties.min_key.as_slice() <= max_key
+            && self.properties.max_key.as_slice() >= min_key
+    }
+
+    /// Decodes a data block, `Ok(None)` if `block_index` is past the last block.
+    fn block(&self, block_index: usize) -> Result<Option<DataBlock<'_>>, TableError> {
+        let Some(entry) = self.index.get(block_index) else {
+            return Ok(None);
+        };
+        let handle = BlockHandle {
+            offset: entry.offset,
+            len: entry.len,
+        };
+
+        handle
+            .read_data_block(&self.mmap, self.footer.version)
+            .and_then(|(stored, compression)| {
+                DataBlock::from_buffer(
+                    &self.mmap,
+                    stored,
+                    compression,
+                    &self.decompressed_blocks[block_index],
+                    self.footer.version,
+                )
+            })
+            .map(Some)
+            .ok_or_else(|| TableError::corruption(&self.path, "data block", entry.offset))
+    }
+
+    /// Looks up `key`, a deleted key is returned as an entry with [`EntryKind::Tombstone`].
+    pub fn get(&self, key: &[u8]) -> Result<Option<TableResult<'_>>, TableError> {
+        self.get_at(key, u64::MAX)
+    }
+
+    /// Like [`SortedStringTable::get`], but returns the newest version with a sequence number
+    /// up to `snapshot`.
+    pub fn get_at(&self, key: &[u8], snapshot: u64) -> Result<Option<TableResult<'_>>, TableError> {
+        if self.properties.entry_count == 0
+            || key < self.properties.min_key.as_slice()
+            || key > self.properties.max_key.as_slice()
+        {
+            return Ok(None);
         }
 
-        for (i, block) in self.data_blocks.iter().enumerate() {
-            if let Some(result) = block.get(key) {
-                println!("Found in block {i}");
-                return Some(result);
+        let Some(filter) = self.filter() else {
+            return self.get_from_blocks(key, snapshot);
+        };
+
+        if !bloom_filter::may_contain(filter, key) {
+            self.filter_counters.record_miss();
+            return Ok(None);
+        }
+
+        let result = self.get_from_blocks(key, snapshot)?;
+        self.filter_counters.record_hit(result.is_some());
+        Ok(result)
+    }
+
+    fn get_from_blocks(
+        &self,
+        key: &[u8],
+        snapshot: u64,
+    ) -> Result<Option<TableResult<'_>>, TableError> {
+        // the last block whose first key is < key holds the first version of key,
+        // unless key starts the following block
+        let mut block_index = self
+            .index
+            .partition_point(|entry| &self.mmap[entry.first_key.clone()] < key)
+            .saturating_sub(1);
+
+        while let Some(block) = self.block(block_index)? {
+            match block.get_at(key, snapshot) {
+                BlockLookup::Found(result) => return Ok(Some(result)),
+                BlockLookup::NotFound => return Ok(None),
+                BlockLookup::Continue => block_index += 1,
+            }
+        }
+        Ok(None)
+    }
+
+    /// Whether a key starting with `prefix` may be stored in this table, checks the key range
+    /// and, if the table has one and `prefix` is long enough, the prefix bloom filter.
+    pub fn may_contain_prefix(&self, prefix: &[u8]) -> bool {
+        let properties = &self.properties;
+        if properties.entry_count == 0
+            || properties.max_key.as_slice() < prefix
+            || !(properties.min_key.starts_with(prefix) || properties.min_key.as_slice() < prefix)
+        {
+            return false;
+        }
+
+        match self.prefix_filter() {
+            Some((prefix_len, filter)) if prefix.len() >= prefix_len => {
+                bloom_filter::may_contain(filter, &prefix[..prefix_len])
             }
+            _ => true,
         }
-        None
+    }
+
+    fn prefix_filter(&self) -> Option<(usize, &[u8])> {
+        let handle = self.footer.prefix_filter_handle?;
+        let block = self.mmap.get(handle.range()).filter(|f| !f.is_empty())?;
+        let (prefix_len, filter) = block.split_at(HEADER_SIZE);
+        Some((LittleEndian::read_u32(prefix_len) as usize, filter))
+    }
+
+    fn filter(&self) -> Option<&[u8]> {
+        let handle = self.footer.filter_handle?;
+        self.mmap.get(handle.range()).filter(|f| !f.is_empty())
+    }
+
+    pub fn filter_stats(&self) -> BloomFilterStats {
+        self.filter_counters.stats()
     }
 
     pub fn iter(&self) -> SSTableIterator<'_> {
         SSTableIterator {
-            remaining_blocks: self.data_blocks.iter(),
+            table: self,
+            next_block: 0,
             current_block_iter: None,
+            error: None,
         }
     }
+
+    /// Iterates the entries from the first one whose key is >= `start`, the block index
+    /// locates the block to start in so earlier blocks are never read.
+    pub fn iter_from(&self, start: &[u8]) -> SSTableIterator<'_> {
+        let block_index = self
+            .index
+            .partition_point(|entry| &self.mmap[entry.first_key.clone()] < start)
+            .saturating_sub(1);
+        let mut iter = SSTableIterator {
+            table: self,
+            next_block: block_index + 1,
+            current_block_iter: None,
+            error: None,
+        };
+
+        match self.block(block_index) {
+            Ok(Some(block)) => {
+                let position = block
+                    .entries
+                    .partition_point(|entry| block.key(entry) < start);
+                iter.current_block_iter = Some(DataBlockIterator { block, position });
+            }
+            Ok(None) => {}
+            Err(e) => {
+                iter.error = Some(e);
+                iter.next_block = self.index.len();
+            }
+        }
+        iter
+    }
 }
 
+/// Iterates all entries in key order. A corrupted block ends the iteration,
+/// the error is kept and can be retrieved with [`SSTableIterator::take_error`].
 pub struct SSTableIterator<'a> {
-    remaining_blocks: std::slice::Iter<'a, DataBlock>,
+    table: &'a SortedStringTable,
+    next_block: usize,
     current_block_iter: Option<DataBlockIterator<'a>>,
+    error: Option<TableError>,
+}
+
+impl SSTableIterator<'_> {
+    pub fn take_error(&mut self) -> Option<TableError> {
+        self.error.take()
+    }
 }
 
 impl<'a> Iterator for SSTableIterator<'a> {
@@ -217,18 +512,23 @@ impl<'a> Iterator for SSTableIterator<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             //entry from current block
-            if let Some(iter) = &mut self.current_block_iter {
-                if let Some(item) = iter.next() {
-                    return Some(item);
-                }
+            if let Some(iter) = &mut self.current_block_iter
+                && let Some(item) = iter.next()
+            {
+                return Some(item);
             }
             //enter next block
-            match self.remaining_blocks.next() {
-                Some(next_block) => {
-                    self.current_block_iter = Some(next_block.iter());
+            let next_block = match self.table.block(self.next_block) {
+                Ok(next_block) => next_block?,
+                Err(e) => {
+                    self.error = Some(e);
+                    self.current_block_iter = None;
+                    self.next_block = self.table.index.len();
+                    return None;
                 }
-                None => return None,
-            }
+            };
+            self.next_block += 1;
+            self.current_block_iter = Some(next_block.into_iter());
         }
     }
 }
@@ -241,19 +541,21 @@ impl<'a> IntoIterator for &'a SortedStringTable {
     }
 }
 
-struct MetaData {
-    metadata_offset: usize,
-    _version: usize,
-}
-
-fn read_metadata(mmap: &Mmap) -> MetaData {
-    let meta_data_binary = &mmap[mmap.len() - 8..];
-
-    let metadata_offset = LittleEndian::read_u32(&meta_data_binary[0..4]) as usize;
-    let version = LittleEndian::read_u32(&meta_data_binary[4..8]) as usize;
-
-    MetaData {
-        metadata_offset,
-        _version: version,
-    }
+/// Tables written before the index block existed pad every data block to `BLOCK_SIZE`,
+/// so the index can be rebuilt from the first entry of each block.
+fn build_legacy_index(buffer: &[u8], data_end: usize, version: u32) -> Vec<IndexEntry> {
+    let data_end = data_end.min(buffer.len());
+
+    (0..data_end)
+        .step_by(BLOCK_SIZE)
+        .filter_map(|start| {
+            let end = (start + BLOCK_SIZE).min(data_end);
+            let (entry, _) = DataEntryBlock::parse_from_offset(&buffer[..end], start, version)?;
+            Some(IndexEntry {
+                first_key: entry.key_range,
+                offset: start,
+                len: end - start,
+            })
+        })
+        .collect()
 }
@@ -1,10 +1,11 @@
 mod tests {
-    //idk why clippy is marking them as unused here
-    //TODO resolve this
-    #[allow(unused_imports)]
-    use crate::persists::lsm_tree::sorted_string_table::sorted_string_table::SortedStringTable;
-    #[allow(unused_imports)]
-    use std::path::PathBuf;
+    use crate::persists::lsm_tree::sorted_string_table::{
+        footer::Footer, sorted_string_table::SortedStringTable, sst_writer::SSTableWriter,
+        table_result::EntryKind,
+    };
+    use std::path::{Path, PathBuf};
+
+    type Entry = (Vec<u8>, (Option<Vec<u8>>, u64));
 
     #[test]
     fn read_string_table_and_verify() {
@@ -14,7 +15,7 @@ mod tests {
         let string_table = SortedStringTable::new(&file_path).expect("Failed to parse SSTable");
 
         let key = b"key2";
-        let result = string_table.get(key);
+        let result = string_table.get(key).unwrap();
 
         assert!(result.is_some(), "Key not found");
 
@@ -23,7 +24,7 @@ mod tests {
         assert_eq!(entry.key, key);
         assert_eq!(entry.value, b"value2");
 
-        let should_not_be_found = string_table.get(b"key_5");
+        let should_not_be_found = string_table.get(b"key_5").unwrap();
 
         assert!(should_not_be_found.is_none());
 
@@ -33,4 +34,235 @@ mod tests {
             std::str::from_utf8(entry.value).unwrap()
         );
     }
+
+    #[test]
+    fn legacy_table_has_no_tombstones() {
+        let file_path = PathBuf::from("test_snapshots/test_sstable.sst");
+        let string_table = SortedStringTable::new(&file_path).expect("Failed to parse SSTable");
+
+        assert!(
+            string_table
+                .iter()
+                .all(|entry| entry.kind == EntryKind::Value)
+        );
+    }
+
+    #[test]
+    fn iterator_reports_tombstones() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("iter.sst");
+
+        let entries = vec![
+            (b"a".to_vec(), (Some(b"1".to_vec()), 1)),
+            (b"b".to_vec(), (None, 2)),
+            (b"c".to_vec(), (Some(b"3".to_vec()), 3)),
+        ];
+        SSTableWriter::write_to_file(&path, entries, 64).unwrap();
+
+        let string_table = SortedStringTable::new(&path).expect("Failed to parse SSTable");
+        let kinds: Vec<_> = string_table
+            .iter()
+            .map(|entry| (entry.key.to_vec(), entry.kind))
+            .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                (b"a".to_vec(), EntryKind::Value),
+                (b"b".to_vec(), EntryKind::Tombstone),
+                (b"c".to_vec(), EntryKind::Value),
+            ]
+        );
+    }
+
+    fn many_entries(count: usize) -> Vec<Entry> {
+        (0..count)
+            .map(|i| {
+                (
+                    format!("key{i:06}").into_bytes(),
+                    (Some(format!("value{i}").into_bytes()), i as u64),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn point_lookups_across_many_blocks() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("many.sst");
+
+        SSTableWriter::write_to_file(&path, many_entries(5000), 0).unwrap();
+        let string_table = SortedStringTable::new(&path).expect("Failed to parse SSTable");
+
+        for i in (0..5000).step_by(7) {
+            let key = format!("key{i:06}");
+            let entry = string_table
+                .get(key.as_bytes())
+                .unwrap()
+                .unwrap_or_else(|| panic!("{key} not found"));
+            assert_eq!(entry.value, format!("value{i}").as_bytes());
+            assert_eq!(entry.sequence_number, i as u64);
+        }
+
+        assert!(string_table.get(b"key000000a").unwrap().is_none());
+        assert!(string_table.get(b"a").unwrap().is_none());
+        assert!(string_table.get(b"zzz").unwrap().is_none());
+    }
+
+    #[test]
+    fn iter_from_seeks_to_the_first_key_at_or_after_start() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("many.sst");
+
+        SSTableWriter::write_to_file(&path, many_entries(3000), 0).unwrap();
+        let string_table = SortedStringTable::new(&path).expect("Failed to parse SSTable");
+
+        let first = |start: &[u8]| {
+            string_table
+                .iter_from(start)
+                .next()
+                .map(|entry| entry.key.to_vec())
+        };
+        assert_eq!(first(b""), Some(b"key000000".to_vec()));
+        assert_eq!(first(b"key001234"), Some(b"key001234".to_vec()));
+        assert_eq!(first(b"key001234a"), Some(b"key001235".to_vec()));
+        assert_eq!(first(b"zzz"), None);
+        assert_eq!(string_table.iter_from(b"key002990").count(), 10);
+    }
+
+    #[test]
+    fn iterator_spans_all_blocks_in_order() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("many.sst");
+
+        SSTableWriter::write_to_file(&path, many_entries(3000), 0).unwrap();
+        let string_table = SortedStringTable::new(&path).expect("Failed to parse SSTable");
+
+        let keys: Vec<_> = string_table
+            .iter()
+            .map(|entry| entry.key.to_vec())
+            .collect();
+        let expected: Vec<_> = many_entries(3000).into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn oversized_entry_gets_own_block() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("large.sst");
+
+        let large_value = vec![b'x'; 3 * 4096];
+        let entries = vec![
+            (b"a".to_vec(), (Some(b"small".to_vec()), 1)),
+            (b"b".to_vec(), (Some(large_value.clone()), 2)),
+            (b"c".to_vec(), (Some(b"small".to_vec()), 3)),
+        ];
+        SSTableWriter::write_to_file(&path, entries, 0).unwrap();
+
+        let string_table = SortedStringTable::new(&path).expect("Failed to parse SSTable");
+        assert_eq!(
+            string_table.get(b"b").unwrap().unwrap().value,
+            large_value.as_slice()
+        );
+        assert_eq!(string_table.get(b"c").unwrap().unwrap().value, b"small");
+        assert_eq!(string_table.iter().count(), 3);
+    }
+
+    #[test]
+    fn versioned_lookups_span_blocks() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("versions.sst");
+
+        // 300 versions of "b", newest first, spread over several blocks
+        let mut entries: Vec<Entry> = vec![(b"a".to_vec(), (Some(b"a".to_vec()), 1))];
+        entries.extend((0..300u64).rev().map(|seq| {
+            (
+                b"b".to_vec(),
+                (Some(format!("value{seq:032}").into_bytes()), seq * 2 + 10),
+            )
+        }));
+        entries.push((b"c".to_vec(), (None, 5)));
+        SSTableWriter::write_to_file(&path, entries, 0).unwrap();
+        let string_table = SortedStringTable::new(&path).expect("Failed to parse SSTable");
+
+        let newest = string_table.get(b"b").unwrap().unwrap();
+        assert_eq!(newest.sequence_number, 608);
+        for seq in [10, 11, 300, 607] {
+            let entry = string_table.get_at(b"b", seq).unwrap().unwrap();
+            assert_eq!(entry.sequence_number, seq - seq % 2);
+        }
+        assert!(string_table.get_at(b"b", 9).unwrap().is_none());
+        assert!(string_table.get_at(b"c", 4).unwrap().is_none());
+        assert_eq!(
+            string_table.get_at(b"c", 5).unwrap().unwrap().kind,
+            EntryKind::Tombstone
+        );
+    }
+
+    #[test]
+    fn empty_table_has_no_entries() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("empty.sst");
+
+        SSTableWriter::write_to_file(&path, Vec::new(), 0).unwrap();
+
+        let string_table = SortedStringTable::new(&path).expect("Failed to parse SSTable");
+        assert!(string_table.get(b"a").unwrap().is_none());
+        assert_eq!(string_table.iter().count(), 0);
+    }
+
+    fn flip_byte(path: &Path, offset: usize) {
+        let mut bytes = std::fs::read(path).unwrap();
+        bytes[offset] ^= 0xff;
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn corrupted_data_block_fails_lookup() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("corrupt.sst");
+
+        SSTableWriter::write_to_file(&path, many_entries(3000), 0).unwrap();
+        flip_byte(&path, 10);
+
+        let string_table = SortedStringTable::new(&path).expect("data blocks are checked lazily");
+        let error = string_table.get(b"key000000").unwrap_err();
+        assert!(error.is_corruption(), "unexpected error: {error}");
+
+        // blocks after the damaged one are still readable
+        assert!(string_table.get(b"key002999").unwrap().is_some());
+    }
+
+    #[test]
+    fn corrupted_index_block_fails_open() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("corrupt.sst");
+
+        SSTableWriter::write_to_file(&path, many_entries(3000), 0).unwrap();
+        let footer = Footer::decode(&std::fs::read(&path).unwrap()).unwrap();
+        flip_byte(&path, footer.index_handle.unwrap().offset);
+
+        let error = SortedStringTable::new(&path)
+            .err()
+            .expect("open should fail");
+        assert!(error.is_corruption(), "unexpected error: {error}");
+    }
+
+    #[test]
+    fn iterator_stops_at_corrupted_block() {
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("corrupt.sst");
+
+        SSTableWriter::write_to_file(&path, many_entries(3000), 0).unwrap();
+        let footer = Footer::decode(&std::fs::read(&path).unwrap()).unwrap();
+        // last byte of the data section belongs to the final data block
+        flip_byte(&path, footer.index_handle.unwrap().offset - 5);
+
+        let string_table = SortedStringTable::new(&path).unwrap();
+        let mut iter = string_table.iter();
+        let count = iter.by_ref().count();
+
+        assert!(count > 0 && count < 3000);
+        assert!(iter.take_error().unwrap().is_corruption());
+    }
 }
@@ -5,9 +5,27 @@ pub const BLOCK_SIZE: usize = 4096; // 4 KB
 
 pub const HEADER_SIZE: usize = std::mem::size_of::<u32>();
 
+/// Original format: entries carry no kind, deletions were written as empty values.
+pub const LEGACY_FORMAT_VERSION: u32 = 1;
+/// Entries carry an explicit [`EntryKind`](super::table_result::EntryKind) byte.
+pub const ENTRY_KIND_FORMAT_VERSION: u32 = 2;
+/// Data blocks are followed by an index block that is referenced from the metadata block.
+pub const INDEXED_FORMAT_VERSION: u32 = 3;
+/// The metadata block additionally references an optional bloom filter block.
+pub const FILTERED_FORMAT_VERSION: u32 = 4;
+/// The metadata block additionally references a table properties block.
+pub const PROPERTIES_FORMAT_VERSION: u32 = 5;
+/// Every block, including the metadata block, is followed by a CRC32C of its contents.
+pub const CHECKSUM_FORMAT_VERSION: u32 = 6;
+/// Data blocks are no longer padded and may be compressed, the codec is stored before the CRC32C.
+pub const COMPRESSION_FORMAT_VERSION: u32 = 7;
+/// The metadata block additionally references an optional prefix bloom filter block.
+pub const PREFIX_FILTER_FORMAT_VERSION: u32 = 8;
+pub const FORMAT_VERSION: u32 = PREFIX_FILTER_FORMAT_VERSION;
+
 pub struct SSTableBlock {
     entry_buf: Vec<u8>,
-    // start_value: (Vec<u8>, Vec<u8>),
+    first_key: Vec<u8>,
 }
 
 impl Default for SSTableBlock {
@@ -20,21 +38,25 @@ impl SSTableBlock {
     pub fn new() -> Self {
         Self {
             entry_buf: Vec::new(),
-            // start_value: (Vec::new(), Vec::new()),
+            first_key: Vec::new(),
         }
     }
     pub fn append_block(&mut self, mut block_entry: BlockEntry) {
+        if self.entry_buf.is_empty() {
+            self.first_key = block_entry.key().to_vec();
+        }
         self.entry_buf.append(block_entry.get_entry_buffer());
     }
 
+    pub fn first_key(&self) -> &[u8] {
+        &self.first_key
+    }
+
     pub fn capacity(&self) -> usize {
-        BLOCK_SIZE - self.entry_buf.len()
+        BLOCK_SIZE.saturating_sub(self.entry_buf.len())
     }
 
-    pub fn finalize(mut self) -> Vec<u8> {
-        if self.entry_buf.len() < BLOCK_SIZE {
-            self.entry_buf.resize(BLOCK_SIZE, 0);
-        }
+    pub fn finalize(self) -> Vec<u8> {
         self.entry_buf
     }
 
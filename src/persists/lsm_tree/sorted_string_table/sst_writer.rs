@@ -1,143 +1,248 @@
 use std::{
     fs::File,
-    io::{Seek, Write},
+    io::{BufWriter, Write},
     path::{Path, PathBuf},
 };
 
 use byteorder::{LittleEndian, WriteBytesExt};
 
 use crate::persists::lsm_tree::sorted_string_table::{
-    sst_table_block::BLOCK_SIZE, table_result::TableResult,
+    compression::CompressionType,
+    footer::{CHECKSUM_SIZE, DATA_BLOCK_TRAILER_SIZE},
+    sst_table_block::BLOCK_SIZE,
+    table_properties::TableProperties,
+    table_result::{EntryKind, TableResult},
 };
 
-use super::{block_entry::BlockEntry, sst_table_block::SSTableBlock};
+use super::{
+    block_entry::BlockEntry,
+    bloom_filter::{BloomFilterBuilder, DEFAULT_BITS_PER_KEY},
+    footer::Footer,
+    index_block::IndexBlockBuilder,
+    sst_table_block::SSTableBlock,
+};
+
+/// Block offsets in a table are `u32`, compaction outputs are cut at this size so the rest
+/// of the last key and the meta blocks still fit.
+pub const MAX_TABLE_SIZE: u64 = u32::MAX as u64 / 2;
 
 type EntryType = Vec<(Vec<u8>, (Option<Vec<u8>>, u64))>;
+
+#[derive(Debug, Clone)]
+pub struct TableOptions {
+    /// Bloom filter bits per key, `0` disables the filter block.
+    pub bits_per_key: usize,
+    /// Level recorded in the table properties.
+    pub level: u32,
+    /// Codec for data blocks, blocks that do not shrink are stored uncompressed.
+    pub compression: CompressionType,
+    /// Length of the key prefixes added to a prefix bloom filter, `None` writes no prefix filter.
+    /// Prefix scans for a prefix at least this long can skip tables the filter rejects.
+    pub prefix_len: Option<usize>,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        Self {
+            bits_per_key: DEFAULT_BITS_PER_KEY,
+            level: 0,
+            compression: CompressionType::None,
+            prefix_len: None,
+        }
+    }
+}
+
+/// Writes `data blocks | index block | filter block | properties block | prefix filter block | metadata block | metadata_offset | version`.
+/// The metadata block holds offset and length of the index, filter, properties and prefix filter block.
+/// The prefix filter block is `prefix_len u32 | filter`, or empty if the table has none.
+/// Data blocks are followed by `codec | crc32c`, all other blocks by their `crc32c`.
 pub struct SSTableWriter {
-    file: File,
-    written_blocks_count: u32,
+    file: BufWriter<File>,
     current_block: SSTableBlock,
+    index: IndexBlockBuilder,
+    filter: Option<BloomFilterBuilder>,
+    prefix_filter: Option<(usize, BloomFilterBuilder)>,
+    properties: TableProperties,
+    compression: CompressionType,
+    written_bytes: u32,
     path: PathBuf,
 }
 
 impl SSTableWriter {
+    #[allow(dead_code)]
     pub fn new<P: Into<PathBuf>>(path: P) -> std::io::Result<Self> {
+        Self::new_with_options(path, &TableOptions::default())
+    }
+
+    pub fn new_with_options<P: Into<PathBuf>>(
+        path: P,
+        options: &TableOptions,
+    ) -> std::io::Result<Self> {
         let path_buf: PathBuf = path.into();
         let file = File::create(&path_buf)?;
 
-        Ok(Self {
-            file,
+        Ok(Self::from_file(file, path_buf, BLOCK_SIZE, options))
+    }
+
+    fn from_file(file: File, path: PathBuf, buffer_size: usize, options: &TableOptions) -> Self {
+        Self {
+            file: BufWriter::with_capacity(buffer_size.max(BLOCK_SIZE), file),
             current_block: SSTableBlock::new(),
-            path: path_buf,
-            written_blocks_count: 0,
-        })
+            index: IndexBlockBuilder::default(),
+            filter: (options.bits_per_key > 0)
+                .then(|| BloomFilterBuilder::new(options.bits_per_key)),
+            prefix_filter: options
+                .prefix_len
+                .map(|prefix_len| (prefix_len, BloomFilterBuilder::new(DEFAULT_BITS_PER_KEY))),
+            properties: TableProperties::new(options.level),
+            compression: options.compression,
+            written_bytes: 0,
+            path,
+        }
     }
 
+    #[allow(dead_code)]
     pub fn write_to_file(
         path: &Path,
         entries: EntryType,
         size: u32,
     ) -> Result<usize, std::io::Error> {
-        let mut file = File::create_new(path)?;
-
-        let mut blocks: Vec<SSTableBlock> = Vec::new();
-
-        let mut data_buffer: Vec<u8> = Vec::with_capacity(size as usize);
+        Self::write_to_file_with_options(path, entries, size, &TableOptions::default())
+    }
 
-        let mut current_block = SSTableBlock::new();
+    pub fn write_to_file_with_options(
+        path: &Path,
+        entries: EntryType,
+        size: u32,
+        options: &TableOptions,
+    ) -> Result<usize, std::io::Error> {
+        let file = File::create_new(path)?;
+        let mut writer = Self::from_file(file, path.to_path_buf(), size as usize, options);
 
         for (key, (value_opt, seq_number)) in &entries {
-            let block_entry =
-                BlockEntry::from_parts(key, &value_opt.clone().unwrap_or_default(), seq_number);
-
-            if block_entry.can_fit(&current_block) {
-                current_block.append_block(block_entry);
-            } else {
-                blocks.push(current_block);
-                current_block = SSTableBlock::new();
-                current_block.append_block(block_entry);
-            }
+            writer.append(key, value_opt.as_deref(), seq_number)?;
         }
 
-        if !current_block.is_empty() {
-            blocks.push(current_block);
-        }
+        let data_len = writer.write_footer()?;
+        writer.file.get_ref().sync_all()?;
+        Ok(data_len)
+    }
 
-        for block in blocks {
-            let padded_block = block.finalize();
-            data_buffer.extend_from_slice(&padded_block);
-        }
+    pub fn append_entry(&mut self, entry: &TableResult) -> std::io::Result<()> {
+        self.append(entry.key, entry.value(), &entry.sequence_number)
+    }
 
-        file.write_all(&data_buffer)?;
+    /// Bytes written so far, the pending block is not included.
+    pub fn written_bytes(&self) -> u64 {
+        self.written_bytes as u64
+    }
 
-        let metadata_offset = data_buffer.len() as u32;
-        let version: u32 = 1;
+    fn append(
+        &mut self,
+        key: &[u8],
+        value: Option<&[u8]>,
+        seq_number: &u64,
+    ) -> std::io::Result<()> {
+        let block_entry = BlockEntry::from_parts(key, value, seq_number);
+        if let Some(filter) = &mut self.filter {
+            filter.add(key);
+        }
+        if let Some((prefix_len, filter)) = &mut self.prefix_filter {
+            // shorter keys can never match a prefix the filter is consulted for
+            filter.add(&key[..key.len().min(*prefix_len)]);
+        }
+        let kind = match value {
+            Some(_) => EntryKind::Value,
+            None => EntryKind::Tombstone,
+        };
+        self.properties
+            .add_entry(key, value.map_or(0, <[u8]>::len), *seq_number, kind);
+
+        // oversized entries get a block of their own
+        if !self.current_block.is_empty() && !block_entry.can_fit(&self.current_block) {
+            self.write_block()?;
+        }
+        self.current_block.append_block(block_entry);
+        Ok(())
+    }
 
-        file.write_u32::<LittleEndian>(metadata_offset).unwrap();
-        file.write_u32::<LittleEndian>(version).unwrap();
+    fn write_block(&mut self) -> std::io::Result<()> {
+        let block = std::mem::take(&mut self.current_block);
+        let first_key = block.first_key().to_vec();
+        let raw_block = block.finalize();
+        let (stored_block, compression) = self.compression.compress(&raw_block)?;
+
+        let offset = self.written_bytes;
+        let codec = [compression.as_byte()];
+        let checksum = crc32c::crc32c_append(crc32c::crc32c(&stored_block), &codec);
+
+        self.file.write_all(&stored_block)?;
+        self.file.write_all(&codec)?;
+        self.file.write_u32::<LittleEndian>(checksum)?;
+        self.written_bytes += (stored_block.len() + DATA_BLOCK_TRAILER_SIZE) as u32;
+        self.index
+            .add(&first_key, offset, stored_block.len() as u32);
+        Ok(())
+    }
 
-        Ok(data_buffer.len())
+    /// Writes `block | crc32c(block)` and returns the handle of the block.
+    fn write_checksummed(&mut self, block: &[u8]) -> std::io::Result<(u32, u32)> {
+        let offset = self.written_bytes;
+        self.file.write_all(block)?;
+        self.file.write_u32::<LittleEndian>(crc32c::crc32c(block))?;
+        self.written_bytes += (block.len() + CHECKSUM_SIZE) as u32;
+        Ok((offset, block.len() as u32))
     }
 
-    pub fn append_entry(&mut self, entry: &TableResult) {
-        //TODO use actully size of seq number
-        println!("appending {:?}", entry);
-
-        let TableResult {
-            key,
-            value,
-            sequence_number,
-            ..
-        } = entry;
-
-        let block_entry = BlockEntry::from_parts(key, value, sequence_number);
-
-        if block_entry.can_fit(&self.current_block) {
-            self.current_block.append_block(block_entry);
-        } else {
-            let block = std::mem::take(&mut self.current_block);
-            let padded_block = block.finalize();
-            self.written_blocks_count += 1;
-            println!(
-                "increased offset to metadata offset: {}",
-                self.written_blocks_count
-            );
-            self.file
-                .write_all(&padded_block)
-                .expect("failed to write block");
-            self.current_block.append_block(block_entry);
+    /// Writes the pending block, the index and the metadata, returns the size of the data section.
+    fn write_footer(&mut self) -> std::io::Result<usize> {
+        if !self.current_block.is_empty() {
+            self.write_block()?;
         }
+
+        let data_len = self.written_bytes;
+        let index_block = std::mem::take(&mut self.index).finish();
+        let index_handle = self.write_checksummed(&index_block)?;
+
+        let filter_block = self
+            .filter
+            .take()
+            .map(BloomFilterBuilder::finish)
+            .unwrap_or_default();
+        let filter_handle = self.write_checksummed(&filter_block)?;
+
+        self.properties.stamp_creation_time();
+        let properties_block = self.properties.encode()?;
+        let properties_handle = self.write_checksummed(&properties_block)?;
+
+        let prefix_filter_block = match self.prefix_filter.take() {
+            Some((prefix_len, filter)) => {
+                let mut block = (prefix_len as u32).to_le_bytes().to_vec();
+                block.extend(filter.finish());
+                block
+            }
+            None => Vec::new(),
+        };
+        let prefix_filter_handle = self.write_checksummed(&prefix_filter_block)?;
+
+        let footer = Footer::encode(
+            self.written_bytes,
+            &[
+                index_handle,
+                filter_handle,
+                properties_handle,
+                prefix_filter_handle,
+            ],
+        )?;
+        self.file.write_all(&footer)?;
+        self.file.flush()?;
+
+        Ok(data_len as usize)
     }
 
     pub fn finalize(mut self) -> Result<PathBuf, std::io::Error> {
-        let block = std::mem::take(&mut self.current_block);
-        let padded_block = block.finalize();
-        self.written_blocks_count += 1;
-        println!(
-            "increased offset to metadata offset: {}",
-            self.written_blocks_count
-        );
-        self.file
-            .write_all(&padded_block)
-            .expect("failed to write block");
-
-        let version: u32 = 1;
-
-        println!(
-            "metadata offset: {}",
-            self.written_blocks_count * BLOCK_SIZE as u32
-        );
-
-        // let metadata_offset = self.written_blocks_count * BLOCK_SIZE as u32;
-
-        let metadata_offset = self.file.stream_position()? as u32;
-        self.file
-            .write_u32::<LittleEndian>(metadata_offset)
-            .unwrap();
-        self.file.write_u32::<LittleEndian>(version).unwrap();
-
-        self.file.flush()?;
-        self.file.sync_all()?;
+        self.write_footer()?;
+        self.file.get_ref().sync_all()?;
         Ok(self.path)
     }
 }
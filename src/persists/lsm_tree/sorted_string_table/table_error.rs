@@ -0,0 +1,67 @@
+use std::{fmt, path::PathBuf};
+
+#[derive(Debug)]
+pub enum TableError {
+    Io(std::io::Error),
+    /// A block failed its checksum or could not be decoded.
+    Corruption {
+        path: PathBuf,
+        block: &'static str,
+        offset: usize,
+    },
+    UnsupportedVersion {
+        path: PathBuf,
+        version: u32,
+    },
+}
+
+impl TableError {
+    pub fn corruption(path: impl Into<PathBuf>, block: &'static str, offset: usize) -> Self {
+        TableError::Corruption {
+            path: path.into(),
+            block,
+            offset,
+        }
+    }
+
+    pub fn is_corruption(&self) -> bool {
+        matches!(self, TableError::Corruption { .. })
+    }
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableError::Io(e) => write!(f, "sstable io error: {e}"),
+            TableError::Corruption {
+                path,
+                block,
+                offset,
+            } => write!(
+                f,
+                "corrupted {block} at offset {offset} in sstable {}",
+                path.display()
+            ),
+            TableError::UnsupportedVersion { path, version } => write!(
+                f,
+                "unsupported sstable format version {version} in {}",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TableError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TableError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for TableError {
+    fn from(e: std::io::Error) -> Self {
+        TableError::Io(e)
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
+
+use super::{
+    footer::{BlockHandle, CHECKSUM_SIZE, Footer},
+    table_result::EntryKind,
+};
+
+/// Six `u64` counters followed by the `u32` level.
+const FIXED_SIZE: usize = 6 * 8 + 4;
+
+/// Summary of a table, written into the properties block by `SSTableWriter`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TableProperties {
+    pub entry_count: u64,
+    pub tombstone_count: u64,
+    pub min_sequence_number: u64,
+    pub max_sequence_number: u64,
+    pub min_key: Vec<u8>,
+    pub max_key: Vec<u8>,
+    /// Sum of key and value lengths before block encoding.
+    pub raw_data_size: u64,
+    /// Seconds since the unix epoch.
+    pub creation_time: u64,
+    /// Level the table was produced for, flushed tables are level 0.
+    pub level: u32,
+}
+
+impl TableProperties {
+    pub fn new(level: u32) -> Self {
+        Self {
+            level,
+            ..Self::default()
+        }
+    }
+
+    /// Updates the counters with an entry, entries must be added in key order.
+    pub fn add_entry(&mut self, key: &[u8], value_len: usize, seq_number: u64, kind: EntryKind) {
+        if self.entry_count == 0 {
+            self.min_key = key.to_vec();
+            self.min_sequence_number = seq_number;
+            self.max_sequence_number = seq_number;
+        }
+        self.max_key.clear();
+        self.max_key.extend_from_slice(key);
+
+        self.entry_count += 1;
+        if kind == EntryKind::Tombstone {
+            self.tombstone_count += 1;
+        }
+        self.min_sequence_number = self.min_sequence_number.min(seq_number);
+        self.max_sequence_number = self.max_sequence_number.max(seq_number);
+        self.raw_data_size += (key.len() + value_len) as u64;
+    }
+
+    pub fn stamp_creation_time(&mut self) {
+        self.creation_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+    }
+
+    pub fn encode(&self) -> std::io::Result<Vec<u8>> {
+        let mut buffer =
+            Vec::with_capacity(FIXED_SIZE + 8 + self.min_key.len() + self.max_key.len());
+        buffer.write_u64::<LittleEndian>(self.entry_count)?;
+        buffer.write_u64::<LittleEndian>(self.tombstone_count)?;
+        buffer.write_u64::<LittleEndian>(self.min_sequence_number)?;
+        buffer.write_u64::<LittleEndian>(self.max_sequence_number)?;
+        buffer.write_u64::<LittleEndian>(self.raw_data_size)?;
+        buffer.write_u64::<LittleEndian>(self.creation_time)?;
+        buffer.write_u32::<LittleEndian>(self.level)?;
+        buffer.write_u32::<LittleEndian>(self.min_key.len() as u32)?;
+        buffer.extend_from_slice(&self.min_key);
+        buffer.write_u32::<LittleEndian>(self.max_key.len() as u32)?;
+        buffer.extend_from_slice(&self.max_key);
+        Ok(buffer)
+    }
+
+    pub fn decode(buffer: &[u8]) -> Option<Self> {
+        let fixed = buffer.get(0..FIXED_SIZE)?;
+        let mut offset = FIXED_SIZE;
+
+        let mut read_key = || {
+            let len = LittleEndian::read_u32(buffer.get(offset..offset + 4)?) as usize;
+            offset += 4;
+            let key = buffer.get(offset..offset + len)?.to_vec();
+            offset += len;
+            Some(key)
+        };
+        let min_key = read_key()?;
+        let max_key = read_key()?;
+
+        Some(Self {
+            entry_count: LittleEndian::read_u64(&fixed[0..8]),
+            tombstone_count: LittleEndian::read_u64(&fixed[8..16]),
+            min_sequence_number: LittleEndian::read_u64(&fixed[16..24]),
+            max_sequence_number: LittleEndian::read_u64(&fixed[24..32]),
+            raw_data_size: LittleEndian::read_u64(&fixed[32..40]),
+            creation_time: LittleEndian::read_u64(&fixed[40..48]),
+            level: LittleEndian::read_u32(&fixed[48..52]),
+            min_key,
+            max_key,
+        })
+    }
+
+    /// Reads the properties block without mapping the data section.
+    /// Returns `None` for tables written before the properties block existed.
+    pub fn read_from_file(path: &Path) -> std::io::Result<Option<Self>> {
+        let mut file = File::open(path)?;
+        let footer = Footer::read_from(&mut file)?;
+
+        let Some(handle) = footer.properties_handle else {
+            return Ok(None);
+        };
+
+        let checksummed = footer.is_checksummed();
+        let mut buffer = vec![0u8; handle.len + if checksummed { CHECKSUM_SIZE } else { 0 }];
+        file.seek(SeekFrom::Start(handle.offset as u64))?;
+        file.read_exact(&mut buffer)?;
+
+        BlockHandle {
+            offset: 0,
+            len: handle.len,
+        }
+        .read(&buffer, checksummed)
+        .and_then(Self::decode)
+        .map(Some)
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "sstable properties block is corrupted",
+            )
+        })
+    }
+}
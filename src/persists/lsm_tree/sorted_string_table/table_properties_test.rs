@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+
+use crate::persists::lsm_tree::sorted_string_table::{
+    sorted_string_table::SortedStringTable,
+    sst_writer::{SSTableWriter, TableOptions},
+    table_properties::TableProperties,
+};
+
+#[test]
+fn writer_records_table_properties() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = tmpdir.path().join("props.sst");
+
+    let entries = vec![
+        (vec![0x00, 0xff], (Some(b"v1".to_vec()), 7)),
+        (b"b".to_vec(), (None, 3)),
+        (vec![0xfe, 0x80], (Some(b"value".to_vec()), 12)),
+    ];
+    let options = TableOptions {
+        level: 2,
+        ..TableOptions::default()
+    };
+    SSTableWriter::write_to_file_with_options(&path, entries, 0, &options).unwrap();
+
+    let table = SortedStringTable::new(&path).unwrap();
+    let properties = table.properties();
+
+    assert_eq!(properties.entry_count, 3);
+    assert_eq!(properties.tombstone_count, 1);
+    assert_eq!(properties.min_sequence_number, 3);
+    assert_eq!(properties.max_sequence_number, 12);
+    assert_eq!(properties.min_key, vec![0x00, 0xff]);
+    assert_eq!(properties.max_key, vec![0xfe, 0x80]);
+    assert_eq!(properties.raw_data_size, (2 + 2) + 1 + (2 + 5));
+    assert_eq!(properties.level, 2);
+    assert!(properties.creation_time > 0);
+
+    // non utf-8 bounds must not break the key range check
+    assert!(table.get(&[0xfe, 0x80]).unwrap().is_some());
+}
+
+#[test]
+fn properties_are_readable_without_opening_the_table() {
+    let tmpdir = tempfile::tempdir().unwrap();
+    let path = tmpdir.path().join("props.sst");
+
+    let entries = (0..500)
+        .map(|i| (format!("key{i:04}").into_bytes(), (Some(vec![b'x'; 64]), i)))
+        .collect();
+    SSTableWriter::write_to_file(&path, entries, 0).unwrap();
+
+    let from_file = TableProperties::read_from_file(&path)
+        .unwrap()
+        .expect("properties block missing");
+    let table = SortedStringTable::new(&path).unwrap();
+
+    assert_eq!(&from_file, table.properties());
+    assert_eq!(from_file.entry_count, 500);
+    assert_eq!(from_file.max_key, b"key0499");
+}
+
+#[test]
+fn legacy_table_properties_are_collected_on_open() {
+    let file_path = PathBuf::from("test_snapshots/test_sstable.sst");
+
+    assert_eq!(TableProperties::read_from_file(&file_path).unwrap(), None);
+
+    let table = SortedStringTable::new(&file_path).unwrap();
+    let properties = table.properties();
+    assert_eq!(properties.entry_count, 3);
+    assert_eq!(properties.min_key, b"key1");
+    assert_eq!(properties.max_key, b"key3");
+    assert_eq!(properties.tombstone_count, 0);
+}
@@ -2,21 +2,63 @@ use std::{cmp::Ordering, sync::Arc};
 
 use memmap2::Mmap;
 
+/// Marks whether an entry stores a value or records the deletion of its key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EntryKind {
+    Value = 0,
+    Tombstone = 1,
+}
+
+impl EntryKind {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(EntryKind::Value),
+            1 => Some(EntryKind::Tombstone),
+            _ => None,
+        }
+    }
+
+    pub fn as_byte(self) -> u8 {
+        self as u8
+    }
+}
+
 #[derive(Debug)]
 pub struct TableResult<'a> {
     pub(crate) _mmap: Arc<Mmap>,
     pub key: &'a [u8],
     pub value: &'a [u8],
     pub sequence_number: u64,
+    pub kind: EntryKind,
 }
 
 impl<'a> TableResult<'a> {
-    pub fn new(mmap: Arc<Mmap>, key: &'a [u8], value: &'a [u8], sequence_number: u64) -> Self {
+    pub fn new(
+        mmap: Arc<Mmap>,
+        key: &'a [u8],
+        value: &'a [u8],
+        sequence_number: u64,
+        kind: EntryKind,
+    ) -> Self {
         Self {
             _mmap: mmap,
             key,
             value,
             sequence_number,
+            kind,
+        }
+    }
+
+    pub fn is_tombstone(&self) -> bool {
+        self.kind == EntryKind::Tombstone
+    }
+
+    /// The stored value, or `None` if this entry is a tombstone.
+    pub fn value(&self) -> Option<&'a [u8]> {
+        match self.kind {
+            EntryKind::Value => Some(self.value),
+            EntryKind::Tombstone => None,
         }
     }
 }
@@ -1,9 +1,10 @@
 mod tests {
 
     use std::fs;
-    use std::path::PathBuf;
 
-    use crate::persists::lsm_tree::sorted_string_table::sst_writer::SSTableWriter;
+    use crate::persists::lsm_tree::sorted_string_table::{
+        sorted_string_table::SortedStringTable, sst_writer::SSTableWriter, table_result::EntryKind,
+    };
 
     #[test]
     fn test_write_simple_sstable() {
@@ -23,9 +24,8 @@ mod tests {
             })
             .sum();
 
-        let tmp_file = PathBuf::from("test_sstable.sst");
-
-        let _ = fs::remove_file(&tmp_file);
+        let tmpdir = tempfile::tempdir().unwrap();
+        let tmp_file = tmpdir.path().join("test_sstable.sst");
 
         SSTableWriter::write_to_file(&tmp_file, entries, estimated_size as u32)
             .expect("write_to_file failed");
@@ -36,7 +36,28 @@ mod tests {
         assert!(file_size > 0);
         //TODO check metadata later
         // assert_eq!(file_size - 8 % BLOCK_SIZE, 0, "file not block-aligned");
+    }
+
+    #[test]
+    fn tombstone_is_distinct_from_empty_value() {
+        let entries = vec![
+            (b"deleted".to_vec(), (None, 2)),
+            (b"empty".to_vec(), (Some(Vec::new()), 3)),
+        ];
+
+        let tmpdir = tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("tombstones.sst");
+
+        SSTableWriter::write_to_file(&path, entries, 64).expect("write_to_file failed");
+        let table = SortedStringTable::new(&path).expect("Failed to parse SSTable");
+
+        let deleted = table.get(b"deleted").unwrap().expect("tombstone not found");
+        assert_eq!(deleted.kind, EntryKind::Tombstone);
+        assert_eq!(deleted.value(), None);
+        assert_eq!(deleted.sequence_number, 2);
 
-        // let _ = fs::remove_file(&tmp_file);
+        let empty = table.get(b"empty").unwrap().expect("empty value not found");
+        assert_eq!(empty.kind, EntryKind::Value);
+        assert_eq!(empty.value(), Some(&b""[..]));
     }
 }
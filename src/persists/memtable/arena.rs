@@ -0,0 +1,93 @@
+use std::{
+    alloc::{Layout, alloc_zeroed, dealloc, handle_alloc_error},
+    ptr::NonNull,
+    sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+const BLOCK_SIZE: usize = 64 * 1024;
+const ALIGN: usize = 8;
+
+/// Bump allocator backing the skiplist memtable.
+///
+/// Allocations are zeroed, 8 byte aligned and never move or get freed before the arena is
+/// dropped, so the memtable can hand out references into them for as long as it lives.
+pub struct Arena {
+    state: Mutex<ArenaState>,
+    /// Bytes of all blocks, including the unused tail of the current one.
+    memory_usage: AtomicUsize,
+}
+
+struct ArenaState {
+    blocks: Vec<(NonNull<u8>, Layout)>,
+    next: *mut u8,
+    remaining: usize,
+}
+
+// the blocks are owned by the arena, the raw pointers are only shared with the memtable
+unsafe impl Send for Arena {}
+unsafe impl Sync for Arena {}
+
+impl Arena {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(ArenaState {
+                blocks: Vec::new(),
+                next: std::ptr::null_mut(),
+                remaining: 0,
+            }),
+            memory_usage: AtomicUsize::new(0),
+        }
+    }
+
+    /// Zeroed memory for `size` bytes, valid until the arena is dropped.
+    pub fn allocate(&self, size: usize) -> NonNull<u8> {
+        let size = size.next_multiple_of(ALIGN);
+        let mut state = self.state.lock().expect("arena poisoned");
+        if size > state.remaining || state.next.is_null() {
+            // large allocations get their own block, the current one keeps its free tail
+            if size > BLOCK_SIZE / 4 {
+                return self.allocate_block(&mut state, size);
+            }
+            let block = self.allocate_block(&mut state, BLOCK_SIZE);
+            state.next = block.as_ptr();
+            state.remaining = BLOCK_SIZE;
+        }
+
+        let ptr = state.next;
+        // stays within the current block, checked against `remaining` above
+        state.next = unsafe { ptr.add(size) };
+        state.remaining -= size;
+        NonNull::new(ptr).expect("blocks are never null")
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage.load(Ordering::Relaxed)
+    }
+
+    fn allocate_block(&self, state: &mut ArenaState, size: usize) -> NonNull<u8> {
+        let layout = Layout::from_size_align(size, ALIGN).expect("valid block layout");
+        let block = NonNull::new(unsafe { alloc_zeroed(layout) })
+            .unwrap_or_else(|| handle_alloc_error(layout));
+        state.blocks.push((block, layout));
+        self.memory_usage.fetch_add(size, Ordering::Relaxed);
+        block
+    }
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().expect("arena poisoned");
+        for (block, layout) in state.blocks.drain(..) {
+            unsafe { dealloc(block.as_ptr(), layout) };
+        }
+    }
+}
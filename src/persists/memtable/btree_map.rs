@@ -1,91 +1,126 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, mem::size_of, ops::Bound};
 
-use crate::persists::{
-    lsm_tree::sorted_string_table::sst_table_block::HEADER_SIZE,
-    memtable::memtable_trait::MemTableValue,
-};
+use crate::persists::memtable::memtable_trait::MemTableValue;
 
 use super::memtable_trait::{LookupResult, MemTable};
 
+/// Slot of a key and its versions in a B-tree node, the nodes' own headers and free slots
+/// are not counted.
+const ENTRY_SIZE: usize = size_of::<(Vec<u8>, Vec<MemTableValue>)>();
+
+/// Keeps every version of a key, oldest first, so snapshots can read past newer writes.
 #[derive(Debug, Default)]
-pub struct BTreeMemTable<const MAX_SIZE: usize> {
-    data: BTreeMap<Vec<u8>, MemTableValue>,
-    used_bytes: usize,
+pub struct BTreeMemTable {
+    data: BTreeMap<Vec<u8>, Vec<MemTableValue>>,
+    encoded_bytes: usize,
+    memory_usage: usize,
 }
 
-impl<const MAX_SIZE: usize> BTreeMemTable<MAX_SIZE> {
+impl BTreeMemTable {
     pub fn new() -> Self {
         Self {
             data: BTreeMap::new(),
-            used_bytes: 0,
+            encoded_bytes: 0,
+            memory_usage: 0,
         }
     }
 
-    pub fn get_all(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
-        self.data
-            .iter()
-            .filter_map(|(k, v)| v.0.as_ref().map(|v| (k.clone(), v.clone())))
-            .collect()
+    /// Newest version of every key, `None` for deleted keys.
+    pub fn iter_all(&self) -> impl Iterator<Item = (&[u8], Option<&[u8]>)> {
+        self.data.iter().filter_map(|(k, versions)| {
+            let (value, _) = versions.last()?;
+            Some((k.as_slice(), value.as_deref()))
+        })
     }
 
-    #[inline]
-    pub fn encoded_len(key: &[u8], value: &[u8]) -> usize {
-        2 * HEADER_SIZE + key.len() + value.len()
-    }
+    fn push_version(&mut self, key: &[u8], value: Option<Vec<u8>>, seq_number: u64) {
+        if !self.data.contains_key(key) {
+            let key = key.to_vec();
+            self.memory_usage += ENTRY_SIZE + key.capacity();
+            self.data.insert(key, Vec::new());
+        }
+        let versions = self.data.get_mut(key).expect("inserted above");
+        // writers and recovery both insert in sequence order
+        debug_assert!(versions.last().is_none_or(|(_, last)| *last <= seq_number));
 
-    pub fn iter_all(&self) -> impl Iterator<Item = (&[u8], Option<&[u8]>)> {
-        self.data
-            .iter()
-            .map(|(k, v_opt)| (k.as_slice(), v_opt.0.as_deref()))
+        self.encoded_bytes += Self::encoded_len(key, value.as_deref().unwrap_or_default());
+
+        let capacity = versions.capacity();
+        self.memory_usage += value.as_ref().map_or(0, Vec::capacity);
+        versions.push((value, seq_number));
+        self.memory_usage += (versions.capacity() - capacity) * size_of::<MemTableValue>();
     }
 }
 
-impl<const MAX_SIZE: usize> MemTable for BTreeMemTable<MAX_SIZE> {
+impl MemTable for BTreeMemTable {
     fn insert(&mut self, key: &[u8], value: &[u8], seq_number: u64) {
-        // adjust counter if we overwrite an existing value
-        // if let Some(Some(old)) = self.data.get(key) {
-        //     self.used_bytes -= old.len();
-        // }
-        self.used_bytes += Self::encoded_len(key, value);
-        self.data
-            .insert(key.to_vec(), (Some(value.to_vec()), seq_number));
+        self.push_version(key, Some(value.to_vec()), seq_number);
     }
 
     fn delete(&mut self, key: &[u8], seq_number: u64) -> Option<MemTableValue> {
-        if let Some((Some(old_val), _)) = self.data.get(key) {
-            self.used_bytes -= old_val.len();
-        }
-        // Tombstone always adds header + key bytes (value len = 0)
-        self.used_bytes += 2 * HEADER_SIZE + key.len();
-        self.data.insert(key.to_vec(), (None, seq_number))
+        let previous = self
+            .data
+            .get(key)
+            .and_then(|versions| versions.last().cloned());
+        self.push_version(key, None, seq_number);
+        previous
     }
 
-    fn get(&self, key: &[u8]) -> LookupResult {
-        match self.data.get(key) {
+    fn get_at(&self, key: &[u8], snapshot: u64) -> LookupResult<'_> {
+        let visible = self.data.get(key).and_then(|versions| {
+            versions
+                .iter()
+                .rev()
+                .find(|(_, seq_number)| *seq_number <= snapshot)
+        });
+        match visible {
             Some((Some(val), seq_number)) => LookupResult::Found((val, *seq_number)),
             Some((None, seq_number)) => LookupResult::Deleted(*seq_number),
             None => LookupResult::NotFound,
         }
     }
 
-    fn flush(&self) -> Vec<(Vec<u8>, MemTableValue)> {
-        let flushed: Vec<_> = self
-            .data
-            .iter()
-            .map(|(key, (value, seq_number))| (key.clone(), (value.clone(), *seq_number)))
-            .collect();
-        flushed
+    fn range<'a>(
+        &'a self,
+        range: (Bound<&[u8]>, Bound<&[u8]>),
+    ) -> Box<dyn Iterator<Item = (&'a [u8], LookupResult<'a>)> + 'a> {
+        Box::new(
+            self.data
+                .range::<[u8], _>(range)
+                .flat_map(|(key, versions)| {
+                    versions.iter().rev().map(|(value, seq_number)| {
+                        let result = match value {
+                            Some(value) => LookupResult::Found((value.as_slice(), *seq_number)),
+                            None => LookupResult::Deleted(*seq_number),
+                        };
+                        (key.as_slice(), result)
+                    })
+                }),
+        )
     }
 
-    fn has_capacity(&self, additional: usize) -> bool {
-        self.used_bytes + additional <= MAX_SIZE
+    fn flush(&self, snapshots: &[u64]) -> Vec<(Vec<u8>, MemTableValue)> {
+        let mut flushed = Vec::with_capacity(self.data.len());
+        for (key, versions) in &self.data {
+            let mut last_stripe = None;
+            for (value, seq_number) in versions.iter().rev() {
+                // index of the oldest snapshot that sees this version, like compaction does
+                let stripe = snapshots.partition_point(|&snapshot| snapshot < *seq_number);
+                if last_stripe == Some(stripe) {
+                    continue;
+                }
+                last_stripe = Some(stripe);
+                flushed.push((key.clone(), (value.clone(), *seq_number)));
+            }
+        }
+        flushed
     }
 
-    fn bytes_used(&self) -> usize {
-        self.used_bytes
+    fn encoded_bytes(&self) -> usize {
+        self.encoded_bytes
     }
 
-    fn inc_bytes_used(&mut self, delta: usize) {
-        self.used_bytes += delta;
+    fn memory_usage(&self) -> usize {
+        self.memory_usage
     }
 }
@@ -1,11 +1,16 @@
 #[cfg(test)]
 mod tests {
-    use crate::persists::memtable::{
-        btree_map::BTreeMemTable,
-        memtable_trait::{LookupResult, MemTable},
+    use std::ops::Bound;
+
+    use crate::persists::{
+        lsm_tree::sorted_string_table::block_entry::{BlockEntry, ENTRY_OVERHEAD},
+        memtable::{
+            btree_map::BTreeMemTable,
+            memtable_trait::{LookupResult, MemTable, MemTableAccounting, MemTableValue},
+        },
     };
 
-    type ActiveTestMemTable = BTreeMemTable<{ 2 * 64 }>;
+    type ActiveTestMemTable = BTreeMemTable;
 
     #[test]
     fn test_insert_and_get() {
@@ -41,6 +46,121 @@ mod tests {
         assert!(matches!(table.get(b"missing"), LookupResult::NotFound));
     }
 
+    #[test]
+    fn test_get_at_reads_older_versions() {
+        let mut table = ActiveTestMemTable::new();
+        table.insert(b"foo", b"v1", 1);
+        table.insert(b"foo", b"v2", 3);
+        table.delete(b"foo", 5);
+
+        assert!(matches!(table.get_at(b"foo", 0), LookupResult::NotFound));
+        assert!(matches!(
+            table.get_at(b"foo", 2),
+            LookupResult::Found((b"v1", 1))
+        ));
+        assert!(matches!(
+            table.get_at(b"foo", 4),
+            LookupResult::Found((b"v2", 3))
+        ));
+        assert!(matches!(table.get(b"foo"), LookupResult::Deleted(5)));
+    }
+
+    #[test]
+    fn test_flush_keeps_versions_visible_to_snapshots() {
+        let mut table = BTreeMemTable::new();
+        for seq in 1..=4 {
+            table.insert(b"a", format!("v{seq}").as_bytes(), seq);
+        }
+        table.insert(b"b", b"only", 2);
+
+        let seqs = |flushed: Vec<(Vec<u8>, MemTableValue)>| -> Vec<(Vec<u8>, u64)> {
+            flushed.into_iter().map(|(k, (_, seq))| (k, seq)).collect()
+        };
+        assert_eq!(
+            seqs(table.flush(&[])),
+            vec![(b"a".to_vec(), 4), (b"b".to_vec(), 2)]
+        );
+        // the snapshot at 2 sees a@2, the one at 3 sees a@3
+        assert_eq!(
+            seqs(table.flush(&[2, 3])),
+            vec![
+                (b"a".to_vec(), 4),
+                (b"a".to_vec(), 3),
+                (b"a".to_vec(), 2),
+                (b"b".to_vec(), 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_range_yields_every_version_newest_first() {
+        let mut table = BTreeMemTable::new();
+        table.insert(b"a", b"1", 1);
+        table.insert(b"b", b"2", 2);
+        table.insert(b"b", b"3", 3);
+        table.delete(b"c", 4);
+
+        let entries: Vec<_> = table
+            .range((Bound::Excluded(b"a".as_slice()), Bound::Unbounded))
+            .map(|(key, result)| (key.to_vec(), result.sequence_number()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (b"b".to_vec(), Some(3)),
+                (b"b".to_vec(), Some(2)),
+                (b"c".to_vec(), Some(4))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_encoded_bytes_match_the_flushed_entries() {
+        let mut table = BTreeMemTable::new();
+        // a snapshot at every sequence number keeps every version
+        let flushed_len = |table: &BTreeMemTable| -> usize {
+            table
+                .flush(&[1, 2, 3, 4, 5, 6])
+                .iter()
+                .map(|(key, (value, _))| {
+                    BlockEntry::encoded_len(key.len(), value.as_ref().map_or(0, Vec::len))
+                })
+                .sum()
+        };
+
+        table.insert(b"a", b"1", 1);
+        table.insert(b"b", b"22", 2);
+        assert_eq!(table.encoded_bytes(), flushed_len(&table));
+        // overwrites and tombstones add versions, the replaced ones count until the flush
+        table.insert(b"a", b"333", 3);
+        table.delete(b"b", 4);
+        table.delete(b"c", 5);
+        assert_eq!(table.encoded_bytes(), flushed_len(&table));
+        assert_eq!(table.encoded_bytes(), 5 * ENTRY_OVERHEAD + 5 + 6);
+
+        let memory_usage = table.memory_usage();
+        assert!(memory_usage > table.encoded_bytes());
+        table.insert(b"a", b"4444", 6);
+        assert!(table.memory_usage() >= memory_usage + 4);
+    }
+
+    #[test]
+    fn test_usage_by_accounting() {
+        let mut table = BTreeMemTable::new();
+        for seq in 1..=3 {
+            table.insert(b"key", b"value", seq);
+        }
+        assert_eq!(
+            table.usage(MemTableAccounting::EncodedSize),
+            3 * BTreeMemTable::encoded_len(b"key", b"value")
+        );
+        assert_eq!(
+            table.usage(MemTableAccounting::HeapUsage),
+            table.memory_usage()
+        );
+        assert!(table.memory_usage() > 3 * b"value".len());
+    }
+
     // #[test]
     // fn test_flush() {
     //     let seq_number: u64 = 0;
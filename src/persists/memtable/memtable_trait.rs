@@ -1,22 +1,82 @@
+use std::ops::Bound;
+
+use crate::persists::lsm_tree::sorted_string_table::block_entry::BlockEntry;
+
 #[derive(Debug)]
 pub enum LookupResult<'a> {
     NotFound,
     Deleted(u64),
     Found((&'a [u8], u64)),
 }
+
+impl LookupResult<'_> {
+    pub fn sequence_number(&self) -> Option<u64> {
+        match self {
+            LookupResult::NotFound => None,
+            LookupResult::Deleted(seq) => Some(*seq),
+            LookupResult::Found((_, seq)) => Some(*seq),
+        }
+    }
+}
 pub type MemTableValue = (Option<Vec<u8>>, u64);
 
-pub trait MemTable {
+/// What the store holds against the memtable size and the memory budget.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MemTableAccounting {
+    /// [`MemTable::encoded_bytes`], a full memtable flushes to a table of at most its size.
+    #[default]
+    EncodedSize,
+    /// [`MemTable::memory_usage`], the heap including the memtable's own bookkeeping.
+    HeapUsage,
+}
+
+pub trait MemTable: Default + Send + Sync + 'static {
     fn insert(&mut self, key: &[u8], value: &[u8], seq_number: u64);
-    fn get(&self, key: &[u8]) -> LookupResult;
-    // fn range<'a>(
-    //     &'a self,
-    //     range: impl RangeBounds<&'a [u8]>,
-    // ) -> Box<dyn Iterator<Item = (&'a [u8], LookupResult<'a>)> + 'a>;
+    /// Newest version of `key`.
+    fn get(&self, key: &[u8]) -> LookupResult<'_> {
+        self.get_at(key, u64::MAX)
+    }
+    /// Newest version of `key` with a sequence number up to `snapshot`.
+    fn get_at(&self, key: &[u8], snapshot: u64) -> LookupResult<'_>;
+    /// Every version of the keys in `range`, in key order and the newest version of a key first.
+    fn range<'a>(
+        &'a self,
+        range: (Bound<&[u8]>, Bound<&[u8]>),
+    ) -> Box<dyn Iterator<Item = (&'a [u8], LookupResult<'a>)> + 'a>;
+    /// Writes a tombstone and returns the version it replaces.
     fn delete(&mut self, key: &[u8], seq_number: u64) -> Option<MemTableValue>;
-    fn flush(&self) -> Vec<(Vec<u8>, MemTableValue)>;
+    /// Entries in key order, the newest version of a key first. Older versions are only
+    /// included if one of the `snapshots` (ascending) can see them.
+    fn flush(&self, snapshots: &[u64]) -> Vec<(Vec<u8>, MemTableValue)>;
+
+    /// Bytes an entry takes in a table, `value` is empty for a tombstone.
+    #[inline]
+    fn encoded_len(key: &[u8], value: &[u8]) -> usize {
+        BlockEntry::encoded_len(key.len(), value.len())
+    }
+    /// Encoded bytes of every version the memtable holds, the most a flush can write. Versions
+    /// are kept until the flush, so overwrites of a key still fill the memtable.
+    fn encoded_bytes(&self) -> usize;
+    /// Heap bytes held by the memtable, including every version it keeps.
+    fn memory_usage(&self) -> usize;
+    /// Size held against the memtable size and the memory budget, see [`MemTableAccounting`].
+    fn usage(&self, accounting: MemTableAccounting) -> usize {
+        match accounting {
+            MemTableAccounting::EncodedSize => self.encoded_bytes(),
+            MemTableAccounting::HeapUsage => self.memory_usage(),
+        }
+    }
+
+    /// Memtables that synchronize writers themselves return their shared write access, the
+    /// store then writes under the read lock of the active memtable and never blocks readers.
+    fn concurrent(&self) -> Option<&dyn ConcurrentMemTable> {
+        None
+    }
+}
 
-    fn bytes_used(&self) -> usize;
-    fn inc_bytes_used(&mut self, delta: usize);
-    fn has_capacity(&self, value_length: usize) -> bool;
+/// Writes through a shared reference, safe to call from many threads at once.
+pub trait ConcurrentMemTable {
+    fn insert_shared(&self, key: &[u8], value: &[u8], seq_number: u64);
+    /// Writes a tombstone and returns the version it replaces.
+    fn delete_shared(&self, key: &[u8], seq_number: u64) -> Option<MemTableValue>;
 }
@@ -1,5 +1,9 @@
+pub mod arena;
 pub mod btree_map;
 pub mod memtable_trait;
+pub mod skiplist;
 
 #[cfg(test)]
 mod btree_map_test;
+#[cfg(test)]
+mod skiplist_test;
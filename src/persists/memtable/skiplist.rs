@@ -0,0 +1,377 @@
+use std::{
+    cmp::Ordering as KeyOrdering,
+    ops::Bound,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+};
+
+use crate::persists::memtable::{
+    arena::Arena,
+    memtable_trait::{ConcurrentMemTable, LookupResult, MemTable, MemTableValue},
+};
+
+const MAX_HEIGHT: usize = 12;
+/// Every level holds about a quarter of the nodes of the level below.
+const BRANCHING_BITS: u32 = 2;
+const TOMBSTONE: u32 = u32::MAX;
+
+/// Fixed part of a node. In the arena it is followed by `height` next pointers, the key and
+/// the value.
+#[repr(C)]
+struct Node {
+    seq_number: u64,
+    key_len: u32,
+    /// [`TOMBSTONE`] for a delete.
+    value_len: u32,
+    height: u32,
+    tower: [AtomicPtr<Node>; 0],
+}
+
+impl Node {
+    fn size(height: usize, key_len: usize, value_len: usize) -> usize {
+        size_of::<Node>() + height * size_of::<AtomicPtr<Node>>() + key_len + value_len
+    }
+
+    /// Next node at `level`.
+    ///
+    /// # Safety
+    /// `node` has to point to a node of the list with more than `level` levels.
+    unsafe fn next<'a>(node: *const Node, level: usize) -> &'a AtomicPtr<Node> {
+        unsafe {
+            debug_assert!(level < (*node).height as usize);
+            &*(ptr::addr_of!((*node).tower) as *const AtomicPtr<Node>).add(level)
+        }
+    }
+
+    /// # Safety
+    /// `node` has to point to a fully written node that outlives `'a`.
+    unsafe fn key<'a>(node: *const Node) -> &'a [u8] {
+        unsafe {
+            let data = Self::data(node);
+            std::slice::from_raw_parts(data, (*node).key_len as usize)
+        }
+    }
+
+    /// # Safety
+    /// Same as [`Node::key`].
+    unsafe fn value<'a>(node: *const Node) -> Option<&'a [u8]> {
+        unsafe {
+            let value_len = (*node).value_len;
+            (value_len != TOMBSTONE).then(|| {
+                let value = Self::data(node).add((*node).key_len as usize);
+                std::slice::from_raw_parts(value, value_len as usize)
+            })
+        }
+    }
+
+    /// Start of the key, right after the tower.
+    unsafe fn data(node: *const Node) -> *const u8 {
+        unsafe {
+            (ptr::addr_of!((*node).tower) as *const AtomicPtr<Node>).add((*node).height as usize)
+                as *const u8
+        }
+    }
+
+    /// # Safety
+    /// Same as [`Node::key`].
+    unsafe fn lookup_result<'a>(node: *const Node) -> LookupResult<'a> {
+        unsafe {
+            match Self::value(node) {
+                Some(value) => LookupResult::Found((value, (*node).seq_number)),
+                None => LookupResult::Deleted((*node).seq_number),
+            }
+        }
+    }
+}
+
+/// Whether `node` sorts before the version `seq_number` of `key`. Keys are ascending and the
+/// versions of a key newest first, so the first node at or after `(key, snapshot)` is the
+/// newest version a snapshot can see.
+///
+/// # Safety
+/// `node` has to point to a fully written node.
+unsafe fn is_before(node: *const Node, key: &[u8], seq_number: u64) -> bool {
+    unsafe {
+        match Node::key(node).cmp(key) {
+            KeyOrdering::Less => true,
+            KeyOrdering::Equal => (*node).seq_number > seq_number,
+            KeyOrdering::Greater => false,
+        }
+    }
+}
+
+/// Multi-version memtable on a concurrent skiplist, an alternative to
+/// [`BTreeMemTable`](super::btree_map::BTreeMemTable).
+///
+/// Inserts link new nodes with compare-and-swap, so any number of writers can insert through
+/// a shared reference and readers never wait. Nodes live in an [`Arena`] and are only freed
+/// together with the table, which is what keeps lock-free readers safe.
+pub struct SkipListMemTable {
+    arena: Arena,
+    head: NonNull<Node>,
+    /// Height of the tallest node, readers start their search there.
+    height: AtomicUsize,
+    /// Encoded size of every node, see [`MemTable::encoded_bytes`].
+    encoded_bytes: AtomicUsize,
+    random_state: AtomicU64,
+}
+
+// nodes are immutable once linked and the links are atomics
+unsafe impl Send for SkipListMemTable {}
+unsafe impl Sync for SkipListMemTable {}
+
+impl SkipListMemTable {
+    pub fn new() -> Self {
+        let arena = Arena::new();
+        let head = Self::allocate_node(&arena, MAX_HEIGHT, &[], None, 0);
+        Self {
+            arena,
+            head,
+            height: AtomicUsize::new(1),
+            encoded_bytes: AtomicUsize::new(0),
+            random_state: AtomicU64::new(0x2545_f491_4f6c_dd1d),
+        }
+    }
+
+    fn allocate_node(
+        arena: &Arena,
+        height: usize,
+        key: &[u8],
+        value: Option<&[u8]>,
+        seq_number: u64,
+    ) -> NonNull<Node> {
+        let value_len = value.map_or(0, <[u8]>::len);
+        assert!(
+            key.len() < u32::MAX as usize && value_len < TOMBSTONE as usize,
+            "memtable entries are limited to 4 GiB"
+        );
+        let node = arena
+            .allocate(Node::size(height, key.len(), value_len))
+            .cast::<Node>();
+        unsafe {
+            // the arena hands out zeroed memory, so the tower starts out as null pointers
+            node.as_ptr().write(Node {
+                seq_number,
+                key_len: key.len() as u32,
+                value_len: value.map_or(TOMBSTONE, |value| value.len() as u32),
+                height: height as u32,
+                tower: [],
+            });
+            let data = Node::data(node.as_ptr()) as *mut u8;
+            ptr::copy_nonoverlapping(key.as_ptr(), data, key.len());
+            if let Some(value) = value {
+                ptr::copy_nonoverlapping(value.as_ptr(), data.add(key.len()), value.len());
+            }
+        }
+        node
+    }
+
+    fn random_height(&self) -> usize {
+        // splitmix64 over a shared counter, good enough to balance the levels
+        let mut z = self
+            .random_state
+            .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+
+        let mut height = 1;
+        while height < MAX_HEIGHT && z & ((1 << BRANCHING_BITS) - 1) == 0 {
+            height += 1;
+            z >>= BRANCHING_BITS;
+        }
+        height
+    }
+
+    /// First node at or after version `seq_number` of `key`, null at the end of the list.
+    fn seek(&self, key: &[u8], seq_number: u64) -> *const Node {
+        let mut node = self.head.as_ptr() as *const Node;
+        let mut level = self.height.load(Ordering::Relaxed) - 1;
+        loop {
+            let next = unsafe { Node::next(node, level) }.load(Ordering::Acquire);
+            if !next.is_null() && unsafe { is_before(next, key, seq_number) } {
+                node = next;
+            } else if level == 0 {
+                return next;
+            } else {
+                level -= 1;
+            }
+        }
+    }
+
+    /// Nodes at `level` between which version `seq_number` of `key` belongs, searching
+    /// from `node` on.
+    fn find_splice(
+        &self,
+        key: &[u8],
+        seq_number: u64,
+        mut node: *const Node,
+        level: usize,
+    ) -> (*const Node, *mut Node) {
+        loop {
+            let next = unsafe { Node::next(node, level) }.load(Ordering::Acquire);
+            if next.is_null() || !unsafe { is_before(next, key, seq_number) } {
+                return (node, next);
+            }
+            node = next;
+        }
+    }
+
+    fn add(&self, key: &[u8], value: Option<&[u8]>, seq_number: u64) {
+        self.encoded_bytes.fetch_add(
+            Self::encoded_len(key, value.unwrap_or_default()),
+            Ordering::Relaxed,
+        );
+        let height = self.random_height();
+        let node = Self::allocate_node(&self.arena, height, key, value, seq_number).as_ptr();
+        self.height.fetch_max(height, Ordering::Relaxed);
+
+        let mut splice = [(self.head.as_ptr() as *const Node, ptr::null_mut()); MAX_HEIGHT];
+        let mut start = self.head.as_ptr() as *const Node;
+        for level in (0..MAX_HEIGHT).rev() {
+            splice[level] = self.find_splice(key, seq_number, start, level);
+            start = splice[level].0;
+        }
+
+        // linked bottom up, the node is visible to readers once it is in level 0
+        for (level, entry) in splice.iter_mut().enumerate().take(height) {
+            loop {
+                let (prev, next) = *entry;
+                unsafe { Node::next(node, level) }.store(next, Ordering::Relaxed);
+                let linked = unsafe { Node::next(prev, level) }.compare_exchange(
+                    next,
+                    node,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                );
+                if linked.is_ok() {
+                    break;
+                }
+                // another writer linked a node in between, search again from `prev`
+                *entry = self.find_splice(key, seq_number, prev, level);
+            }
+        }
+    }
+
+    /// Newest version of `key`.
+    fn newest(&self, key: &[u8]) -> Option<*const Node> {
+        let node = self.seek(key, u64::MAX);
+        (!node.is_null() && unsafe { Node::key(node) } == key).then_some(node)
+    }
+
+    /// Nodes from `node` on in list order.
+    fn iter_from(&self, node: *const Node) -> impl Iterator<Item = *const Node> + '_ {
+        std::iter::successors((!node.is_null()).then_some(node), |&node| {
+            let next = unsafe { Node::next(node, 0) }.load(Ordering::Acquire);
+            (!next.is_null()).then_some(next as *const Node)
+        })
+    }
+
+    fn first(&self) -> *const Node {
+        unsafe { Node::next(self.head.as_ptr(), 0) }.load(Ordering::Acquire)
+    }
+}
+
+impl Default for SkipListMemTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for SkipListMemTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SkipListMemTable")
+            .field("encoded_bytes", &self.encoded_bytes())
+            .field("memory_usage", &self.memory_usage())
+            .finish()
+    }
+}
+
+impl ConcurrentMemTable for SkipListMemTable {
+    fn insert_shared(&self, key: &[u8], value: &[u8], seq_number: u64) {
+        self.add(key, Some(value), seq_number);
+    }
+
+    fn delete_shared(&self, key: &[u8], seq_number: u64) -> Option<MemTableValue> {
+        let previous = self
+            .newest(key)
+            .map(|node| unsafe { (Node::value(node).map(<[u8]>::to_vec), (*node).seq_number) });
+        self.add(key, None, seq_number);
+        previous
+    }
+}
+
+impl MemTable for SkipListMemTable {
+    fn insert(&mut self, key: &[u8], value: &[u8], seq_number: u64) {
+        self.insert_shared(key, value, seq_number);
+    }
+
+    fn get_at(&self, key: &[u8], snapshot: u64) -> LookupResult<'_> {
+        let node = self.seek(key, snapshot);
+        if node.is_null() || unsafe { Node::key(node) } != key {
+            return LookupResult::NotFound;
+        }
+        unsafe { Node::lookup_result(node) }
+    }
+
+    fn range<'a>(
+        &'a self,
+        range: (Bound<&[u8]>, Bound<&[u8]>),
+    ) -> Box<dyn Iterator<Item = (&'a [u8], LookupResult<'a>)> + 'a> {
+        let first = match range.0 {
+            Bound::Included(start) | Bound::Excluded(start) => self.seek(start, u64::MAX),
+            Bound::Unbounded => self.first(),
+        };
+        let end = range.1.map(<[u8]>::to_vec);
+        let excluded_start = match range.0 {
+            Bound::Excluded(start) => Some(start.to_vec()),
+            _ => None,
+        };
+        Box::new(
+            self.iter_from(first)
+                .map(|node| unsafe { (Node::key(node), node) })
+                .skip_while(move |(key, _)| excluded_start.as_deref() == Some(*key))
+                .take_while(move |(key, _)| match &end {
+                    Bound::Included(end) => *key <= end.as_slice(),
+                    Bound::Excluded(end) => *key < end.as_slice(),
+                    Bound::Unbounded => true,
+                })
+                .map(|(key, node)| (key, unsafe { Node::lookup_result(node) })),
+        )
+    }
+
+    fn delete(&mut self, key: &[u8], seq_number: u64) -> Option<MemTableValue> {
+        self.delete_shared(key, seq_number)
+    }
+
+    fn flush(&self, snapshots: &[u64]) -> Vec<(Vec<u8>, MemTableValue)> {
+        let mut flushed = Vec::new();
+        let mut last: Option<(&[u8], usize)> = None;
+        for node in self.iter_from(self.first()) {
+            let (key, value, seq_number) =
+                unsafe { (Node::key(node), Node::value(node), (*node).seq_number) };
+            // same striping as BTreeMemTable::flush, versions arrive newest first
+            let stripe = snapshots.partition_point(|&snapshot| snapshot < seq_number);
+            if last == Some((key, stripe)) {
+                continue;
+            }
+            last = Some((key, stripe));
+            flushed.push((key.to_vec(), (value.map(<[u8]>::to_vec), seq_number)));
+        }
+        flushed
+    }
+
+    fn encoded_bytes(&self) -> usize {
+        self.encoded_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Bytes taken from the allocator, including the towers and the unused tail of the
+    /// current arena block.
+    fn memory_usage(&self) -> usize {
+        self.arena.memory_usage()
+    }
+
+    fn concurrent(&self) -> Option<&dyn ConcurrentMemTable> {
+        Some(self)
+    }
+}
@@ -0,0 +1,182 @@
+#[cfg(test)]
+mod tests {
+    use std::{ops::Bound, sync::Arc, thread};
+
+    use crate::persists::{
+        lsm_tree::sorted_string_table::block_entry::BlockEntry,
+        memtable::{
+            btree_map::BTreeMemTable,
+            memtable_trait::{ConcurrentMemTable, LookupResult, MemTable},
+            skiplist::SkipListMemTable,
+        },
+    };
+
+    type TestSkipList = SkipListMemTable;
+
+    fn versions(
+        table: &impl MemTable,
+        range: (Bound<&[u8]>, Bound<&[u8]>),
+    ) -> Vec<(Vec<u8>, Option<u64>)> {
+        table
+            .range(range)
+            .map(|(key, result)| (key.to_vec(), result.sequence_number()))
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_get_and_delete() {
+        let mut table = TestSkipList::new();
+        table.insert(b"foo", b"bar", 1);
+        assert!(matches!(
+            table.get(b"foo"),
+            LookupResult::Found((b"bar", 1))
+        ));
+        assert!(matches!(table.get(b"fo"), LookupResult::NotFound));
+        assert!(matches!(table.get(b"foo0"), LookupResult::NotFound));
+
+        assert_eq!(table.delete(b"foo", 2), Some((Some(b"bar".to_vec()), 1)));
+        assert!(matches!(table.get(b"foo"), LookupResult::Deleted(2)));
+        assert_eq!(table.delete(b"missing", 3), None);
+    }
+
+    #[test]
+    fn test_get_at_reads_older_versions() {
+        let mut table = TestSkipList::new();
+        table.insert(b"foo", b"v1", 1);
+        table.insert(b"foo", b"v2", 3);
+        table.delete(b"foo", 5);
+
+        assert!(matches!(table.get_at(b"foo", 0), LookupResult::NotFound));
+        assert!(matches!(
+            table.get_at(b"foo", 2),
+            LookupResult::Found((b"v1", 1))
+        ));
+        assert!(matches!(
+            table.get_at(b"foo", 4),
+            LookupResult::Found((b"v2", 3))
+        ));
+        assert!(matches!(table.get(b"foo"), LookupResult::Deleted(5)));
+    }
+
+    #[test]
+    fn test_range_and_flush_match_the_btree_memtable() {
+        let mut skiplist = TestSkipList::new();
+        let mut btree = BTreeMemTable::new();
+        for (i, key) in [b"b", b"a", b"c", b"b", b"a", b"d"].into_iter().enumerate() {
+            let seq = i as u64 + 1;
+            if seq == 5 {
+                skiplist.delete(key, seq);
+                btree.delete(key, seq);
+            } else {
+                skiplist.insert(key, format!("v{seq}").as_bytes(), seq);
+                btree.insert(key, format!("v{seq}").as_bytes(), seq);
+            }
+        }
+
+        for range in [
+            (Bound::Unbounded, Bound::Unbounded),
+            (
+                Bound::Excluded(b"a".as_slice()),
+                Bound::Included(b"c".as_slice()),
+            ),
+            (
+                Bound::Included(b"b".as_slice()),
+                Bound::Excluded(b"d".as_slice()),
+            ),
+        ] {
+            assert_eq!(versions(&skiplist, range), versions(&btree, range));
+        }
+        for snapshots in [&[][..], &[2], &[1, 4]] {
+            assert_eq!(skiplist.flush(snapshots), btree.flush(snapshots));
+        }
+    }
+
+    #[test]
+    fn test_encoded_bytes_match_the_flushed_entries() {
+        let mut table = TestSkipList::new();
+        table.insert(b"a", b"1", 1);
+        table.insert(b"b", b"22", 2);
+        table.insert(b"a", b"333", 3);
+        table.delete(b"b", 4);
+        table.delete(b"c", 5);
+
+        // a snapshot at every sequence number keeps every version
+        let flushed: usize = table
+            .flush(&[1, 2, 3, 4, 5])
+            .iter()
+            .map(|(key, (value, _))| {
+                BlockEntry::encoded_len(key.len(), value.as_ref().map_or(0, Vec::len))
+            })
+            .sum();
+        assert_eq!(table.encoded_bytes(), flushed);
+        assert!(table.memory_usage() > flushed);
+    }
+
+    #[test]
+    fn test_large_values_get_their_own_block() {
+        let mut table = TestSkipList::new();
+        let large = vec![7u8; 100 * 1024];
+        table.insert(b"small", b"1", 1);
+        table.insert(b"large", &large, 2);
+        table.insert(b"small", b"2", 3);
+
+        assert!(matches!(table.get(b"large"), LookupResult::Found((value, 2)) if value == large));
+        assert!(matches!(
+            table.get(b"small"),
+            LookupResult::Found((b"2", 3))
+        ));
+        assert!(table.memory_usage() >= large.len());
+    }
+
+    #[test]
+    fn test_concurrent_writers_and_readers() {
+        const WRITERS: u64 = 4;
+        const PER_WRITER: u64 = 2000;
+        let table = Arc::new(TestSkipList::new());
+
+        let handles: Vec<_> = (0..WRITERS)
+            .map(|writer| {
+                let table = Arc::clone(&table);
+                thread::spawn(move || {
+                    for i in 0..PER_WRITER {
+                        let seq = writer * PER_WRITER + i + 1;
+                        let key = format!("key{:05}", i * WRITERS + writer);
+                        table.insert_shared(key.as_bytes(), &seq.to_le_bytes(), seq);
+                    }
+                })
+            })
+            .collect();
+        let reader = {
+            let table = Arc::clone(&table);
+            thread::spawn(move || {
+                // whatever a reader sees is sorted, even while nodes are linked
+                for _ in 0..50 {
+                    let keys: Vec<_> = table
+                        .range((Bound::Unbounded, Bound::Unbounded))
+                        .map(|(key, _)| key.to_vec())
+                        .collect();
+                    assert!(keys.is_sorted());
+                }
+            })
+        };
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        reader.join().unwrap();
+
+        let flushed = table.flush(&[]);
+        assert_eq!(flushed.len() as u64, WRITERS * PER_WRITER);
+        assert_eq!(
+            table.encoded_bytes(),
+            flushed
+                .iter()
+                .map(|(key, _)| TestSkipList::encoded_len(key, &[0; 8]))
+                .sum::<usize>()
+        );
+        assert!(flushed.is_sorted_by(|a, b| a.0 < b.0));
+        for (key, (value, seq)) in flushed {
+            assert_eq!(value, Some(seq.to_le_bytes().to_vec()));
+            assert!(matches!(table.get(&key), LookupResult::Found((_, s)) if s == seq));
+        }
+    }
+}
@@ -1,7 +1,23 @@
 pub mod kv_store;
 pub mod kv_store_test;
 pub mod memtable;
+pub mod snapshot;
+pub mod store_options;
 pub mod wal;
+pub mod write_batch;
 
 pub use kv_store::*;
+pub use lsm_tree::compaction::{
+    compaction_strategy::CompactionStrategy, leveled::LeveledCompaction,
+    size_tiered::SizeTieredCompaction,
+};
+pub use lsm_tree::sorted_string_table::table_error::TableError;
+pub use memtable::memtable_trait::MemTableAccounting;
+pub use snapshot::{Snapshot, SnapshotPages};
+pub use store_options::StoreOptions;
+pub use wal::{
+    log_format::WalRecoveryMode,
+    wal_sync::{Durability, WriteOptions},
+};
+pub use write_batch::{BatchOp, WriteBatch};
 mod lsm_tree;
@@ -0,0 +1,120 @@
+use std::{
+    ops::{Bound, RangeBounds},
+    sync::Arc,
+};
+
+use crate::persists::{
+    KvStore, TableError,
+    memtable::{btree_map::BTreeMemTable, memtable_trait::MemTable},
+};
+
+/// A point-in-time view of a [`KvStore`], taken with [`KvStore::snapshot`].
+///
+/// Reads see every write up to [`Snapshot::sequence_number`] and nothing newer.
+/// Dropping the snapshot lets flushes and compactions discard the versions it pinned.
+pub struct Snapshot<M: MemTable = BTreeMemTable> {
+    store: Arc<KvStore<M>>,
+    sequence_number: u64,
+}
+
+impl<M: MemTable> Snapshot<M> {
+    pub(crate) fn new(store: Arc<KvStore<M>>, sequence_number: u64) -> Self {
+        Self {
+            store,
+            sequence_number,
+        }
+    }
+
+    /// Sequence number of the newest write visible to the snapshot.
+    pub fn sequence_number(&self) -> u64 {
+        self.sequence_number
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Option<String>, TableError> {
+        Ok(self
+            .get_bytes(key.as_bytes())
+            .await?
+            .map(|value| String::from_utf8_lossy(&value).to_string()))
+    }
+
+    pub async fn get_bytes(&self, key: &[u8]) -> Result<Option<Vec<u8>>, TableError> {
+        self.store.lookup_at(key, self.sequence_number).await
+    }
+
+    /// [`KvStore::scan`] as of the snapshot.
+    pub async fn scan(
+        &self,
+        range: impl RangeBounds<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, String)>, TableError> {
+        self.store.scan_at(range, limit, self.sequence_number).await
+    }
+
+    /// [`KvStore::scan_bytes`] as of the snapshot.
+    pub async fn scan_bytes(
+        &self,
+        range: impl RangeBounds<&[u8]>,
+        limit: usize,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, TableError> {
+        self.store
+            .scan_bytes_at(range, limit, self.sequence_number)
+            .await
+    }
+}
+
+impl<M: MemTable> Drop for Snapshot<M> {
+    fn drop(&mut self) {
+        self.store.release_snapshot(self.sequence_number);
+    }
+}
+
+/// Every entry of a [`Snapshot`] in key order, read a page at a time, see [`KvStore::get_all`].
+///
+/// Each page is a separate scan, so the memtables and tables are only locked while a page is
+/// read. The snapshot keeps the pages consistent with each other.
+pub struct SnapshotPages<M: MemTable = BTreeMemTable> {
+    snapshot: Snapshot<M>,
+    page_size: usize,
+    /// Last key of the previous page, the next page starts after it.
+    last_key: Option<Vec<u8>>,
+    done: bool,
+}
+
+impl<M: MemTable> SnapshotPages<M> {
+    pub(crate) fn new(snapshot: Snapshot<M>, page_size: usize) -> Self {
+        Self {
+            snapshot,
+            page_size: page_size.max(1),
+            last_key: None,
+            done: false,
+        }
+    }
+
+    pub fn sequence_number(&self) -> u64 {
+        self.snapshot.sequence_number()
+    }
+
+    /// Next non-empty page, `None` once every entry was returned.
+    pub async fn next_page(&mut self) -> Result<Option<Vec<(Vec<u8>, Vec<u8>)>>, TableError> {
+        if self.done {
+            return Ok(None);
+        }
+        let start = self
+            .last_key
+            .as_deref()
+            .map_or(Bound::Unbounded, Bound::Excluded);
+        let page = self
+            .snapshot
+            .scan_bytes((start, Bound::Unbounded), self.page_size)
+            .await?;
+
+        self.done = page.len() < self.page_size;
+        match page.last() {
+            Some((key, _)) => {
+                self.last_key = Some(key.clone());
+                Ok(Some(page))
+            }
+            None => Ok(None),
+        }
+    }
+}
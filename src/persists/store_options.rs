@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+
+use crate::persists::{
+    lsm_tree::{manifest::MANIFEST_FILE_NAME, sorted_string_table::sst_writer::TableOptions},
+    memtable::memtable_trait::MemTableAccounting,
+    wal::{legacy_json::LEGACY_WAL_FILE_NAME, log_format::WalRecoveryMode, wal_sync::Durability},
+};
+
+/// Memtable size of [`StoreOptions::new`], 64 KiB.
+pub const DEFAULT_MEMTABLE_SIZE: usize = 64 * 1024;
+
+/// Where a store keeps its files. Everything lives below `root_dir`:
+///
+/// ```text
+/// <root_dir>/
+///   MANIFEST   live tables per level
+///   wal/       write-ahead log
+///   sst/       sorted string tables
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreOptions {
+    pub root_dir: PathBuf,
+    /// How damaged WAL records are handled on startup.
+    pub wal_recovery_mode: WalRecoveryMode,
+    /// When writes are synced to disk before they are acknowledged.
+    pub durability: Durability,
+    /// Key prefix length indexed by a prefix bloom filter in every table, `None` disables it.
+    /// Pick the length of the shortest prefix scans should skip tables for, e.g. 5 for `user:`.
+    pub prefix_bloom_len: Option<usize>,
+    /// Bytes the active memtable takes before it is frozen and flushed.
+    pub memtable_size: usize,
+    /// Heap bytes the active and the frozen memtables may take together, `None` for no limit.
+    /// It is measured by [`MemTableAccounting::HeapUsage`] whatever `memtable_accounting` is.
+    /// The active memtable is frozen at half the budget at the latest, and writes that would
+    /// freeze it wait for flushes while the frozen ones would take more than the other half.
+    pub memory_budget: Option<usize>,
+    /// What `memtable_size` limits, the encoded size of the table a flush writes or the heap
+    /// the memtable takes.
+    pub memtable_accounting: MemTableAccounting,
+    /// JSON log of a version from before the `wal/` directory, migrated into it on open.
+    /// Those versions wrote `wal.log` to the working directory, which is where
+    /// [`StoreOptions::default`] looks. A `wal.log` inside `wal/` is always migrated.
+    pub legacy_wal_path: Option<PathBuf>,
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        Self {
+            legacy_wal_path: Some(LEGACY_WAL_FILE_NAME.into()),
+            ..Self::new("data")
+        }
+    }
+}
+
+impl StoreOptions {
+    pub fn new(root_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            root_dir: root_dir.into(),
+            wal_recovery_mode: WalRecoveryMode::default(),
+            durability: Durability::default(),
+            prefix_bloom_len: None,
+            memtable_size: DEFAULT_MEMTABLE_SIZE,
+            memory_budget: None,
+            memtable_accounting: MemTableAccounting::default(),
+            legacy_wal_path: None,
+        }
+    }
+
+    pub fn wal_dir(&self) -> PathBuf {
+        self.root_dir.join("wal")
+    }
+
+    pub fn sst_dir(&self) -> PathBuf {
+        self.root_dir.join("sst")
+    }
+
+    pub fn manifest_path(&self) -> PathBuf {
+        self.root_dir.join(MANIFEST_FILE_NAME)
+    }
+
+    /// Options for newly written tables.
+    pub(crate) fn table_options(&self) -> TableOptions {
+        TableOptions {
+            prefix_len: self.prefix_bloom_len,
+            ..TableOptions::default()
+        }
+    }
+
+    /// Creates the root directory and its subdirectories if they do not exist yet.
+    pub fn create_dirs(&self) -> std::io::Result<()> {
+        for dir in [self.wal_dir(), self.sst_dir()] {
+            std::fs::create_dir_all(dir)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+//! Reader for logs written before the binary format, one JSON encoded [`LogCommand`] per line.
+
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::from_slice;
+
+use crate::persists::wal::wal::LogCommand;
+
+pub const LEGACY_WAL_FILE_NAME: &str = "wal.log";
+
+/// A line of the legacy log, keys and values were always text.
+#[derive(Deserialize)]
+enum LegacyCommand {
+    Put {
+        key: String,
+        value: String,
+        seq_number: u64,
+    },
+    Delete {
+        key: String,
+        seq_number: u64,
+    },
+}
+
+impl From<LegacyCommand> for LogCommand {
+    fn from(command: LegacyCommand) -> Self {
+        match command {
+            LegacyCommand::Put {
+                key,
+                value,
+                seq_number,
+            } => LogCommand::Put {
+                key: key.into(),
+                value: value.into(),
+                seq_number,
+            },
+            LegacyCommand::Delete { key, seq_number } => LogCommand::Delete {
+                key: key.into(),
+                seq_number,
+            },
+        }
+    }
+}
+
+/// Reads every parseable line of the legacy log at `path`.
+/// Lines that fail to parse, such as a torn last line, are skipped.
+pub async fn read_legacy_wal(path: &Path) -> std::io::Result<Vec<LogCommand>> {
+    let content = tokio::fs::read(path).await?;
+
+    let mut entries = Vec::new();
+    for line in content
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+    {
+        match from_slice::<LegacyCommand>(line) {
+            Ok(cmd) => entries.push(cmd.into()),
+            Err(e) => {
+                eprintln!(
+                    "Skipping invalid WAL line: {} — {e:?}",
+                    String::from_utf8_lossy(line)
+                );
+            }
+        }
+    }
+    Ok(entries)
+}
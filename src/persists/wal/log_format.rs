@@ -0,0 +1,266 @@
+//! Block framing of the write-ahead log.
+//!
+//! The log is a sequence of 32 KiB blocks. Each logical record is split into one or more
+//! physical records that never cross a block boundary:
+//!
+//! ```text
+//! crc32c u32 | length u16 | type u8 | payload[length]
+//! ```
+//!
+//! The checksum covers the type and the payload. A block tail too short for a header is
+//! zero-filled. After a corrupted header the reader resynchronizes at the next block.
+
+use byteorder::{ByteOrder, LittleEndian};
+
+pub const BLOCK_SIZE: usize = 32 * 1024;
+pub const HEADER_SIZE: usize = 4 + 2 + 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum RecordType {
+    /// Zero-filled space, only written as block padding.
+    Zero = 0,
+    Full = 1,
+    First = 2,
+    Middle = 3,
+    Last = 4,
+}
+
+impl RecordType {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::Zero),
+            1 => Some(Self::Full),
+            2 => Some(Self::First),
+            3 => Some(Self::Middle),
+            4 => Some(Self::Last),
+            _ => None,
+        }
+    }
+}
+
+/// How recovery treats damaged records.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WalRecoveryMode {
+    /// A torn last record from a crash during append is dropped,
+    /// corruption anywhere else aborts the recovery.
+    #[default]
+    TolerateCorruptedTail,
+    /// Damaged records are logged and skipped, recovery continues with the next intact record.
+    SkipCorruptedRecords,
+    /// Any damaged record aborts the recovery, including a torn tail.
+    AbsoluteConsistency,
+}
+
+/// Frames records for appending to a log that is already `file_len` bytes long.
+#[derive(Debug)]
+pub struct LogWriter {
+    block_offset: usize,
+}
+
+impl LogWriter {
+    pub fn new(file_len: u64) -> Self {
+        Self {
+            block_offset: (file_len % BLOCK_SIZE as u64) as usize,
+        }
+    }
+
+    /// Appends the physical records of `payload` to `out`.
+    pub fn encode_record(&mut self, payload: &[u8], out: &mut Vec<u8>) {
+        let mut remaining = payload;
+        let mut first = true;
+
+        loop {
+            let leftover = BLOCK_SIZE - self.block_offset;
+            if leftover < HEADER_SIZE {
+                out.resize(out.len() + leftover, 0);
+                self.block_offset = 0;
+            }
+
+            let available = BLOCK_SIZE - self.block_offset - HEADER_SIZE;
+            let (fragment, rest) = remaining.split_at(remaining.len().min(available));
+            let last = rest.is_empty();
+            let record_type = match (first, last) {
+                (true, true) => RecordType::Full,
+                (true, false) => RecordType::First,
+                (false, true) => RecordType::Last,
+                (false, false) => RecordType::Middle,
+            };
+
+            let mut header = [0u8; HEADER_SIZE];
+            LittleEndian::write_u32(&mut header[0..4], record_crc(record_type as u8, fragment));
+            LittleEndian::write_u16(&mut header[4..6], fragment.len() as u16);
+            header[6] = record_type as u8;
+            out.extend_from_slice(&header);
+            out.extend_from_slice(fragment);
+
+            self.block_offset += HEADER_SIZE + fragment.len();
+            remaining = rest;
+            first = false;
+            if last {
+                return;
+            }
+        }
+    }
+}
+
+fn record_crc(record_type: u8, fragment: &[u8]) -> u32 {
+    crc32c::crc32c_append(crc32c::crc32c(&[record_type]), fragment)
+}
+
+/// A record that could not be read, `offset` is where its first physical record starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BadRecord {
+    pub offset: usize,
+}
+
+/// Result of reading a complete log.
+#[derive(Debug, Default)]
+pub struct LogContents {
+    /// Payloads of the intact logical records in log order, with their offsets.
+    pub records: Vec<(usize, Vec<u8>)>,
+    /// Damaged records before the end of the log.
+    pub corrupted: Vec<BadRecord>,
+    /// Start of a record torn by a crash during append, everything from here on is garbage.
+    pub torn_tail: Option<usize>,
+}
+
+enum Physical<'a> {
+    Record {
+        record_type: RecordType,
+        fragment: &'a [u8],
+        offset: usize,
+    },
+    Bad {
+        offset: usize,
+    },
+    Torn {
+        offset: usize,
+    },
+    Eof,
+}
+
+struct PhysicalReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> PhysicalReader<'a> {
+    fn next(&mut self) -> Physical<'a> {
+        loop {
+            let block_end = (self.offset / BLOCK_SIZE + 1) * BLOCK_SIZE;
+            if block_end - self.offset < HEADER_SIZE {
+                // zero padding at the end of a block
+                self.offset = block_end;
+            }
+            if self.offset >= self.data.len() {
+                return Physical::Eof;
+            }
+
+            let offset = self.offset;
+            let rest = &self.data[offset..];
+            if rest.len() < HEADER_SIZE {
+                return Physical::Torn { offset };
+            }
+
+            let crc = LittleEndian::read_u32(&rest[0..4]);
+            let length = LittleEndian::read_u16(&rest[4..6]) as usize;
+            let record_type = RecordType::from_byte(rest[6]);
+            let end = offset + HEADER_SIZE + length;
+
+            // only the last block can end in the middle of a record
+            if end > self.data.len() && block_end >= self.data.len() {
+                return Physical::Torn { offset };
+            }
+            if end > block_end || record_type.is_none() {
+                self.offset = block_end;
+                return Physical::Bad { offset };
+            }
+            self.offset = end;
+
+            let record_type = record_type.expect("checked above");
+            let fragment = &rest[HEADER_SIZE..HEADER_SIZE + length];
+            if record_type == RecordType::Zero {
+                if length == 0 && crc == 0 {
+                    // preallocated or zeroed space, nothing was written here
+                    if self.data[offset..].iter().all(|&b| b == 0) {
+                        return Physical::Eof;
+                    }
+                    continue;
+                }
+                return Physical::Bad { offset };
+            }
+            if record_crc(record_type as u8, fragment) != crc {
+                // a partially written last record can fail the checksum as well
+                return if end == self.data.len() {
+                    Physical::Torn { offset }
+                } else {
+                    Physical::Bad { offset }
+                };
+            }
+
+            return Physical::Record {
+                record_type,
+                fragment,
+                offset,
+            };
+        }
+    }
+}
+
+/// Reassembles the logical records of a complete log.
+pub fn read_log(data: &[u8]) -> LogContents {
+    let mut reader = PhysicalReader { data, offset: 0 };
+    let mut contents = LogContents::default();
+    // start offset and payload of a record spanning several blocks
+    let mut pending: Option<(usize, Vec<u8>)> = None;
+
+    loop {
+        match reader.next() {
+            Physical::Record {
+                record_type,
+                fragment,
+                offset,
+            } => match record_type {
+                RecordType::Full | RecordType::First => {
+                    if let Some((start, _)) = pending.take() {
+                        contents.corrupted.push(BadRecord { offset: start });
+                    }
+                    if record_type == RecordType::Full {
+                        contents.records.push((offset, fragment.to_vec()));
+                    } else {
+                        pending = Some((offset, fragment.to_vec()));
+                    }
+                }
+                RecordType::Middle | RecordType::Last => match pending.as_mut() {
+                    Some((_, payload)) => {
+                        payload.extend_from_slice(fragment);
+                        if record_type == RecordType::Last {
+                            contents.records.extend(pending.take());
+                        }
+                    }
+                    None => contents.corrupted.push(BadRecord { offset }),
+                },
+                RecordType::Zero => unreachable!("zero records are skipped by the reader"),
+            },
+            Physical::Bad { offset } => {
+                if let Some((start, _)) = pending.take() {
+                    contents.corrupted.push(BadRecord { offset: start });
+                }
+                contents.corrupted.push(BadRecord { offset });
+            }
+            Physical::Torn { offset } => {
+                let start = pending.take().map_or(offset, |(start, _)| start);
+                contents.torn_tail = Some(start);
+                return contents;
+            }
+            Physical::Eof => {
+                // a record whose last fragment was never written
+                if let Some((start, _)) = pending.take() {
+                    contents.torn_tail = Some(start);
+                }
+                return contents;
+            }
+        }
+    }
+}
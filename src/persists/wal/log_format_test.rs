@@ -0,0 +1,91 @@
+use crate::persists::wal::log_format::{BLOCK_SIZE, BadRecord, HEADER_SIZE, LogWriter, read_log};
+
+fn encode(payloads: &[Vec<u8>]) -> Vec<u8> {
+    let mut writer = LogWriter::new(0);
+    let mut data = Vec::new();
+    for payload in payloads {
+        writer.encode_record(payload, &mut data);
+    }
+    data
+}
+
+fn payloads(data: &[u8]) -> Vec<Vec<u8>> {
+    read_log(data)
+        .records
+        .into_iter()
+        .map(|(_, payload)| payload)
+        .collect()
+}
+
+#[test]
+fn records_never_cross_a_block_boundary() {
+    let records = vec![
+        vec![1u8; 10],
+        vec![2u8; 3 * BLOCK_SIZE],
+        Vec::new(),
+        vec![3u8; 5],
+    ];
+    let data = encode(&records);
+
+    // 10 bytes, then three blocks worth of payload need four fragments
+    assert!(data.len() > 3 * BLOCK_SIZE + 4 * HEADER_SIZE);
+    assert_eq!(payloads(&data), records);
+}
+
+#[test]
+fn short_block_tail_is_padded() {
+    // leaves fewer than HEADER_SIZE bytes in the first block
+    let first = vec![7u8; BLOCK_SIZE - HEADER_SIZE - 3];
+    let data = encode(&[first.clone(), vec![8u8; 4]]);
+
+    assert_eq!(data.len(), BLOCK_SIZE + HEADER_SIZE + 4);
+    assert_eq!(&data[BLOCK_SIZE - 3..BLOCK_SIZE], &[0, 0, 0]);
+    assert_eq!(payloads(&data), vec![first, vec![8u8; 4]]);
+}
+
+#[test]
+fn writer_continues_the_framing_of_an_existing_log() {
+    let mut data = encode(&[vec![1u8; BLOCK_SIZE - 100]]);
+    let mut writer = LogWriter::new(data.len() as u64);
+    writer.encode_record(&[2u8; 200], &mut data);
+
+    assert_eq!(
+        payloads(&data),
+        vec![vec![1u8; BLOCK_SIZE - 100], vec![2u8; 200]]
+    );
+}
+
+#[test]
+fn record_missing_its_last_fragment_is_torn() {
+    let data = encode(&[vec![1u8; 10], vec![2u8; 2 * BLOCK_SIZE]]);
+    let contents = read_log(&data[..BLOCK_SIZE + 100]);
+
+    assert_eq!(contents.records.len(), 1);
+    assert!(contents.corrupted.is_empty());
+    assert_eq!(contents.torn_tail, Some(HEADER_SIZE + 10));
+}
+
+#[test]
+fn zeroed_tail_is_not_a_record() {
+    let mut data = encode(&[vec![1u8; 10]]);
+    data.resize(data.len() + 64, 0);
+
+    let contents = read_log(&data);
+    assert_eq!(contents.records.len(), 1);
+    assert!(contents.corrupted.is_empty());
+    assert_eq!(contents.torn_tail, None);
+}
+
+#[test]
+fn damaged_header_resyncs_at_the_next_block() {
+    let data = encode(&[vec![1u8; 10], vec![2u8; BLOCK_SIZE], vec![3u8; 10]]);
+    let mut damaged = data.clone();
+    // length of the first record now points past the block
+    damaged[4] = 0xff;
+    damaged[5] = 0xff;
+
+    let contents = read_log(&damaged);
+    assert_eq!(contents.corrupted.first(), Some(&BadRecord { offset: 0 }));
+    // the tail of the large record in block 1 has no start, only the last record survives
+    assert_eq!(payloads(&damaged), vec![vec![3u8; 10]]);
+}
@@ -0,0 +1,10 @@
+pub mod legacy_json;
+pub mod log_format;
+#[allow(clippy::module_inception)]
+pub mod wal;
+pub mod wal_sync;
+
+#[cfg(test)]
+mod log_format_test;
+#[cfg(test)]
+mod wal_test;
@@ -0,0 +1,430 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use byteorder::{ByteOrder, LittleEndian};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+};
+
+use crate::persists::{
+    lsm_tree::sorted_string_table::table_error::TableError,
+    wal::{
+        legacy_json::{LEGACY_WAL_FILE_NAME, read_legacy_wal},
+        log_format::{LogWriter, WalRecoveryMode, read_log},
+    },
+    write_batch::BatchOp,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogCommand {
+    Put {
+        key: Vec<u8>,
+        value: Vec<u8>,
+        seq_number: u64,
+    },
+    Delete {
+        key: Vec<u8>,
+        seq_number: u64,
+    },
+    /// A [`WriteBatch`](crate::persists::WriteBatch), the operations are numbered
+    /// consecutively from `seq_number` on.
+    Batch {
+        ops: Vec<BatchOp>,
+        seq_number: u64,
+    },
+}
+
+const PUT_RECORD: u8 = 1;
+const DELETE_RECORD: u8 = 2;
+const BATCH_RECORD: u8 = 3;
+
+impl LogCommand {
+    /// Sequence number of the command, the first one for a batch.
+    pub fn seq_number(&self) -> u64 {
+        match self {
+            LogCommand::Put { seq_number, .. }
+            | LogCommand::Delete { seq_number, .. }
+            | LogCommand::Batch { seq_number, .. } => *seq_number,
+        }
+    }
+
+    /// Highest sequence number used by the command.
+    pub fn last_seq_number(&self) -> u64 {
+        match self {
+            LogCommand::Batch { ops, seq_number } => {
+                seq_number + (ops.len() as u64).saturating_sub(1)
+            }
+            _ => self.seq_number(),
+        }
+    }
+
+    /// `type u8 | seq u64 | key_len u32 | key | value_len u32 | value`, the value only for puts.
+    /// A batch is `type u8 | seq u64 | count u32` followed by `count` operations, each encoded
+    /// like a single command without the sequence number.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        match self {
+            LogCommand::Put {
+                key,
+                value,
+                seq_number,
+            } => {
+                buffer.push(PUT_RECORD);
+                buffer.extend_from_slice(&seq_number.to_le_bytes());
+                write_slice(&mut buffer, key);
+                write_slice(&mut buffer, value);
+            }
+            LogCommand::Delete { key, seq_number } => {
+                buffer.push(DELETE_RECORD);
+                buffer.extend_from_slice(&seq_number.to_le_bytes());
+                write_slice(&mut buffer, key);
+            }
+            LogCommand::Batch { ops, seq_number } => {
+                buffer.push(BATCH_RECORD);
+                buffer.extend_from_slice(&seq_number.to_le_bytes());
+                buffer.extend_from_slice(&(ops.len() as u32).to_le_bytes());
+                for op in ops {
+                    match op {
+                        BatchOp::Put { key, value } => {
+                            buffer.push(PUT_RECORD);
+                            write_slice(&mut buffer, key);
+                            write_slice(&mut buffer, value);
+                        }
+                        BatchOp::Delete { key } => {
+                            buffer.push(DELETE_RECORD);
+                            write_slice(&mut buffer, key);
+                        }
+                    }
+                }
+            }
+        }
+        buffer
+    }
+
+    /// Inverse of [`LogCommand::encode`], `None` if the payload is malformed.
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        let (&record_type, rest) = payload.split_first()?;
+        let seq_number = LittleEndian::read_u64(rest.get(..8)?);
+        let rest = &rest[8..];
+
+        let (command, rest) = match record_type {
+            BATCH_RECORD => {
+                let count = LittleEndian::read_u32(rest.get(..4)?) as usize;
+                let mut rest = &rest[4..];
+                // every operation takes at least five bytes, a bogus count must not allocate
+                let mut ops = Vec::with_capacity(count.min(rest.len() / 5));
+                for _ in 0..count {
+                    let (&op_type, op) = rest.split_first()?;
+                    let (op, op_rest) = read_op(op_type, op)?;
+                    ops.push(op);
+                    rest = op_rest;
+                }
+                (LogCommand::Batch { ops, seq_number }, rest)
+            }
+            _ => match read_op(record_type, rest)? {
+                (BatchOp::Put { key, value }, rest) => (
+                    LogCommand::Put {
+                        key,
+                        value,
+                        seq_number,
+                    },
+                    rest,
+                ),
+                (BatchOp::Delete { key }, rest) => (LogCommand::Delete { key, seq_number }, rest),
+            },
+        };
+        rest.is_empty().then_some(command)
+    }
+}
+
+/// Reads the key and, for puts, the value of an operation of type `op_type`.
+fn read_op(op_type: u8, buffer: &[u8]) -> Option<(BatchOp, &[u8])> {
+    let (key, rest) = read_slice(buffer)?;
+    let key = key.to_vec();
+    match op_type {
+        PUT_RECORD => {
+            let (value, rest) = read_slice(rest)?;
+            let value = value.to_vec();
+            Some((BatchOp::Put { key, value }, rest))
+        }
+        DELETE_RECORD => Some((BatchOp::Delete { key }, rest)),
+        _ => None,
+    }
+}
+
+/// Appends `bytes` as a `len u32 | bytes` field.
+fn write_slice(buffer: &mut Vec<u8>, bytes: &[u8]) {
+    buffer.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    buffer.extend_from_slice(bytes);
+}
+
+/// Splits a `len u32 | bytes` field off the front of `buffer`.
+fn read_slice(buffer: &[u8]) -> Option<(&[u8], &[u8])> {
+    let len = LittleEndian::read_u32(buffer.get(..4)?) as usize;
+    let rest = &buffer[4..];
+    (rest.len() >= len).then(|| rest.split_at(len))
+}
+
+const SEGMENT_EXTENSION: &str = "log";
+
+/// Write-ahead log split into numbered segments, `<dir>/000001.log`, `<dir>/000002.log`, ...
+///
+/// A new segment is started whenever the active memtable is frozen, so every segment only
+/// holds entries of a few memtables. Once all of them reached an SSTable the segment is deleted.
+pub struct Wal {
+    dir: PathBuf,
+    file: File,
+    /// Handle to the segment appended to, syncs run on it without holding the log.
+    sync_file: Arc<std::fs::File>,
+    writer: LogWriter,
+    /// One past the sequence number of the last appended record.
+    appended_until: u64,
+    segment_number: u64,
+    /// Segments on disk, including the one appended to.
+    segments: BTreeSet<u64>,
+    /// Oldest segment holding entries of the active memtable.
+    active_first_segment: u64,
+    /// Oldest segment holding entries of every frozen memtable that is not flushed yet, by memtable id.
+    frozen_first_segments: BTreeMap<u64, u64>,
+}
+
+impl Wal {
+    /// Recovers every segment in `dir` and starts a new segment for appending.
+    ///
+    /// A legacy JSON log in `dir` is migrated to the binary format first. A torn last record is cut
+    /// off the last segment unless `mode` demands absolute consistency, how damaged records
+    /// before the end of the log are handled depends on `mode` as well.
+    /// The recovered segments are kept until the memtables they are replayed into are flushed.
+    pub async fn open(
+        dir: &Path,
+        mode: WalRecoveryMode,
+    ) -> Result<(Self, Vec<LogCommand>), TableError> {
+        tokio::fs::create_dir_all(dir).await?;
+        Self::migrate_legacy_wal(dir, &dir.join(LEGACY_WAL_FILE_NAME)).await?;
+
+        let mut segments = Self::segments(dir).await?;
+        let mut entries = Vec::new();
+        let last = segments.last().copied();
+        for &segment_number in &segments {
+            let path = Self::segment_path(dir, segment_number);
+            let is_last = Some(segment_number) == last;
+            entries.extend(Self::read_segment(&path, mode, is_last).await?);
+        }
+
+        let segment_number = segments.last().map_or(1, |last| last + 1);
+        let (file, sync_file) = Self::create_segment(dir, segment_number).await?;
+        segments.insert(segment_number);
+
+        let wal = Self {
+            dir: dir.to_path_buf(),
+            file,
+            sync_file,
+            writer: LogWriter::new(0),
+            appended_until: 0,
+            segment_number,
+            active_first_segment: *segments.first().expect("the new segment was inserted"),
+            segments,
+            frozen_first_segments: BTreeMap::new(),
+        };
+        Ok((wal, entries))
+    }
+
+    pub async fn append(&mut self, command: &LogCommand) -> std::io::Result<()> {
+        let mut buffer = Vec::new();
+        self.writer.encode_record(&command.encode(), &mut buffer);
+        self.file.write_all(&buffer).await?;
+        // hands the write to the OS, a process crash no longer loses it
+        self.file.flush().await?;
+        self.appended_until = self.appended_until.max(command.last_seq_number() + 1);
+        Ok(())
+    }
+
+    /// Syncs every appended record to disk.
+    pub async fn sync(&mut self) -> std::io::Result<()> {
+        self.file.sync_data().await
+    }
+
+    /// Handle to sync and the position it makes durable, see
+    /// [`WalSyncer`](crate::persists::wal::wal_sync::WalSyncer).
+    pub fn sync_point(&self) -> (Arc<std::fs::File>, u64) {
+        (Arc::clone(&self.sync_file), self.appended_until)
+    }
+
+    /// The active memtable was frozen as `memtable_id`, later appends go to a new segment.
+    pub async fn rotate(&mut self, memtable_id: u64) -> std::io::Result<()> {
+        // later syncs only cover the new segment, and only the last segment may end torn
+        self.file.sync_data().await?;
+        let segment_number = self.segment_number + 1;
+        let (file, sync_file) = Self::create_segment(&self.dir, segment_number).await?;
+
+        self.freeze(memtable_id);
+        self.file = file;
+        self.sync_file = sync_file;
+        self.writer = LogWriter::new(0);
+        self.segment_number = segment_number;
+        self.segments.insert(segment_number);
+        self.active_first_segment = segment_number;
+        Ok(())
+    }
+
+    /// Like [`Wal::rotate`] but keeps appending to the current segment, used while replaying
+    /// recovered entries that are already in the log.
+    pub fn freeze(&mut self, memtable_id: u64) {
+        self.frozen_first_segments
+            .insert(memtable_id, self.active_first_segment);
+    }
+
+    /// The frozen memtable `memtable_id` reached an SSTable, deletes every segment
+    /// that no unflushed memtable has entries in.
+    pub async fn memtable_flushed(&mut self, memtable_id: u64) -> std::io::Result<()> {
+        self.frozen_first_segments.remove(&memtable_id);
+        let oldest_needed = self
+            .frozen_first_segments
+            .values()
+            .copied()
+            .fold(self.active_first_segment, u64::min);
+
+        while let Some(&segment_number) = self.segments.first()
+            && segment_number < oldest_needed
+        {
+            match tokio::fs::remove_file(Self::segment_path(&self.dir, segment_number)).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+            self.segments.remove(&segment_number);
+        }
+        Ok(())
+    }
+
+    pub fn segment_path(dir: &Path, segment_number: u64) -> PathBuf {
+        dir.join(format!("{segment_number:06}.{SEGMENT_EXTENSION}"))
+    }
+
+    /// Numbers of the segments in `dir`.
+    pub async fn segments(dir: &Path) -> std::io::Result<BTreeSet<u64>> {
+        let mut segments = BTreeSet::new();
+        let mut entries = match tokio::fs::read_dir(dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(segments),
+            Err(e) => return Err(e),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == SEGMENT_EXTENSION)
+                && let Some(segment_number) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse().ok())
+            {
+                segments.insert(segment_number);
+            }
+        }
+        Ok(segments)
+    }
+
+    async fn create_segment(
+        dir: &Path,
+        segment_number: u64,
+    ) -> std::io::Result<(File, Arc<std::fs::File>)> {
+        let file = OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(Self::segment_path(dir, segment_number))
+            .await?;
+        // syncing the segment is pointless if its directory entry is lost
+        File::open(dir).await?.sync_all().await?;
+
+        let sync_file = file.try_clone().await?.into_std().await;
+        Ok((file, Arc::new(sync_file)))
+    }
+
+    /// Reads every record of one segment, see [`Wal::open`] for how `mode` applies.
+    ///
+    /// Only the last segment can end in a torn record. Older ones were synced when the log
+    /// rotated, a torn tail there is corruption in the middle of the log.
+    async fn read_segment(
+        path: &Path,
+        mode: WalRecoveryMode,
+        is_last: bool,
+    ) -> Result<Vec<LogCommand>, TableError> {
+        let data = tokio::fs::read(path).await?;
+        let contents = read_log(&data);
+
+        let mut corrupted: Vec<_> = contents.corrupted.iter().map(|bad| bad.offset).collect();
+        if !is_last {
+            corrupted.extend(contents.torn_tail);
+        }
+        let mut entries = Vec::with_capacity(contents.records.len());
+        for (offset, payload) in &contents.records {
+            match LogCommand::decode(payload) {
+                Some(command) => entries.push(command),
+                None => corrupted.push(*offset),
+            }
+        }
+        corrupted.sort_unstable();
+
+        if let Some(&offset) = corrupted.first() {
+            if mode != WalRecoveryMode::SkipCorruptedRecords {
+                return Err(TableError::corruption(path, "wal record", offset));
+            }
+            eprintln!(
+                "skipped {} corrupted WAL records in {}",
+                corrupted.len(),
+                path.display()
+            );
+        }
+
+        if let Some(offset) = contents.torn_tail.filter(|_| is_last) {
+            if mode == WalRecoveryMode::AbsoluteConsistency {
+                return Err(TableError::corruption(path, "wal tail", offset));
+            }
+            eprintln!("truncating torn last WAL record in {}", path.display());
+            let file = OpenOptions::new().write(true).open(path).await?;
+            file.set_len(offset as u64).await?;
+            file.sync_data().await?;
+        }
+
+        Ok(entries)
+    }
+
+    /// Rewrites the JSON log at `legacy_path`, from before the binary format, into the first
+    /// segment in `dir`. The legacy file is removed once the converted segment is durable.
+    pub(crate) async fn migrate_legacy_wal(dir: &Path, legacy_path: &Path) -> std::io::Result<()> {
+        if !tokio::fs::try_exists(legacy_path).await? {
+            return Ok(());
+        }
+
+        // a crash after the rename below leaves both files, the segment is complete
+        if Self::segments(dir).await?.is_empty() {
+            let entries = read_legacy_wal(legacy_path).await?;
+
+            let mut writer = LogWriter::new(0);
+            let mut buffer = Vec::new();
+            for entry in &entries {
+                writer.encode_record(&entry.encode(), &mut buffer);
+            }
+
+            let path = Self::segment_path(dir, 1);
+            let tmp_path = path.with_extension("tmp");
+            let mut tmp = File::create(&tmp_path).await?;
+            tmp.write_all(&buffer).await?;
+            tmp.sync_all().await?;
+            drop(tmp);
+            tokio::fs::rename(&tmp_path, &path).await?;
+            println!(
+                "migrated {} records from the legacy WAL {}",
+                entries.len(),
+                legacy_path.display()
+            );
+        }
+
+        tokio::fs::remove_file(legacy_path).await
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use tokio::sync::Mutex;
+
+use crate::persists::wal::wal::Wal;
+
+/// When appended WAL records reach the disk.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Durability {
+    /// Every write is synced before it is acknowledged, writers wait for each other's syncs.
+    Always,
+    /// Every write is synced before it is acknowledged, concurrent writers share one sync.
+    #[default]
+    GroupCommit,
+    /// Writes are acknowledged once the OS has them and synced in the background at this
+    /// interval. A power failure loses at most the last interval, a process crash nothing.
+    Interval(Duration),
+}
+
+/// Per write overrides of the store's [`Durability`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WriteOptions {
+    /// Waits until the write is synced, even if the store only syncs periodically.
+    pub sync: bool,
+}
+
+/// Group commit: a writer that needs its record on disk becomes the leader and syncs
+/// everything appended so far. Writers queued behind it are usually covered by that
+/// sync and return without one of their own.
+#[derive(Debug, Default)]
+pub struct WalSyncer {
+    /// Every record below this sequence number is durable.
+    synced_until: AtomicU64,
+    leader: Mutex<()>,
+    sync_count: AtomicU64,
+}
+
+impl WalSyncer {
+    /// Returns once the record with `seq_number` is synced.
+    pub async fn wait_durable(&self, wal: &Mutex<Wal>, seq_number: u64) -> std::io::Result<()> {
+        if self.synced_until.load(Ordering::Acquire) > seq_number {
+            return Ok(());
+        }
+        let _leader = self.leader.lock().await;
+        // the previous leader may have synced this record as well
+        if self.synced_until.load(Ordering::Acquire) > seq_number {
+            return Ok(());
+        }
+        self.sync(wal).await
+    }
+
+    /// Syncs every record appended so far.
+    pub async fn sync(&self, wal: &Mutex<Wal>) -> std::io::Result<()> {
+        let (file, appended_until) = wal.lock().await.sync_point();
+        if self.synced_until.load(Ordering::Acquire) >= appended_until {
+            return Ok(());
+        }
+
+        tokio::task::spawn_blocking(move || file.sync_data())
+            .await
+            .map_err(std::io::Error::other)??;
+        self.synced_until
+            .fetch_max(appended_until, Ordering::Release);
+        self.sync_count.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Number of syncs run so far.
+    pub fn sync_count(&self) -> u64 {
+        self.sync_count.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,277 @@
+use std::io::Write;
+use std::path::Path;
+
+use tempfile::tempdir;
+
+use crate::persists::{
+    BatchOp, TableError,
+    wal::{
+        legacy_json::LEGACY_WAL_FILE_NAME,
+        log_format::WalRecoveryMode,
+        wal::{LogCommand, Wal},
+    },
+};
+
+fn put(key: &str, seq_number: u64) -> LogCommand {
+    LogCommand::Put {
+        key: key.into(),
+        value: format!("value{seq_number}").into(),
+        seq_number,
+    }
+}
+
+fn append_raw(path: &Path, bytes: &[u8]) {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .unwrap();
+    file.write_all(bytes).unwrap();
+}
+
+async fn write_log(dir: &Path, commands: &[LogCommand]) -> Wal {
+    let (mut wal, _) = Wal::open(dir, WalRecoveryMode::default()).await.unwrap();
+    for command in commands {
+        wal.append(command).await.unwrap();
+    }
+    wal
+}
+
+async fn recover(dir: &Path, mode: WalRecoveryMode) -> Result<Vec<LogCommand>, TableError> {
+    Wal::open(dir, mode).await.map(|(_, entries)| entries)
+}
+
+async fn segments(dir: &Path) -> Vec<u64> {
+    Wal::segments(dir).await.unwrap().into_iter().collect()
+}
+
+#[test]
+fn commands_round_trip_through_the_binary_encoding() {
+    let commands = [
+        put("key", 7),
+        LogCommand::Put {
+            key: Vec::new(),
+            value: vec![0xff, 0, 0xfe],
+            seq_number: u64::MAX,
+        },
+        LogCommand::Delete {
+            key: "gone".into(),
+            seq_number: 3,
+        },
+        LogCommand::Batch {
+            ops: vec![
+                BatchOp::Put {
+                    key: "a".into(),
+                    value: "1".into(),
+                },
+                BatchOp::Delete { key: "b".into() },
+            ],
+            seq_number: 10,
+        },
+    ];
+    for command in commands {
+        assert_eq!(LogCommand::decode(&command.encode()), Some(command));
+    }
+
+    let encoded = put("key", 7).encode();
+    assert_eq!(LogCommand::decode(&encoded[..encoded.len() - 1]), None);
+}
+
+#[tokio::test]
+async fn empty_dir_has_no_records() {
+    let tmpdir = tempdir().unwrap();
+    let entries = recover(tmpdir.path(), WalRecoveryMode::default())
+        .await
+        .unwrap();
+    assert!(entries.is_empty());
+}
+
+#[tokio::test]
+async fn records_are_read_in_append_order() {
+    let tmpdir = tempdir().unwrap();
+    let large = LogCommand::Put {
+        key: "large".into(),
+        value: "x".repeat(100 * 1024).into(),
+        seq_number: 2,
+    };
+    let delete = LogCommand::Delete {
+        key: "a".into(),
+        seq_number: 3,
+    };
+    write_log(tmpdir.path(), &[put("a", 1), large.clone(), delete.clone()]).await;
+
+    let entries = recover(tmpdir.path(), WalRecoveryMode::AbsoluteConsistency)
+        .await
+        .unwrap();
+    assert_eq!(entries, vec![put("a", 1), large, delete]);
+}
+
+#[tokio::test]
+async fn torn_last_record_is_truncated() {
+    let tmpdir = tempdir().unwrap();
+    let path = Wal::segment_path(tmpdir.path(), 1);
+    drop(write_log(tmpdir.path(), &[put("a", 1)]).await);
+    let valid_len = std::fs::metadata(&path).unwrap().len();
+
+    // header and half of the payload of a second record
+    append_raw(&path, &std::fs::read(&path).unwrap()[..10]);
+
+    let entries = recover(tmpdir.path(), WalRecoveryMode::TolerateCorruptedTail)
+        .await
+        .unwrap();
+    assert_eq!(entries, vec![put("a", 1)]);
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), valid_len);
+}
+
+#[tokio::test]
+async fn torn_tail_before_the_last_segment_is_corruption() {
+    let tmpdir = tempdir().unwrap();
+    let mut wal = write_log(tmpdir.path(), &[put("a", 1)]).await;
+    wal.rotate(2).await.unwrap();
+    wal.append(&put("b", 2)).await.unwrap();
+    drop(wal);
+    append_raw(&Wal::segment_path(tmpdir.path(), 1), &[1, 2, 3]);
+
+    let error = recover(tmpdir.path(), WalRecoveryMode::TolerateCorruptedTail)
+        .await
+        .unwrap_err();
+    assert!(error.is_corruption(), "unexpected error: {error}");
+
+    let entries = recover(tmpdir.path(), WalRecoveryMode::SkipCorruptedRecords)
+        .await
+        .unwrap();
+    assert_eq!(entries, vec![put("a", 1), put("b", 2)]);
+}
+
+#[tokio::test]
+async fn torn_batch_is_dropped_as_a_whole() {
+    let tmpdir = tempdir().unwrap();
+    let path = Wal::segment_path(tmpdir.path(), 1);
+    let batch = LogCommand::Batch {
+        ops: (0..4)
+            .map(|i| BatchOp::Put {
+                key: format!("key{i}").into(),
+                value: "x".repeat(20_000).into(),
+            })
+            .collect(),
+        seq_number: 2,
+    };
+    drop(write_log(tmpdir.path(), &[put("a", 1), batch]).await);
+
+    // the batch spans several blocks, cut it after the first one
+    let data = std::fs::read(&path).unwrap();
+    std::fs::write(&path, &data[..40_000]).unwrap();
+
+    let entries = recover(tmpdir.path(), WalRecoveryMode::TolerateCorruptedTail)
+        .await
+        .unwrap();
+    assert_eq!(entries, vec![put("a", 1)]);
+}
+
+#[tokio::test]
+async fn absolute_consistency_rejects_a_torn_tail() {
+    let tmpdir = tempdir().unwrap();
+    drop(write_log(tmpdir.path(), &[put("a", 1)]).await);
+    append_raw(&Wal::segment_path(tmpdir.path(), 1), &[1, 2, 3]);
+
+    let error = recover(tmpdir.path(), WalRecoveryMode::AbsoluteConsistency)
+        .await
+        .unwrap_err();
+    assert!(error.is_corruption(), "unexpected error: {error}");
+}
+
+#[tokio::test]
+async fn corrupted_record_is_skipped_or_reported_depending_on_mode() {
+    let tmpdir = tempdir().unwrap();
+    let path = Wal::segment_path(tmpdir.path(), 1);
+    drop(write_log(tmpdir.path(), &[put("a", 1), put("b", 2), put("c", 3)]).await);
+
+    // flip a payload byte of the first record
+    let mut data = std::fs::read(&path).unwrap();
+    data[10] ^= 0xff;
+    std::fs::write(&path, &data).unwrap();
+
+    let error = recover(tmpdir.path(), WalRecoveryMode::TolerateCorruptedTail)
+        .await
+        .unwrap_err();
+    assert!(error.is_corruption(), "unexpected error: {error}");
+
+    let entries = recover(tmpdir.path(), WalRecoveryMode::SkipCorruptedRecords)
+        .await
+        .unwrap();
+    assert_eq!(entries, vec![put("b", 2), put("c", 3)]);
+}
+
+#[tokio::test]
+async fn segments_are_removed_once_every_memtable_in_them_is_flushed() {
+    let tmpdir = tempdir().unwrap();
+    let mut wal = write_log(tmpdir.path(), &[put("a", 1)]).await;
+
+    wal.rotate(10).await.unwrap();
+    wal.append(&put("b", 2)).await.unwrap();
+    wal.rotate(20).await.unwrap();
+    wal.append(&put("c", 3)).await.unwrap();
+    assert_eq!(segments(tmpdir.path()).await, vec![1, 2, 3]);
+
+    // segment 1 is still needed by memtable 10
+    wal.memtable_flushed(20).await.unwrap();
+    assert_eq!(segments(tmpdir.path()).await, vec![1, 2, 3]);
+
+    wal.memtable_flushed(10).await.unwrap();
+    assert_eq!(segments(tmpdir.path()).await, vec![3]);
+
+    drop(wal);
+    let entries = recover(tmpdir.path(), WalRecoveryMode::default())
+        .await
+        .unwrap();
+    assert_eq!(entries, vec![put("c", 3)]);
+}
+
+#[tokio::test]
+async fn recovered_segments_are_kept_until_their_memtables_are_flushed() {
+    let tmpdir = tempdir().unwrap();
+    drop(write_log(tmpdir.path(), &[put("a", 1)]).await);
+
+    let (mut wal, entries) = Wal::open(tmpdir.path(), WalRecoveryMode::default())
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(segments(tmpdir.path()).await, vec![1, 2]);
+
+    // replay froze a memtable without starting a segment, the active memtable
+    // still holds replayed entries of segment 1
+    wal.freeze(5);
+    wal.memtable_flushed(5).await.unwrap();
+    assert_eq!(segments(tmpdir.path()).await, vec![1, 2]);
+
+    wal.rotate(6).await.unwrap();
+    wal.memtable_flushed(6).await.unwrap();
+    assert_eq!(segments(tmpdir.path()).await, vec![3]);
+}
+
+#[tokio::test]
+async fn legacy_json_log_is_migrated() {
+    let tmpdir = tempdir().unwrap();
+    let legacy_path = tmpdir.path().join(LEGACY_WAL_FILE_NAME);
+    for (key, seq_number) in [("a", 1), ("b", 2)] {
+        let line = format!(
+            r#"{{"Put":{{"key":"{key}","value":"value{seq_number}","seq_number":{seq_number}}}}}"#
+        );
+        append_raw(&legacy_path, format!("{line}\n").as_bytes());
+    }
+    // torn last line
+    append_raw(&legacy_path, br#"{"Put":{"key":"c","val"#);
+
+    let entries = recover(tmpdir.path(), WalRecoveryMode::AbsoluteConsistency)
+        .await
+        .unwrap();
+    assert_eq!(entries, vec![put("a", 1), put("b", 2)]);
+    assert!(!legacy_path.exists());
+    assert!(Wal::segment_path(tmpdir.path(), 1).exists());
+
+    // the migrated segment is read like any other
+    let entries = recover(tmpdir.path(), WalRecoveryMode::AbsoluteConsistency)
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 2);
+}